@@ -2,27 +2,38 @@ use std::time::Duration;
 
 use clipboard::{ClipboardContext, ClipboardProvider};
 use ratatui::{
-    crossterm::event::{KeyEventKind, KeyEvent},
-    widgets::{
-        ListItem, ListState,
-    },
+    crossterm::event::{KeyEventKind, KeyEvent, MouseEvent},
+    widgets::ListState,
 };
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
 use std::time::Instant;
 
 use crate::panel::{Panel, PanelName};
 use crate::handle_keys::*;
+use crate::handle_mouse::*;
+use crate::history::{Command, History, Op};
+use crate::input::Input;
+use crate::keymap::{Action, Keymap};
+use crate::theme::Theme;
+use crate::attachment;
+use crate::cmds;
+use crate::config::{self, expand_home, Config};
+use crate::generator::{Generator, DEFAULT_PASSPHRASE, DEFAULT_WORDS, KINDS, STRONG_POLICY};
+use crate::utils;
+use crate::tree::{self, TreeRow};
 use crate::Storage;
-use crate::model::Secret;
+use crate::model::{self, Approval, Attachment, Record, RecordKind, Secret, SecretStats};
+use crate::otp::{self, Otp};
+use crate::sort::{self, SortOrder};
+use crate::timer::Scheduler;
 
-pub const GUIDE_NORMAL: &str = "d: delete, a: add secret, m: make secret, enter: copy to clipboard, /: filter secrets, r: update, q: quit";
-pub const GUIDE_ADD: &str = "enter: confirm, tab: switch input, esc: cancel";
-pub const GUIDE_UPDATE: &str = "enter: update secret, esc: cancel";
-pub const GUIDE_DELETE: &str = "enter: confirm, esc: cancel";
-pub const GUIDE_MAKE: &str = "enter: make secret, esc: cancel, tab: switch input";
+/// file the marked secrets are exported to, next to where secm runs like `secm export`
+pub const EXPORT_FILE: &str = "secrets.json";
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub enum Mode {
     Normal,
     Filter,
@@ -30,47 +41,238 @@ pub enum Mode {
     Add,
     Update,
     Delete,
+    /// asks before a change to every marked secret
+    Confirm,
+    Tag,
+    Move,
+    Command,
+}
+
+/// how many lines the `:` command line remembers
+const MAX_COMMAND_HISTORY: usize = 100;
+
+/// the setting the sort order is remembered in
+const SORT_SETTING: &str = "sort";
+
+/// how often the database is checked for changes made by another secm
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// how often signing requests of `secm ssh-agent` are looked for
+const APPROVAL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// work the event loop does on time instead of on input
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Task {
+    ReloadIfChanged,
+    CheckApprovals,
+}
+
+/// the commands of the `:` command line, for completion
+pub const COMMANDS: [&str; 12] = ["add", "gen", "mv", "tag", "type", "field", "export", "save", "sort", "vault", "help", "q"];
+
+/// an action on all marked secrets, it waits for the `y` of the delete prompt
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bulk {
+    Delete,
+    Tag(String),
+    Move(String),
+    Export(String),
+    CopyNames,
+}
+
+impl Bulk {
+    /// what the prompt asks about `count` marked secrets
+    pub fn question(&self, count: usize) -> String {
+        match self {
+            Bulk::Delete => format!("delete {} marked secrets", count),
+            Bulk::Tag(expr) => format!("tag {} marked secrets {}", count, expr.trim()),
+            Bulk::Move(folder) => match tree::normalize_prefix(folder) {
+                "" => format!("move {} marked secrets to the top", count),
+                folder => format!("move {} marked secrets to {}/", count, folder),
+            },
+            Bulk::Export(path) => format!("export {} marked secrets to {}", count, path),
+            Bulk::CopyNames => format!("copy the names of {} marked secrets", count),
+        }
+    }
+}
+
+/// a line typed into the `:` command line, e.g. `:gen aws/root 24 --symbols`
+#[derive(Debug, PartialEq)]
+pub enum PaletteCommand {
+    Add { name: String },
+    Gen { name: String, length: Option<usize>, symbols: bool, words: bool },
+    Move { from: String, to: String },
+    Tag { expr: String },
+    Type { kind: String },
+    Field { field: String, value: String },
+    Export { path: Option<String> },
+    Save { attachment: String, path: Option<String> },
+    Sort { order: String },
+    Vault { name: String },
+    Help,
+    Quit,
+}
+
+impl PaletteCommand {
+    pub fn parse(line: &str) -> Result<PaletteCommand, String> {
+        let args = split_args(line)?;
+        let Some((command, args)) = args.split_first() else {
+            return Err("No command given".to_string());
+        };
+        let usage = |usage: &str| Err(format!("Usage: :{}", usage));
+        match (command.as_str(), args) {
+            ("add", [name]) => Ok(PaletteCommand::Add { name: name.clone() }),
+            ("add", _) => usage("add <name>"),
+            ("gen", _) => {
+                let is_symbols = |arg: &String| arg == "--symbols" || arg == "-s";
+                let is_words = |arg: &String| arg == "--words" || arg == "-w";
+                let is_flag = |arg: &String| is_symbols(arg) || is_words(arg);
+                let symbols = args.iter().any(is_symbols);
+                let words = args.iter().any(is_words);
+                let rest: Vec<&String> = args.iter().filter(|arg| !is_flag(arg)).collect();
+                let (name, length) = match rest.as_slice() {
+                    [name] => (name.to_string(), None),
+                    [name, length] => {
+                        let length = length.parse().map_err(|_| format!("Length must be number, not '{}'", length))?;
+                        (name.to_string(), Some(length))
+                    }
+                    _ => return usage("gen <name> [length] [--symbols] [--words]"),
+                };
+                Ok(PaletteCommand::Gen { name, length, symbols, words })
+            }
+            ("mv", [from, to]) => Ok(PaletteCommand::Move { from: from.clone(), to: to.clone() }),
+            ("mv", _) => usage("mv <from> <to>"),
+            ("tag", [_, ..]) => Ok(PaletteCommand::Tag { expr: args.join(" ") }),
+            ("tag", _) => usage("tag <+tag|-tag...>"),
+            ("export", []) => Ok(PaletteCommand::Export { path: None }),
+            ("export", [path]) => Ok(PaletteCommand::Export { path: Some(path.clone()) }),
+            ("export", _) => usage("export [path]"),
+            ("save", [attachment]) => Ok(PaletteCommand::Save { attachment: attachment.clone(), path: None }),
+            ("save", [attachment, path]) => Ok(PaletteCommand::Save { attachment: attachment.clone(), path: Some(path.clone()) }),
+            ("save", _) => usage("save <attachment> [path]"),
+            ("type", [kind]) => Ok(PaletteCommand::Type { kind: kind.clone() }),
+            ("type", _) => usage("type <login|api-key|database|ssh-key|note|card|none>"),
+            ("field", [field, value @ ..]) => Ok(PaletteCommand::Field { field: field.clone(), value: value.join(" ") }),
+            ("field", _) => usage("field <name> [value]"),
+            ("sort", [order]) => Ok(PaletteCommand::Sort { order: order.clone() }),
+            ("sort", _) => usage("sort <order>"),
+            ("vault", [name]) => Ok(PaletteCommand::Vault { name: name.clone() }),
+            ("vault", _) => usage("vault <name>"),
+            ("help", []) => Ok(PaletteCommand::Help),
+            ("q" | "quit", []) => Ok(PaletteCommand::Quit),
+            _ if COMMANDS.contains(&command.as_str()) || command == "quit" => Err(format!("Too many arguments for :{}", command)),
+            _ => Err(format!("Unknown command '{}'", command)),
+        }
+    }
+}
+
+/// split a command line at whitespace, keeping "quoted parts" together
+fn split_args(line: &str) -> Result<Vec<String>, String> {
+    let mut args = vec![];
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+    for ch in line.chars() {
+        match quote {
+            Some(q) if ch == q => quote = None,
+            Some(_) => current.get_or_insert_with(String::new).push(ch),
+            None if ch == '"' || ch == '\'' => {
+                quote = Some(ch);
+                current.get_or_insert_with(String::new);
+            }
+            None if ch.is_whitespace() => args.extend(current.take()),
+            None => current.get_or_insert_with(String::new).push(ch),
+        }
+    }
+    if quote.is_some() {
+        return Err("Unclosed quote".to_string());
+    }
+    args.extend(current);
+    Ok(args)
+}
+
+/// the longest text every candidate starts with
+fn common_prefix(candidates: &[String]) -> String {
+    let Some(first) = candidates.first() else {
+        return String::new();
+    };
+    let mut prefix = first.as_str();
+    for candidate in &candidates[1..] {
+        while !candidate.starts_with(prefix) {
+            prefix = &prefix[..prefix.char_indices().next_back().map(|(i, _)| i).unwrap_or(0)];
+        }
+    }
+    prefix.to_string()
 }
 
 pub struct App<S: Storage> {
     pub should_exit: bool,
     pub secrets:  Vec<(String, String)>, // todo: change this to map
     pub secret_list: SecretList,         // todo: consider merge 2 secrets 
+    pub collapsed: HashSet<String>,      // folders whose children are hidden in the tree
+    pub tags: HashMap<String, Vec<String>>,
+    pub stats: HashMap<String, SecretStats>,
+    pub favorites: Vec<String>,          // pinned above the tree in the order they were pinned
+    pub reused: HashSet<String>,         // secrets sharing their value with another one
+    pub records: HashMap<String, Record>, // the fields of typed secrets
+    pub attachments: HashMap<String, Vec<Attachment>>, // files attached to secrets, by name
+    pub sort: SortOrder,
+    pub marked: BTreeSet<String>,        // names of the secrets bulk actions apply to
+    pub mark_anchor: Option<usize>,      // row where a `v` range starts
+    pub bulk: Option<Bulk>,              // what the delete prompt asks about besides deleting the selection
+    pub history: History,
     pub panels: HashMap<PanelName, Panel>,
     // pub cursor: u8,
     pub mode: Mode,
-    pub guide: &'static str,
+    pub keymap: Keymap,
+    pub theme: Theme,
+    pub config: Config,
+    pub guide: String,
+    pub help_scroll: Option<u16>,        // the help overlay is open while this is set
+    pub approval: Option<Approval>,      // a signing request of `secm ssh-agent` waiting for y or n
+    pub reveal: bool,                    // show the value being edited instead of `*`s
+    pub command_history: Vec<String>,    // lines run from the `:` command line, oldest first
+    pub command_history_index: Option<usize>, // the line shown while walking the history
+    pub open_editor: bool,               // the main loop opens $EDITOR on the value being edited
+    pub click_areas: ClickAreas,
+    pub last_click: Option<LastClick>,
+    pub scheduler: Scheduler<Task>,
+    pub data_version: i64,               // changes when another process wrote the database
     pub error: AppErr,
+    pub notice: AppErr,
+    pub clipboard: Box<dyn Clipboard>,   // where copies go, the system clipboard outside of tests
+    pub vault_dir: Option<PathBuf>,      // where `:vault` opens databases, the data dir of secm when unset
     pub storage: S,
 }
 
+/// something text can be copied to
+pub trait Clipboard {
+    fn copy(&mut self, text: String) -> Result<(), String>;
+}
+
+/// the clipboard of the desktop, opened for every copy
+pub struct SystemClipboard;
+
+impl Clipboard for SystemClipboard {
+    fn copy(&mut self, text: String) -> Result<(), String> {
+        let mut clipboard = ClipboardContext::new().map_err(|e| format!("Unable to open the clipboard: {}", e))?;
+        clipboard.set_contents(text).map_err(|e| format!("Unable to copy to the clipboard: {}", e))
+    }
+}
+
 pub struct AppErr {
     pub msg: String,
     pub error_timer: Option<Instant>,
 }
 
 pub struct SecretList {
-    pub secrets: Vec<Secret>,
+    pub rows: Vec<TreeRow>,
     pub state: ListState,
 }
 
-impl FromIterator<(String, String)> for SecretList {
-    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
-        let secrets = iter
-            .into_iter()
-            .map(|(name, value)| Secret::new(name, value))
-            .collect();
+impl SecretList {
+    fn new(rows: Vec<TreeRow>) -> Self {
         let state = ListState::default();
-        Self { secrets, state } // 这里的secrets为什么要和结构体中的匿名字段名一致？
-    }
-}
-
-impl Secret {
-    fn new(name: String, value: String) -> Self {
-        Self {
-            name,
-            value,
-        }
+        Self { rows, state } // 这里的rows为什么要和结构体中的匿名字段名一致？
     }
 }
 
@@ -82,7 +284,15 @@ impl<S: Storage> App<S> {
                 Panel {
                     index: 0,
                     panel_name: PanelName::Filter,
-                    content: vec!["".to_string()],
+                    content: vec![Input::default()],
+                }
+            ),
+            (
+                PanelName::Secrets,
+                Panel {
+                    index: 0,
+                    panel_name: PanelName::Secrets,
+                    content: vec![],
                 }
             ),
             (
@@ -90,7 +300,7 @@ impl<S: Storage> App<S> {
                 Panel {
                     index: 0,
                     panel_name: PanelName::UpdateSecret,
-                    content: vec!["".to_string(), "".to_string()],
+                    content: vec![Input::default(), Input::multiline()],
                 }
             ),
             (
@@ -98,7 +308,7 @@ impl<S: Storage> App<S> {
                 Panel {
                     index: 0,
                     panel_name: PanelName::MakeSecret,
-                    content: vec!["".to_string(), "10".to_string(), "n".to_string()],
+                    content: vec![Input::default(), Input::default(), Input::default(), Input::default()],
                 }
             ),
             (
@@ -106,7 +316,7 @@ impl<S: Storage> App<S> {
                 Panel {
                     index: 0,
                     panel_name: PanelName::AddSecret,
-                    content: vec!["".to_string(), "".to_string()],
+                    content: vec![Input::default(), Input::multiline()],
                 }
             ),
             (
@@ -114,7 +324,40 @@ impl<S: Storage> App<S> {
                 Panel {
                     index: 0,
                     panel_name: PanelName::DeleteSecret,
-                    content: vec!["n".to_string()],
+                    // empty like after every other delete, anything but `y` keeps the secret
+                    content: vec![Input::default()],
+                }
+            ),
+            (
+                PanelName::Confirm,
+                Panel {
+                    index: 0,
+                    panel_name: PanelName::Confirm,
+                    content: vec![Input::default()],
+                }
+            ),
+            (
+                PanelName::TagSecrets,
+                Panel {
+                    index: 0,
+                    panel_name: PanelName::TagSecrets,
+                    content: vec![Input::default()],
+                }
+            ),
+            (
+                PanelName::MoveSecrets,
+                Panel {
+                    index: 0,
+                    panel_name: PanelName::MoveSecrets,
+                    content: vec![Input::default()],
+                }
+            ),
+            (
+                PanelName::Command,
+                Panel {
+                    index: 0,
+                    panel_name: PanelName::Command,
+                    content: vec![Input::default()],
                 }
             )
         ]);
@@ -124,21 +367,73 @@ impl<S: Storage> App<S> {
                 eprintln!("Failed to load secrets from storage: {}", err);
                 vec![]
             });
-        let secret_list = SecretList::from_iter(all_secrets.clone());
+        let tags = storage
+            .get_tags()
+            .unwrap_or_else(|err| {
+                eprintln!("Failed to load tags from storage: {}", err);
+                vec![]
+            });
 
-        Self {
+        // the sort order of the last session
+        let sort = match storage.get_setting(SORT_SETTING) {
+            Ok(Some(name)) => SortOrder::parse(&name).unwrap_or_default(),
+            _ => SortOrder::default(),
+        };
+
+        let keymap = Keymap::default();
+        let guide = keymap.guide(&Mode::Normal, false);
+        let mut scheduler = Scheduler::default();
+        scheduler.every(Task::ReloadIfChanged, RELOAD_INTERVAL);
+        scheduler.every(Task::CheckApprovals, APPROVAL_INTERVAL);
+        let data_version = storage.data_version().unwrap_or_default();
+
+        let mut app = Self {
             should_exit: false,
             secrets: all_secrets,
-            secret_list,
+            secret_list: SecretList::new(vec![]),
+            collapsed: HashSet::new(),
+            tags: tags.into_iter().collect(),
+            stats: HashMap::new(),
+            favorites: vec![],
+            reused: HashSet::new(),
+            records: HashMap::new(),
+            attachments: HashMap::new(),
+            sort,
+            marked: BTreeSet::new(),
+            mark_anchor: None,
+            bulk: None,
+            history: History::default(),
             panels,
             mode: Mode::Normal,
-            guide: GUIDE_NORMAL,
+            keymap,
+            theme: Theme::default(),
+            config: Config::default(),
+            guide,
+            help_scroll: None,
+            approval: None,
+            reveal: false,
+            command_history: vec![],
+            command_history_index: None,
+            open_editor: false,
+            click_areas: ClickAreas::default(),
+            last_click: None,
+            scheduler,
+            data_version,
             error: AppErr {
                 msg: "".to_string(),
                 error_timer: None,
             },
+            notice: AppErr {
+                msg: "".to_string(),
+                error_timer: None,
+            },
+            clipboard: Box::new(SystemClipboard),
+            vault_dir: None,
             storage,
-        }
+        };
+        app.refresh_secret_list();
+        app.select_most_likely();
+        app
     }
 
     pub fn filter_secrets_list(&mut self, filter: &str) {
@@ -147,13 +442,107 @@ impl<S: Storage> App<S> {
             .filter(|(name, _)| name.contains(filter))
             .collect();
 
-        self.secret_list = SecretList::from_iter(filtered_secrets);
+        // keep the cursor on the same row when the list is rebuilt
+        let selected = self.get_selected_path();
+        let selected_pinned = self.selected_pinned();
+        // folders holding a match are always opened while filtering
+        let collapsed = |path: &str| filter.is_empty() && self.collapsed.contains(path);
+        let now = sort::now();
+        let rank = |name: &str| self.stats.get(name).map(|stats| self.sort.rank(stats, now)).unwrap_or(0);
+        let mut rows = tree::pinned_rows(&filtered_secrets, &self.favorites);
+        rows.extend(tree::build_rows(&filtered_secrets, "", &collapsed, &rank));
+        self.secret_list = SecretList::new(rows);
+        if let Some(path) = selected {
+            // a favorite is listed twice, stay in the section the cursor was in
+            let index = self.secret_list.rows.iter().position(|row| match row {
+                TreeRow::Secret { secret, pinned, .. } => secret.name == path && pinned.is_some() == selected_pinned,
+                TreeRow::Folder { .. } => false,
+            });
+            match index {
+                Some(index) => self.secret_list.state.select(Some(index)),
+                None => self.select_path(&path),
+            }
+        }
+    }
+
+    /// rebuild the list after the secrets or the folding changed
+    pub fn refresh_secret_list(&mut self) {
+        // times and use counts change with the secrets, they are cheap to read again
+        if let Ok(stats) = self.storage.get_stats() {
+            self.stats = stats.into_iter().collect();
+        }
+        if let Ok(favorites) = self.storage.get_favorites() {
+            self.favorites = favorites;
+        }
+        if let Ok(reused) = self.storage.get_reused() {
+            self.reused = reused.into_iter().flatten().collect();
+        }
+        if let Ok(records) = self.storage.get_records() {
+            self.records = records.into_iter().collect();
+        }
+        if let Ok(attachments) = self.storage.get_attachments() {
+            self.attachments.clear();
+            for (key, attachment) in attachments {
+                self.attachments.entry(key).or_default().push(attachment);
+            }
+        }
+        let filter = self.get_filter_string();
+        self.filter_secrets_list(&filter);
+    }
+
+    /// select the secret with the highest frecency, what is likely copied next
+    pub fn select_most_likely(&mut self) {
+        let now = sort::now();
+        let likely = self.stats
+            .iter()
+            .filter(|(_, stats)| stats.use_count > 0)
+            .max_by_key(|(name, stats)| (sort::frecency(stats, now), std::cmp::Reverse(*name)))
+            .map(|(name, _)| name.clone());
+        if let Some(name) = likely {
+            self.select_path(&name);
+        }
+    }
+
+    /// show or hide the value being edited, showing a stored secret counts as reading it
+    pub fn toggle_reveal(&mut self) {
+        self.reveal = !self.reveal;
+        if self.reveal && self.mode == Mode::Update {
+            if let Some(secret) = self.get_selected_item() {
+                if let Err(err) = self.storage.record_use(&secret.name) {
+                    self.error = AppErr{msg: err, error_timer: Some(Instant::now())};
+                }
+            }
+        }
+    }
+
+    /// use the next sort order and remember it for the next session
+    pub fn cycle_sort(&mut self) {
+        self.set_sort(self.sort.next());
+    }
+
+    pub fn set_sort(&mut self, sort: SortOrder) {
+        self.sort = sort;
+        if let Err(err) = self.storage.set_setting(SORT_SETTING, sort.name()) {
+            self.error = AppErr{msg: err, error_timer: Some(Instant::now())};
+        }
+        self.refresh_secret_list();
+        self.notify(format!("Sorted by {}", sort.name()));
     }
 
     pub fn handle_key(&mut self, key: KeyEvent) {
         if key.kind != KeyEventKind::Press {
             return;
         }
+        if self.approval.is_some() {
+            return handle_key_in_approval(self, key);
+        }
+        if self.help_scroll.is_some() {
+            return handle_key_in_help(self, key);
+        }
+        if self.keymap.action(&self.mode, key) == Some(Action::Help) {
+            self.help_scroll = Some(0);
+            return;
+        }
         match self.mode {
             Mode::Filter => handle_key_in_filter_mode(self, key),
             Mode::Add => handle_key_in_add_mode(self, key),
@@ -161,13 +550,62 @@ impl<S: Storage> App<S> {
             Mode::Make => handle_key_in_make_mode(self, key),
             Mode::Update => handle_key_in_update_mode(self, key),
             Mode::Delete => handle_key_in_delete_mode(self, key),
+            Mode::Confirm => handle_key_in_confirm_mode(self, key),
+            Mode::Tag => handle_key_in_tag_mode(self, key),
+            Mode::Move => handle_key_in_move_mode(self, key),
+            Mode::Command => handle_key_in_command_mode(self, key),
+        }
+    }
+
+    pub fn handle_mouse(&mut self, event: MouseEvent) {
+        if self.help_scroll.is_some() {
+            return handle_mouse_in_help(self, event);
         }
+        handle_mouse_event(self, event);
+    }
+
+    /// replace the value being added or updated with what is saved in $EDITOR
+    pub fn edit_value_in_editor(&mut self) {
+        self.open_editor = false;
+        let panel_name = if self.mode == Mode::Add { PanelName::AddSecret } else { PanelName::UpdateSecret };
+        let input = &mut self.get_panel(panel_name).content[1];
+        match utils::edit_in_editor(input.value()) {
+            Ok(value) => input.set(&value),
+            Err(err) => self.error = AppErr{msg: err, error_timer: Some(Instant::now())},
+        }
+    }
+
+    /// bracketed paste goes into the focused input as a whole, instead of key by key
+    pub fn handle_paste(&mut self, text: String) {
+        let panel_name = match self.mode {
+            Mode::Normal => return,
+            Mode::Filter => PanelName::Filter,
+            Mode::Make => PanelName::MakeSecret,
+            Mode::Add => PanelName::AddSecret,
+            Mode::Update => PanelName::UpdateSecret,
+            Mode::Delete => PanelName::DeleteSecret,
+            Mode::Confirm => PanelName::Confirm,
+            Mode::Tag => PanelName::TagSecrets,
+            Mode::Move => PanelName::MoveSecrets,
+            Mode::Command => PanelName::Command,
+        };
+        let panel = self.get_panel(panel_name);
+        panel.content[panel.index].insert_str(&text);
     }
 
     pub fn get_panel(&mut self, panel_name: PanelName) -> &mut Panel {
         self.panels.get_mut(&panel_name).unwrap()
     }
 
+    /// the help page for the current mode
+    pub fn get_help(&self) -> String {
+        self.panels
+            .values()
+            .find(|panel| panel.mode() == self.mode)
+            .map(|panel| panel.get_help(&self.keymap))
+            .unwrap_or_default()
+    }
+
     pub fn select_next(&mut self) {
         self.secret_list.state.select_next();
     }
@@ -176,6 +614,74 @@ impl<S: Storage> App<S> {
         self.secret_list.state.select_previous();
     }
 
+    /// select the row of the secret or folder at `path`
+    pub fn select_path(&mut self, path: &str) {
+        let index = self.secret_list.rows.iter().position(|row| match row {
+            TreeRow::Folder { path: p, .. } => p == path,
+            TreeRow::Secret { secret, .. } => secret.name == path,
+        });
+        if index.is_some() {
+            self.secret_list.state.select(index);
+        }
+    }
+
+    fn get_selected_path(&self) -> Option<String> {
+        let i = self.secret_list.state.selected()?;
+        match self.secret_list.rows.get(i)? {
+            TreeRow::Folder { path, .. } => Some(path.clone()),
+            TreeRow::Secret { secret, .. } => Some(secret.name.clone()),
+        }
+    }
+
+    fn selected_pinned(&self) -> bool {
+        let selected = self.secret_list.state.selected().and_then(|i| self.secret_list.rows.get(i));
+        matches!(selected, Some(TreeRow::Secret { pinned: Some(_), .. }))
+    }
+
+    pub fn get_selected_folder(&self) -> Option<String> {
+        let i = self.secret_list.state.selected()?;
+        match self.secret_list.rows.get(i)? {
+            TreeRow::Folder { path, .. } => Some(path.clone()),
+            TreeRow::Secret { .. } => None,
+        }
+    }
+
+    /// open the selected folder
+    pub fn expand_selected(&mut self) {
+        if let Some(folder) = self.get_selected_folder() {
+            if self.collapsed.remove(&folder) {
+                self.refresh_secret_list();
+            }
+        }
+    }
+
+    /// close the selected folder, or the folder holding the selected secret
+    pub fn collapse_selected(&mut self) {
+        let Some(path) = self.get_selected_path() else {
+            return;
+        };
+        let folder = match self.get_selected_folder() {
+            Some(folder) if !self.collapsed.contains(&folder) => folder,
+            _ => match tree::parent(&path) {
+                Some(parent) => parent.to_string(),
+                None => return,
+            },
+        };
+        self.collapsed.insert(folder.clone());
+        self.refresh_secret_list();
+        self.select_path(&folder);
+    }
+
+    pub fn toggle_selected_folder(&mut self) {
+        if let Some(folder) = self.get_selected_folder() {
+            if self.collapsed.contains(&folder) {
+                self.expand_selected();
+            } else {
+                self.collapse_selected();
+            }
+        }
+    }
+
     // fn select_first(&mut self) {
     //     self.secret_list.state.select_first();
     // }
@@ -186,31 +692,76 @@ impl<S: Storage> App<S> {
 
     pub fn get_filter_string(&mut self) -> String {
         let panel = self.panels.get(&PanelName::Filter).unwrap();
-        let filter = panel.content[0].clone();
+        let filter = panel.content[0].value().to_string();
         return filter;
     }
 
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+        self.update_guide();
+    }
+
+    /// regenerate the guide line from the keymap for the current mode
+    pub fn update_guide(&mut self) {
+        self.guide = self.keymap.guide(&self.mode, !self.marked.is_empty());
+    }
+
+    /// switch the make popup between `chars` and `words`, with the defaults of the kind
+    pub fn set_make_kind(&mut self, kind: &str) {
+        let (length, spec) = match kind {
+            "words" => (DEFAULT_WORDS, DEFAULT_PASSPHRASE.to_string()),
+            _ => (self.config.make_length(), self.config.make_policy().to_string()),
+        };
+        let make_secret_panel = self.get_panel(PanelName::MakeSecret);
+        make_secret_panel.content[1].set(kind);
+        make_secret_panel.content[2].set(&length.to_string());
+        make_secret_panel.content[3].set(&spec);
+    }
+
+    /// the next kind of the make popup, what left, right and space do on the type field
+    pub fn cycle_make_kind(&mut self) {
+        let panel = self.get_panel(PanelName::MakeSecret);
+        let kind = match panel.content[1].value() {
+            "words" => "chars",
+            _ => "words",
+        };
+        self.set_make_kind(kind);
+    }
+
     pub fn switch_mode(&mut self, mode: Mode) {
         self.mode = mode;
+        self.update_guide();
         match self.mode {
-            Mode::Add => self.guide = GUIDE_ADD,
-            Mode::Make => self.guide = GUIDE_MAKE,
-            Mode::Delete => self.guide = GUIDE_DELETE,
+            Mode::Make => {
+                // start from the defaults of the config, not what the last make left
+                self.set_make_kind(KINDS[0]);
+            }
             Mode::Update => {
                 if let Some(secret) = self.get_selected_item() {
                     let update_secret_panel = self.get_panel(PanelName::UpdateSecret);
-                    update_secret_panel.content[0] = secret.name;
-                    update_secret_panel.content[1] = secret.value;
-                    self.guide = GUIDE_UPDATE;
+                    update_secret_panel.content[0].set(&secret.name);
+                    update_secret_panel.content[1].set(&secret.value);
+                } else if let Some(folder) = self.get_selected_folder() {
+                    // updating a folder moves the whole subtree
+                    let update_secret_panel = self.get_panel(PanelName::UpdateSecret);
+                    update_secret_panel.index = 0;
+                    update_secret_panel.content[0].set(&folder);
                 }
             }
             Mode::Normal => {
-                self.guide = GUIDE_NORMAL;
                 self.error.msg.clear();
+                self.reveal = false;
+                self.bulk = None;
                 self.panels.get_mut(&PanelName::UpdateSecret).unwrap().clear_content();
                 self.panels.get_mut(&PanelName::Filter).unwrap().clear_content();
                 self.panels.get_mut(&PanelName::AddSecret).unwrap().clear_content();
                 self.panels.get_mut(&PanelName::DeleteSecret).unwrap().clear_content();
+                self.panels.get_mut(&PanelName::Confirm).unwrap().clear_content();
+                self.panels.get_mut(&PanelName::TagSecrets).unwrap().clear_content();
+                self.panels.get_mut(&PanelName::MoveSecrets).unwrap().clear_content();
+                self.panels.get_mut(&PanelName::Command).unwrap().clear_content();
+                self.command_history_index = None;
+                self.refresh_secret_list();
             }
             _ => {},
         }
@@ -218,29 +769,159 @@ impl<S: Storage> App<S> {
 
     pub fn get_selected_item(&mut self) ->  Option<Secret>  {
         if let Some(i) = self.secret_list.state.selected() {
-            let item = self.secret_list.secrets[i].clone();
-            Some(item)
+            match self.secret_list.rows.get(i) {
+                Some(TreeRow::Secret { secret, .. }) => Some(secret.clone()),
+                _ => None,
+            }
         } else {
             None
         }
     }
 
-    pub fn copy_selected_to_clipboard(&mut self) {
-        if let Some(secret) = self.get_selected_item() {
-            let mut clipboard = ClipboardContext::new().unwrap();
-            clipboard.set_contents(secret.value).unwrap();
-        } else {
-            self.error = AppErr{msg: "No secret selected".to_string(), error_timer: Some(Instant::now())};
+    pub fn copy_selected_to_clipboard(&mut self) -> Result<(), String> {
+        let secret = self.get_selected_item().ok_or("No secret selected")?;
+        self.clipboard.copy(secret.value)?;
+        self.storage.record_use(&secret.name)
+    }
+
+    /// copy one field of the selected secret, `username` or `password` work for any record
+    pub fn copy_selected_field(&mut self, field: &str) -> Result<(), String> {
+        let secret = self.get_selected_item().ok_or("No secret selected")?;
+        let value = model::secret_field(self.records.get(&secret.name), &secret.value, field)?
+            .ok_or(format!("{} has no {}", secret.name, field))?
+            .to_string();
+        self.clipboard.copy(value)?;
+        self.storage.record_use(&secret.name)?;
+        self.notify(format!("Copied {} of {}", field, secret.name));
+        Ok(())
+    }
+
+    /// the one-time code setup of the selected secret, if it has one
+    pub fn selected_otp(&mut self) -> Option<Result<Otp, String>> {
+        let secret = self.get_selected_item()?;
+        otp::source(self.records.get(&secret.name), &secret.value).map(Otp::parse)
+    }
+
+    /// copy the one-time code of the selected secret
+    pub fn copy_selected_otp(&mut self) -> Result<(), String> {
+        let secret = self.get_selected_item().ok_or("No secret selected")?;
+        let (code, _) = otp::use_code(&self.storage, &secret.name, self.records.get(&secret.name), &secret.value)?;
+        self.clipboard.copy(code)?;
+        self.storage.record_use(&secret.name)?;
+        self.refresh_secret_list();
+        self.notify(format!("Copied one-time code of {}", secret.name));
+        Ok(())
+    }
+
+    /// make the selected secret a record of `kind`, `none` makes it a plain secret again
+    pub fn set_selected_kind(&mut self, kind: &str) -> Result<(), String> {
+        let secret = self.get_selected_item().ok_or("No secret selected")?;
+        let before = self.records.get(&secret.name).cloned();
+        let after = match kind {
+            "none" => None,
+            // fields the new kind also has are kept
+            kind => {
+                let kind = RecordKind::parse(kind)?;
+                let mut record = Record::new(kind);
+                if let Some(before) = &before {
+                    record.fields = before.fields.clone();
+                    record.fields.retain(|field, _| kind.fields()[1..].contains(&field.as_str()));
+                }
+                Some(record)
+            }
+        };
+        self.set_record(&secret.name, before, after)
+    }
+
+    /// set a field of the selected record, an empty value removes it
+    pub fn set_selected_field(&mut self, field: &str, value: &str) -> Result<(), String> {
+        let secret = self.get_selected_item().ok_or("No secret selected")?;
+        let before = self.records.get(&secret.name).cloned();
+        let mut record = before.clone().ok_or(format!("{} is no record, give it a type with :type first", secret.name))?;
+        record.set(field, value)?;
+        self.set_record(&secret.name, before, Some(record))
+    }
+
+    fn set_record(&mut self, name: &str, before: Option<Record>, after: Option<Record>) -> Result<(), String> {
+        self.storage.set_record(name, after.as_ref())?;
+        let description = match &after {
+            Some(record) => format!("{} is a {}", name, record.kind.name()),
+            None => format!("{} is a plain secret", name),
+        };
+        self.history.record(Command::new(description.clone(), vec![Op::SetRecord { name: name.to_string(), before, after }]));
+        self.refresh_secret_list();
+        self.notify(description);
+        Ok(())
+    }
+
+    /// called by the event loop a few times a second, with or without input
+    pub fn tick(&mut self) {
+        self.clear_error_if_expired();
+        for task in self.scheduler.due(Instant::now()) {
+            match task {
+                Task::ReloadIfChanged => self.reload_if_changed(),
+                Task::CheckApprovals => self.check_approvals(),
+            }
+        }
+    }
+
+    /// show the oldest signing request nobody answered yet, drop the shown one
+    /// once the agent gave up on it or another TUI answered it
+    fn check_approvals(&mut self) {
+        match self.storage.get_approvals() {
+            Ok(pending) => {
+                self.approval = match self.approval.take() {
+                    Some(shown) if pending.contains(&shown) => Some(shown),
+                    _ => pending.into_iter().next(),
+                };
+            }
+            Err(err) => self.error = AppErr{msg: err, error_timer: Some(Instant::now())},
+        }
+    }
+
+    /// answer the signing request shown
+    pub fn answer_approval(&mut self, allow: bool) -> Result<(), String> {
+        let Some(approval) = self.approval.take() else {
+            return Ok(());
+        };
+        self.storage.decide_approval(approval.id, allow)?;
+        let verb = if allow { "Allowed" } else { "Refused" };
+        self.notify(format!("{} {} with {}", verb, approval.what, approval.key));
+        Ok(())
+    }
+
+    /// pick up secrets another secm added, changed or deleted, e.g. `secm rm` in a shell
+    fn reload_if_changed(&mut self) {
+        let res = self.storage.data_version().and_then(|version| {
+            if version == self.data_version {
+                return Ok(());
+            }
+            self.data_version = version;
+            self.reload_secrets()
+        });
+        if let Err(err) = res {
+            self.error = AppErr{msg: err, error_timer: Some(Instant::now())};
         }
     }
 
     pub fn clear_error_if_expired(&mut self) {
         if let Some(timer) = self.error.error_timer {
-            if timer.elapsed() >= Duration::from_secs(3) {
+            if timer.elapsed() >= self.config.message_timeout() {
                 self.error.msg.clear();
                 self.error.error_timer = None;
             }
         }
+        if let Some(timer) = self.notice.error_timer {
+            if timer.elapsed() >= self.config.message_timeout() {
+                self.notice.msg.clear();
+                self.notice.error_timer = None;
+            }
+        }
+    }
+
+    /// show a short message in the guide line, it fades like errors do
+    pub fn notify(&mut self, msg: String) {
+        self.notice = AppErr{msg, error_timer: Some(Instant::now())};
     }
 
     pub fn add_secret(&mut self, name: String, value: String) -> Result<(), String> {
@@ -251,21 +932,46 @@ impl<S: Storage> App<S> {
         if self.secrets.iter().any(|s| s.0 == name) {
             return Err("Secret already exists".to_string());
         }
+        self.config.check_secret(&value)?;
         self.storage.write(&name, &value)?;
+        self.history.record(Command::new(
+            format!("add {}", name),
+            vec![Op::Write { name: name.clone(), value: value.clone(), tags: vec![], record: None, favorite: false, attachments: vec![] }],
+        ));
 
-        self.secrets.push((name, value));
-        self.secret_list= SecretList::from_iter(self.secrets.clone());
-        self.secret_list.state.select_last();
+        self.warn_if_reused(&name, &value);
+        self.secrets.push((name.clone(), value));
+        self.refresh_secret_list();
+        self.select_path(&name);
 
         Ok(())
     }
 
+    /// the secret is saved, but say so when its value is used by other secrets too
+    fn warn_if_reused(&mut self, name: &str, value: &str) {
+        let others: Vec<String> = self
+            .storage
+            .names_with_value(value)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|other| other != name)
+            .collect();
+        // a notice, errors are cleared when the popup closes
+        if !others.is_empty() {
+            self.notify(format!("Saved, but the same value is used by {}", others.join(", ")));
+        }
+    }
+
     pub fn update_selected_secret(&mut self) -> Result<(), String> {
-        if let Some(i)  = self.secret_list.state.selected() {
-            let selected_key = &self.secret_list.secrets[i].name;
+        if let Some(folder) = self.get_selected_folder() {
+            let to = self.panels.get(&PanelName::UpdateSecret).unwrap().content[0].value().to_string();
+            return self.move_folder(&folder, tree::normalize_prefix(&to));
+        }
+        if let Some(selected) = self.get_selected_item() {
+            let selected_key = &selected.name;
             let update_secret_panel = self.panels.get_mut(&PanelName::UpdateSecret).unwrap();
-            let name = update_secret_panel.content[0].trim();
-            let value = update_secret_panel.content[1].trim();
+            let name = update_secret_panel.content[0].value().trim();
+            let value = update_secret_panel.content[1].value();
 
             if name.is_empty() || value.is_empty() {
                 return Err("Name and value cannot be empty".to_string());
@@ -280,41 +986,677 @@ impl<S: Storage> App<S> {
             // self.secret_list = SecretList::from_iter(self.secrets.clone());
             // return Ok(())
             if let Some(original_index) = self.secrets.iter().position(|(key, _)| key == selected_key) {
-                // 从存储中删除
-                self.storage.delete(selected_key)?;
-                self.storage.write(name, value)?;
+                if name != selected_key && self.secrets.iter().any(|(key, _)| key == name) {
+                    return Err(format!("Secret {} already exists", name));
+                }
+                // an old weak secret can still be renamed
+                if value != selected.value {
+                    self.config.check_secret(value)?;
+                }
+                // renaming in place keeps the tags of the secret
+                self.storage.atomic(&mut |storage| {
+                    if name != selected_key {
+                        storage.rename(selected_key, name)?;
+                    }
+                    storage.update(name, value)
+                })?;
+                let mut ops = vec![];
+                if name != selected_key {
+                    ops.push(Op::Rename { from: selected_key.clone(), to: name.to_string() });
+                }
+                if value != selected.value {
+                    ops.push(Op::Update { name: name.to_string(), before: selected.value.clone(), after: value.to_string() });
+                }
+                self.history.record(Command::new(format!("update {}", name), ops));
                 self.secrets[original_index] = (name.to_string(), value.to_string());
-                self.secret_list = SecretList::from_iter(self.secrets.clone());
+                let changed = value != selected.value;
+                let (name, value) = (name.to_string(), value.to_string());
+                if let Some(tags) = self.tags.remove(selected_key) {
+                    self.tags.insert(name.clone(), tags);
+                }
+                if changed {
+                    self.warn_if_reused(&name, &value);
+                }
+                self.refresh_secret_list();
+                self.select_path(&name);
             }
 
-            // todo: set selected index to current index
             return Ok(())
         }
         Err("No secret selected".to_string())
     }
 
+    /// move every secret below the folder `from` to the folder `to` in one storage operation
+    pub fn move_folder(&mut self, from: &str, to: &str) -> Result<(), String> {
+        if to.is_empty() {
+            return Err("Folder name cannot be empty".to_string());
+        }
+        if from == to {
+            return Ok(());
+        }
+        let moved: Vec<String> = self.secrets
+            .iter()
+            .filter(|(name, _)| tree::in_folder(name, from))
+            .map(|(name, _)| format!("{}{}", to, &name[from.len()..]))
+            .collect();
+        if let Some(existing) = moved.iter().find(|name| self.secrets.iter().any(|(key, _)| key == *name)) {
+            return Err(format!("Secret {} already exists", existing));
+        }
+        self.storage.rename_prefix(from, to)?;
+        let ops = self.secrets
+            .iter()
+            .filter(|(name, _)| tree::in_folder(name, from))
+            .map(|(name, _)| Op::Rename { from: name.clone(), to: format!("{}{}", to, &name[from.len()..]) })
+            .collect();
+        self.history.record(Command::new(format!("move {}/ to {}/", from, to), ops));
+
+        for (name, _) in self.secrets.iter_mut() {
+            if tree::in_folder(name, from) {
+                *name = format!("{}{}", to, &name[from.len()..]);
+            }
+        }
+        self.tags = self.tags
+            .drain()
+            .map(|(name, tags)| match tree::in_folder(&name, from) {
+                true => (format!("{}{}", to, &name[from.len()..]), tags),
+                false => (name, tags),
+            })
+            .collect();
+        if self.collapsed.remove(from) {
+            self.collapsed.insert(to.to_string());
+        }
+        self.refresh_secret_list();
+        self.select_path(to);
+        Ok(())
+    }
+
     pub fn delete_selected_secret(&mut self) -> Result<(), String> {
-        if let Some(i) = self.secret_list.state.selected() {
-            let selected_key = &self.secret_list.secrets[i].name;
+        if let Some(folder) = self.get_selected_folder() {
+            let names: Vec<String> = self.secrets
+                .iter()
+                .filter(|(name, _)| tree::in_folder(name, &folder))
+                .map(|(name, _)| name.clone())
+                .collect();
+            let ops = self.delete_ops(&names)?;
+            self.storage.delete_prefix(&folder)?;
+            self.history.record(Command::new(format!("delete {}/", folder), ops));
+            self.secrets.retain(|(name, _)| !tree::in_folder(name, &folder));
+            self.tags.retain(|name, _| !tree::in_folder(name, &folder));
+            self.collapsed.remove(&folder);
+            self.refresh_secret_list();
+            return Ok(());
+        }
+        if let Some(selected) = self.get_selected_item() {
+            let selected_key = &selected.name;
             // 在 self.secrets 中找到对应的索引
             if let Some(original_index) = self.secrets.iter().position(|(key, _)| key == selected_key) {
                 // 从存储中删除
+                let ops = self.delete_ops(std::slice::from_ref(selected_key))?;
                 self.storage.delete(selected_key)?;
+                self.history.record(Command::new(format!("delete {}", selected_key), ops));
                 // 从原始 secrets 列表中删除
                 self.secrets.remove(original_index);
+                self.tags.remove(selected_key);
             }
-            self.secret_list.secrets.remove(i);
+            self.refresh_secret_list();
 
             return Ok(());
         }
         Err("No secret selected".to_string())
     }
-}
 
-impl From<&Secret> for ListItem<'_> {
-    fn from(secret: &Secret) -> Self {
-        let line = secret.name.clone();
-        ListItem::new(line)
+    /// ops recreating the secrets `names` as they are now, recorded before deleting them
+    fn delete_ops(&self, names: &[String]) -> Result<Vec<Op>, String> {
+        self.secrets
+            .iter()
+            .filter(|(name, _)| names.contains(name))
+            .map(|(name, value)| {
+                Ok(Op::Delete {
+                    name: name.clone(),
+                    value: value.clone(),
+                    tags: self.tags.get(name).cloned().unwrap_or_default(),
+                    record: self.records.get(name).cloned(),
+                    favorite: self.favorites.contains(name),
+                    // the files stay sealed while the history keeps them
+                    attachments: self.storage.get_sealed_attachments(name)?,
+                })
+            })
+            .collect()
+    }
+
+    fn vault_dir(&self) -> Result<PathBuf, String> {
+        match &self.vault_dir {
+            Some(dir) => Ok(dir.clone()),
+            None => config::vault_dir(),
+        }
+    }
+
+    /// close the database and work on `<data dir>/secm/<name>.db`, created when it doesn't exist
+    pub fn open_vault(&mut self, name: &str) -> Result<(), String> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(format!("Invalid vault name '{}'", name));
+        }
+        self.storage = S::open(&self.vault_dir()?.join(format!("{}.db", name)))?;
+        // undo, marks and folds belong to the vault they were made in
+        self.history = History::default();
+        self.marked.clear();
+        self.mark_anchor = None;
+        self.collapsed.clear();
+        self.approval = None;
+        self.data_version = self.storage.data_version()?;
+        if let Ok(Some(order)) = self.storage.get_setting(SORT_SETTING) {
+            self.sort = SortOrder::parse(&order).unwrap_or_default();
+        }
+        self.secret_list.state.select(None);
+        self.reload_secrets()?;
+        self.select_most_likely();
+        self.notify(format!("Opened vault {}", name));
+        Ok(())
+    }
+
+    /// the vaults `:vault` can open, by name
+    fn vault_names(&self) -> Vec<String> {
+        let Some(entries) = self.vault_dir().ok().and_then(|dir| fs::read_dir(dir).ok()) else {
+            return vec![];
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.strip_suffix(".db").map(String::from))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// load secrets and tags again after the storage was changed behind the app's back
+    fn reload_secrets(&mut self) -> Result<(), String> {
+        self.secrets = self.storage.get_all()?;
+        self.tags = self.storage.get_tags()?.into_iter().collect();
+        let secrets = &self.secrets;
+        self.marked.retain(|name| secrets.iter().any(|(key, _)| key == name));
+        self.refresh_secret_list();
+        Ok(())
+    }
+
+    pub fn undo(&mut self) {
+        let res = self.history
+            .undo(&self.storage)
+            .and_then(|description| self.reload_secrets().map(|_| description));
+        match res {
+            Ok(description) => self.notify(format!("Undid: {}", description)),
+            Err(err) => self.error = AppErr{msg: err, error_timer: Some(Instant::now())},
+        }
+    }
+
+    pub fn redo(&mut self) {
+        let res = self.history
+            .redo(&self.storage)
+            .and_then(|description| self.reload_secrets().map(|_| description));
+        match res {
+            Ok(description) => self.notify(format!("Redid: {}", description)),
+            Err(err) => self.error = AppErr{msg: err, error_timer: Some(Instant::now())},
+        }
+    }
+
+    /// names of the secrets a row stands for, a folder stands for everything in it
+    fn row_secret_names(&self, i: usize) -> Vec<String> {
+        match self.secret_list.rows.get(i) {
+            Some(TreeRow::Secret { secret, .. }) => vec![secret.name.clone()],
+            Some(TreeRow::Folder { path, .. }) => self.secrets
+                .iter()
+                .filter(|(name, _)| tree::in_folder(name, path))
+                .map(|(name, _)| name.clone())
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    fn set_marked(&mut self, names: Vec<String>, mark: bool) {
+        for name in names {
+            if mark {
+                self.marked.insert(name);
+            } else {
+                self.marked.remove(&name);
+            }
+        }
+        self.update_guide();
+    }
+
+    /// mark or unmark the selected secret, or everything in the selected folder
+    pub fn toggle_mark(&mut self) {
+        let Some(i) = self.secret_list.state.selected() else {
+            return;
+        };
+        let names = self.row_secret_names(i);
+        let mark = !names.iter().all(|name| self.marked.contains(name));
+        self.set_marked(names, mark);
+        self.mark_anchor = Some(i);
+    }
+
+    /// mark every row between the last toggled row and the cursor
+    pub fn mark_range(&mut self) {
+        let Some(i) = self.secret_list.state.selected() else {
+            return;
+        };
+        let anchor = self.mark_anchor.unwrap_or(i).min(self.secret_list.rows.len().saturating_sub(1));
+        let names = (anchor.min(i)..=anchor.max(i))
+            .flat_map(|row| self.row_secret_names(row))
+            .collect();
+        self.set_marked(names, true);
+        self.mark_anchor = Some(i);
+    }
+
+    /// mark every secret matching the filter, or clear the marks when they are all marked already
+    pub fn toggle_mark_all(&mut self) {
+        let filter = self.get_filter_string();
+        let names: Vec<String> = self.secrets
+            .iter()
+            .filter(|(name, _)| name.contains(&filter))
+            .map(|(name, _)| name.clone())
+            .collect();
+        let mark = !names.iter().all(|name| self.marked.contains(name));
+        self.set_marked(names, mark);
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+        self.mark_anchor = None;
+        self.update_guide();
+    }
+
+    fn marked_names(&self) -> Vec<String> {
+        self.marked.iter().cloned().collect()
+    }
+
+    /// ask before `bulk` changes every marked secret
+    pub fn confirm_bulk(&mut self, bulk: Bulk) {
+        self.switch_mode(Mode::Confirm);
+        self.bulk = Some(bulk);
+    }
+
+    /// run the action the confirm prompt asked about, it stays asked when it fails
+    pub fn run_bulk(&mut self) -> Result<(), String> {
+        let Some(bulk) = self.bulk.clone() else {
+            return Err("Nothing to confirm".to_string());
+        };
+        match bulk {
+            Bulk::Delete => self.delete_marked(),
+            Bulk::Tag(expr) => self.tag_marked(&expr),
+            Bulk::Move(folder) => self.move_marked(&folder),
+            Bulk::Export(path) => self.export_secrets(&self.marked_names(), &path),
+            Bulk::CopyNames => self.copy_marked_names(),
+        }
+    }
+
+    pub fn delete_marked(&mut self) -> Result<(), String> {
+        let names = self.marked_names();
+        let ops = self.delete_ops(&names)?;
+        self.storage.atomic(&mut |storage| {
+            names.iter().try_for_each(|name| storage.delete(name))
+        })?;
+        self.history.record(Command::new(format!("delete {} secrets", names.len()), ops));
+
+        self.secrets.retain(|(name, _)| !self.marked.contains(name));
+        self.tags.retain(|name, _| !self.marked.contains(name));
+        self.clear_marks();
+        self.refresh_secret_list();
+        self.notify(format!("Deleted {} secrets", names.len()));
+        Ok(())
+    }
+
+    /// pin the selected secret above the tree, or unpin it
+    pub fn toggle_favorite(&mut self) -> Result<(), String> {
+        let Some(secret) = self.get_selected_item() else {
+            return Err("Only secrets can be favorites".to_string());
+        };
+        let before = self.favorites.contains(&secret.name);
+        self.storage.set_favorite(&secret.name, !before)?;
+        self.history.record(Command::new(
+            format!("{} {}", if before { "unpin" } else { "pin" }, secret.name),
+            vec![Op::SetFavorite { name: secret.name.clone(), before, after: !before }],
+        ));
+        self.refresh_secret_list();
+        if before {
+            self.notify(format!("Unpinned {}", secret.name));
+        } else {
+            self.notify(format!("Pinned {} as favorite {}", secret.name, self.favorites.len()));
+        }
+        Ok(())
+    }
+
+    /// apply a tag expression like `prod +db -old` to the marked secrets
+    pub fn tag_marked(&mut self, expr: &str) -> Result<(), String> {
+        self.tag_secrets(&self.marked_names(), expr)
+    }
+
+    pub fn tag_secrets(&mut self, names: &[String], expr: &str) -> Result<(), String> {
+        let mut add = vec![];
+        let mut remove = vec![];
+        for token in expr.split_whitespace() {
+            let (target, tag) = match token.strip_prefix('-') {
+                Some(tag) => (&mut remove, tag),
+                None => (&mut add, token.trim_start_matches('+')),
+            };
+            if tag.is_empty() || tag.contains(',') {
+                return Err(format!("Invalid tag '{}'", token));
+            }
+            target.push(tag.to_string());
+        }
+        if add.is_empty() && remove.is_empty() {
+            return Err("No tags given".to_string());
+        }
+
+        let updated: Vec<(String, Vec<String>)> = names
+            .iter()
+            .map(|name| {
+                let mut tags = self.tags.get(name).cloned().unwrap_or_default();
+                tags.retain(|tag| !remove.contains(tag));
+                for tag in &add {
+                    if !tags.contains(tag) {
+                        tags.push(tag.clone());
+                    }
+                }
+                (name.clone(), tags)
+            })
+            .collect();
+        self.storage.atomic(&mut |storage| {
+            updated.iter().try_for_each(|(name, tags)| storage.set_tags(name, tags))
+        })?;
+        let ops = updated
+            .iter()
+            .map(|(name, tags)| Op::SetTags {
+                name: name.clone(),
+                before: self.tags.get(name).cloned().unwrap_or_default(),
+                after: tags.clone(),
+            })
+            .collect();
+        self.history.record(Command::new(format!("tag {} secrets", updated.len()), ops));
+
+        for (name, tags) in updated {
+            if tags.is_empty() {
+                self.tags.remove(&name);
+            } else {
+                self.tags.insert(name, tags);
+            }
+        }
+        self.notify(format!("Tagged {} secrets", names.len()));
+        Ok(())
+    }
+
+    /// move the marked secrets into `folder`, keeping only the last part of their names
+    pub fn move_marked(&mut self, folder: &str) -> Result<(), String> {
+        let folder = tree::normalize_prefix(folder);
+        let renames: Vec<(String, String)> = self.marked
+            .iter()
+            .map(|name| {
+                let label = name.rsplit_once(tree::SEPARATOR).map(|(_, last)| last).unwrap_or(name);
+                let to = if folder.is_empty() { label.to_string() } else { format!("{}{}{}", folder, tree::SEPARATOR, label) };
+                (name.clone(), to)
+            })
+            .filter(|(from, to)| from != to)
+            .collect();
+        for (_, to) in &renames {
+            if renames.iter().filter(|(_, other)| other == to).count() > 1
+                || self.secrets.iter().any(|(name, _)| name == to)
+            {
+                return Err(format!("Secret {} already exists", to));
+            }
+        }
+        self.storage.atomic(&mut |storage| {
+            renames.iter().try_for_each(|(from, to)| storage.rename(from, to))
+        })?;
+        let ops = renames
+            .iter()
+            .map(|(from, to)| Op::Rename { from: from.clone(), to: to.clone() })
+            .collect();
+        self.history.record(Command::new(format!("move {} secrets", renames.len()), ops));
+
+        for (from, to) in &renames {
+            if let Some(secret) = self.secrets.iter_mut().find(|(name, _)| name == from) {
+                secret.0 = to.clone();
+            }
+            if let Some(tags) = self.tags.remove(from) {
+                self.tags.insert(to.clone(), tags);
+            }
+        }
+        let count = self.marked.len();
+        self.clear_marks();
+        self.refresh_secret_list();
+        self.notify(format!("Moved {} secrets", count));
+        Ok(())
+    }
+
+    pub fn export_secrets(&mut self, names: &[String], path: &str) -> Result<(), String> {
+        let secrets: Vec<Secret> = self.secrets
+            .iter()
+            .filter(|(name, _)| names.contains(name))
+            .map(|(name, value)| Secret { name: name.clone(), value: value.clone() })
+            .collect();
+        let file = expand_home(path);
+        cmds::write_secrets_json(&self.storage, &file.to_string_lossy(), &secrets, false)?;
+        self.notify(format!("Exported {} secrets to {}", secrets.len(), path));
+        Ok(())
+    }
+
+    /// open the command line on `:save` for the attachments of the selected secret
+    pub fn start_save_attachment(&mut self) -> Result<(), String> {
+        let secret = self.get_selected_item().ok_or("No secret selected")?;
+        let attachments = self.attachments.get(&secret.name).ok_or(format!("{} has no attachments", secret.name))?;
+        let line = match attachments.as_slice() {
+            [only] => format!("save {} ", only.name),
+            _ => "save ".to_string(),
+        };
+        self.switch_mode(Mode::Command);
+        self.get_panel(PanelName::Command).content[0].set(&line);
+        Ok(())
+    }
+
+    /// write the attachment `name` of the selected secret to a new file at `path` only the user can read
+    pub fn save_selected_attachment(&mut self, name: &str, path: &str) -> Result<(), String> {
+        let secret = self.get_selected_item().ok_or("No secret selected")?;
+        let size = attachment::save_to(&expand_home(path), |file| self.storage.read_attachment(&secret.name, name, file))?;
+        self.storage.record_use(&secret.name)?;
+        self.notify(format!("Saved {} ({}) to {}", name, attachment::format_size(size), path));
+        Ok(())
+    }
+
+    /// rename the secret `from`, or move everything in the folder `from`
+    pub fn rename_path(&mut self, from: &str, to: &str) -> Result<(), String> {
+        if self.secrets.iter().any(|(name, _)| name == from) {
+            return self.rename_secret(from, to);
+        }
+        let from = tree::normalize_prefix(from);
+        if !from.is_empty() && self.secrets.iter().any(|(name, _)| tree::in_folder(name, from)) {
+            return self.move_folder(from, tree::normalize_prefix(to));
+        }
+        Err(format!("No secret or folder named {}", from))
+    }
+
+    /// rename a secret keeping its value and tags
+    pub fn rename_secret(&mut self, from: &str, to: &str) -> Result<(), String> {
+        if to.is_empty() {
+            return Err("Name cannot be empty".to_string());
+        }
+        if from == to {
+            return Ok(());
+        }
+        if self.secrets.iter().any(|(name, _)| name == to) {
+            return Err(format!("Secret {} already exists", to));
+        }
+        self.storage.rename(from, to)?;
+        self.history.record(Command::new(
+            format!("rename {} to {}", from, to),
+            vec![Op::Rename { from: from.to_string(), to: to.to_string() }],
+        ));
+        if let Some(secret) = self.secrets.iter_mut().find(|(name, _)| name == from) {
+            secret.0 = to.to_string();
+        }
+        if let Some(tags) = self.tags.remove(from) {
+            self.tags.insert(to.to_string(), tags);
+        }
+        if self.marked.remove(from) {
+            self.marked.insert(to.to_string());
+        }
+        self.refresh_secret_list();
+        self.select_path(to);
+        Ok(())
+    }
+
+    /// run a line of the `:` command line and remember it
+    pub fn run_command(&mut self, line: &str) -> Result<(), String> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+        if self.command_history.last().map(String::as_str) != Some(line) {
+            self.command_history.push(line.to_string());
+            if self.command_history.len() > MAX_COMMAND_HISTORY {
+                self.command_history.remove(0);
+            }
+        }
+        self.command_history_index = None;
+
+        match PaletteCommand::parse(line)? {
+            PaletteCommand::Add { name } => {
+                self.switch_mode(Mode::Add);
+                let panel = self.get_panel(PanelName::AddSecret);
+                panel.content[0].set(&name);
+                panel.index = 1;
+                Ok(())
+            }
+            PaletteCommand::Gen { name, length, symbols, words: true } => {
+                let spec = match symbols {
+                    true => format!("{} digit symbol", DEFAULT_PASSPHRASE),
+                    false => DEFAULT_PASSPHRASE.to_string(),
+                };
+                let value = Generator::parse("words", &spec, length.unwrap_or(DEFAULT_WORDS))?.generate()?;
+                self.add_secret(name, value)
+            }
+            PaletteCommand::Gen { name, length, symbols, words: false } => {
+                let length = length.unwrap_or(self.config.make_length());
+                let policy = if symbols { STRONG_POLICY } else { self.config.make_policy() };
+                let value = Generator::parse("chars", policy, length)?.generate()?;
+                self.add_secret(name, value)
+            }
+            PaletteCommand::Move { from, to } => self.rename_path(&from, &to),
+            PaletteCommand::Tag { expr } if !self.marked.is_empty() => {
+                self.confirm_bulk(Bulk::Tag(expr));
+                Ok(())
+            }
+            PaletteCommand::Tag { expr } => {
+                let secret = self.get_selected_item().ok_or("No secret selected")?;
+                self.tag_secrets(&[secret.name], &expr)
+            }
+            PaletteCommand::Export { path } if !self.marked.is_empty() => {
+                self.confirm_bulk(Bulk::Export(path.unwrap_or_else(|| EXPORT_FILE.to_string())));
+                Ok(())
+            }
+            PaletteCommand::Export { path } => {
+                let names: Vec<String> = self.secrets.iter().map(|(name, _)| name.clone()).collect();
+                self.export_secrets(&names, path.as_deref().unwrap_or(EXPORT_FILE))
+            }
+            PaletteCommand::Save { attachment, path } => {
+                let path = path.unwrap_or_else(|| attachment.clone());
+                self.save_selected_attachment(&attachment, &path)
+            }
+            PaletteCommand::Type { kind } => self.set_selected_kind(&kind),
+            PaletteCommand::Field { field, value } => self.set_selected_field(&field, &value),
+            PaletteCommand::Sort { order } => {
+                self.set_sort(SortOrder::parse(&order)?);
+                Ok(())
+            }
+            PaletteCommand::Vault { name } => self.open_vault(&name),
+            PaletteCommand::Help => {
+                self.help_scroll = Some(0);
+                Ok(())
+            }
+            PaletteCommand::Quit => {
+                self.should_exit = true;
+                Ok(())
+            }
+        }
+    }
+
+    /// complete the last word of the command line, a command name first, then
+    /// secret names, folders or tags depending on the command
+    pub fn complete_command(&mut self) {
+        let line = self.panels[&PanelName::Command].content[0].value().to_string();
+        let (head, word) = match line.rfind(char::is_whitespace) {
+            Some(i) => line.split_at(i + 1),
+            None => ("", line.as_str()),
+        };
+        let candidates: Vec<String> = match head.split_whitespace().next() {
+            None => COMMANDS.iter().map(|command| command.to_string()).collect(),
+            Some("add" | "gen" | "mv") => {
+                let mut paths = BTreeSet::new();
+                for (name, _) in &self.secrets {
+                    let mut folder = name.as_str();
+                    while let Some(parent) = tree::parent(folder) {
+                        paths.insert(format!("{}{}", parent, tree::SEPARATOR));
+                        folder = parent;
+                    }
+                    paths.insert(name.clone());
+                }
+                paths.into_iter().collect()
+            }
+            Some("tag") => {
+                let sign = word.chars().next().filter(|ch| *ch == '+' || *ch == '-').map(String::from).unwrap_or_default();
+                let tags: BTreeSet<&String> = self.tags.values().flatten().collect();
+                tags.into_iter().map(|tag| format!("{}{}", sign, tag)).collect()
+            }
+            Some("sort") => SortOrder::names().into_iter().map(String::from).collect(),
+            Some("vault") => self.vault_names(),
+            Some("type") => RecordKind::ALL.iter().map(|kind| kind.name().to_string()).chain(["none".to_string()]).collect(),
+            Some("save") if head.split_whitespace().count() == 1 => self
+                .get_selected_item()
+                .and_then(|secret| self.attachments.get(&secret.name))
+                .map(|attachments| attachments.iter().map(|attachment| attachment.name.clone()).collect())
+                .unwrap_or_default(),
+            Some("field") => self
+                .get_selected_item()
+                .and_then(|secret| self.records.get(&secret.name))
+                .map(|record| record.kind.fields()[1..].iter().map(|field| field.to_string()).collect())
+                .unwrap_or_default(),
+            _ => vec![],
+        };
+        let matches: Vec<String> = candidates.into_iter().filter(|candidate| candidate.starts_with(word)).collect();
+        let completed = match matches.as_slice() {
+            [] => return,
+            // a folder can be completed further, anything else is done
+            [only] if only.ends_with(tree::SEPARATOR) => only.clone(),
+            [only] => format!("{} ", only),
+            _ => {
+                let prefix = common_prefix(&matches);
+                if prefix.len() == word.len() {
+                    self.notify(matches.join("  "));
+                    return;
+                }
+                prefix
+            }
+        };
+        self.get_panel(PanelName::Command).content[0].set(&format!("{}{}", head, completed));
+    }
+
+    /// show an older (or newer) line from the command history
+    pub fn step_command_history(&mut self, older: bool) {
+        let index = match (self.command_history_index, older) {
+            (None, true) => self.command_history.len().checked_sub(1),
+            (None, false) => None,
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) => Some(i + 1).filter(|i| *i < self.command_history.len()),
+        };
+        if index.is_none() && self.command_history_index.is_none() {
+            return;
+        }
+        self.command_history_index = index;
+        let line = index.map(|i| self.command_history[i].clone()).unwrap_or_default();
+        self.get_panel(PanelName::Command).content[0].set(&line);
+    }
+
+    /// copy the marked names to the clipboard, one per line
+    pub fn copy_marked_names(&mut self) -> Result<(), String> {
+        let names = self.marked_names().join("\n");
+        self.clipboard.copy(names)?;
+        self.notify(format!("Copied {} names", self.marked.len()));
+        Ok(())
     }
 }
 
@@ -324,4 +1666,37 @@ impl From<&Secret> for ListItem<'_> {
 //         result.push(format!("{}: {}", secret.name, secret.value));
 //     }
 //     result
-// }
\ No newline at end of file
+// }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            PaletteCommand::parse("gen aws/root 24 --symbols").unwrap(),
+            PaletteCommand::Gen { name: "aws/root".to_string(), length: Some(24), symbols: true, words: false },
+        );
+        assert_eq!(
+            PaletteCommand::parse("mv \"old name\" new").unwrap(),
+            PaletteCommand::Move { from: "old name".to_string(), to: "new".to_string() },
+        );
+        assert_eq!(PaletteCommand::parse("tag +prod -old").unwrap(), PaletteCommand::Tag { expr: "+prod -old".to_string() });
+        assert_eq!(PaletteCommand::parse("export").unwrap(), PaletteCommand::Export { path: None });
+        assert_eq!(PaletteCommand::parse("vault work").unwrap(), PaletteCommand::Vault { name: "work".to_string() });
+        assert!(PaletteCommand::parse("vault").is_err());
+        assert!(PaletteCommand::parse("gen x many").is_err());
+        assert!(PaletteCommand::parse("mv a").is_err());
+        assert!(PaletteCommand::parse("q now").is_err());
+        assert!(PaletteCommand::parse("rmrf").is_err());
+        assert!(PaletteCommand::parse("add \"open").is_err());
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        let names = vec!["aws/prod".to_string(), "aws/pre".to_string(), "aws/prod/db".to_string()];
+        assert_eq!(common_prefix(&names), "aws/pr");
+        assert_eq!(common_prefix(&[]), "");
+    }
+}
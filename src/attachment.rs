@@ -0,0 +1,173 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+
+use crypto::hmac::Hmac;
+use crypto::mac::{Mac, MacResult};
+use crypto::sha2::Sha256;
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+use crate::utils;
+
+/// attachments are encrypted and stored in pieces of this size, so neither
+/// writing nor reading one holds the whole file in memory
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// `max_attachment_size` in the config counts these
+pub const MIB: u64 = 1024 * 1024;
+
+const IV_LEN: usize = 16;
+const MAC_LEN: usize = 32;
+
+/// encrypts the chunks of attachments with keys derived from the secm key in the keychain
+pub struct Sealer {
+    encryption: [u8; 32],
+    authentication: [u8; 32],
+}
+
+impl Sealer {
+    pub fn new(master: &[u8]) -> Sealer {
+        let derive = |purpose: &str| {
+            let mut mac = Hmac::new(Sha256::new(), master);
+            mac.input(purpose.as_bytes());
+            let mut key = [0; 32];
+            key.copy_from_slice(mac.result().code());
+            key
+        };
+        Sealer {
+            encryption: derive("secm attachment encryption"),
+            authentication: derive("secm attachment authentication"),
+        }
+    }
+
+    pub fn from_keychain() -> Sealer {
+        utils::generate_secm_key();
+        Sealer::new(utils::get_secm_key().as_bytes())
+    }
+
+    /// iv, ciphertext and a MAC over where the chunk belongs, so chunks can't be
+    /// swapped, dropped or moved to another attachment without `open` noticing
+    pub fn seal(&self, attachment: i64, seq: i64, last: bool, data: &[u8]) -> Result<Vec<u8>, String> {
+        let mut iv = [0; IV_LEN];
+        OsRng.fill_bytes(&mut iv);
+        let ciphertext = utils::aes256_cbc_encrypt(data, &self.encryption, &iv)
+            .map_err(|e| format!("Unable to encrypt attachment: {:?}", e))?;
+        let mut sealed = iv.to_vec();
+        sealed.extend(ciphertext);
+        let mac = self.mac(attachment, seq, last, &sealed);
+        sealed.extend(mac.code());
+        Ok(sealed)
+    }
+
+    pub fn open(&self, attachment: i64, seq: i64, last: bool, sealed: &[u8]) -> Result<Vec<u8>, String> {
+        let damaged = || "Attachment is damaged or was encrypted with another key".to_string();
+        if sealed.len() < IV_LEN + MAC_LEN {
+            return Err(damaged());
+        }
+        let (body, mac) = sealed.split_at(sealed.len() - MAC_LEN);
+        // MacResult compares in constant time
+        if self.mac(attachment, seq, last, body) != MacResult::new(mac) {
+            return Err(damaged());
+        }
+        let (iv, ciphertext) = body.split_at(IV_LEN);
+        let iv: &[u8; IV_LEN] = iv.try_into().map_err(|_| damaged())?;
+        utils::aes256_cbc_decrypt(ciphertext, &self.encryption, iv).map_err(|_| damaged())
+    }
+
+    fn mac(&self, attachment: i64, seq: i64, last: bool, body: &[u8]) -> MacResult {
+        let mut mac = Hmac::new(Sha256::new(), &self.authentication);
+        mac.input(&attachment.to_be_bytes());
+        mac.input(&seq.to_be_bytes());
+        mac.input(&[last as u8]);
+        mac.input(body);
+        mac.result()
+    }
+}
+
+/// attachment names double as file names when they are saved, so they can't point elsewhere
+pub fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') || name.chars().any(char::is_control) {
+        return Err(format!("Invalid attachment name '{}'", name));
+    }
+    Ok(())
+}
+
+/// fill `buf` from `data` as far as it goes, short only at the end of `data`
+pub fn read_chunk(data: &mut dyn Read, buf: &mut Vec<u8>) -> Result<(), String> {
+    buf.resize(CHUNK_SIZE, 0);
+    let mut filled = 0;
+    while filled < CHUNK_SIZE {
+        match data.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(format!("Unable to read attachment: {}", e)),
+        }
+    }
+    buf.truncate(filled);
+    Ok(())
+}
+
+/// create `path` readable by the user only and fill it with `write`. An existing
+/// file is left alone, and nothing is left behind when `write` fails.
+pub fn save_to<T>(path: &Path, write: impl FnOnce(&mut File) -> Result<T, String>) -> Result<T, String> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| format!("Unable to create {}: {}", path.display(), e))?;
+    // the umask can't widen the mode, but make sure it is exactly 0600
+    let written = file
+        .set_permissions(fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("Unable to protect {}: {}", path.display(), e))
+        .and_then(|_| write(&mut file))
+        .and_then(|written| {
+            file.sync_all().map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+            Ok(written)
+        });
+    if written.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    written
+}
+
+/// a size for people, e.g. `1.5 MiB`
+pub fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / MIB as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal() {
+        let sealer = Sealer::new(b"master key");
+        let sealed = sealer.seal(7, 2, false, b"kubeconfig").unwrap();
+        assert_eq!(sealer.open(7, 2, false, &sealed).unwrap(), b"kubeconfig");
+
+        // a chunk only opens where it was sealed
+        assert!(sealer.open(8, 2, false, &sealed).is_err());
+        assert!(sealer.open(7, 3, false, &sealed).is_err());
+        assert!(sealer.open(7, 2, true, &sealed).is_err());
+        assert!(Sealer::new(b"other key").open(7, 2, false, &sealed).is_err());
+        let mut tampered = sealed.clone();
+        tampered[IV_LEN] ^= 1;
+        assert!(sealer.open(7, 2, false, &tampered).is_err());
+
+        assert!(check_name("cert.p12").is_ok());
+        assert!(check_name("../.bashrc").is_err());
+        assert!(check_name("..").is_err());
+
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(16 * MIB), "16.0 MiB");
+    }
+}
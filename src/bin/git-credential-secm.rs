@@ -0,0 +1,16 @@
+use secm::{cmds, config::{Config, Options}};
+
+/// `git config credential.helper secm` makes git run `git-credential-secm get|store|erase`,
+/// the same as `secm git-credential` with the default config and database
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = Options::default();
+    let result = Config::load(&options).and_then(|config| {
+        let (db, _) = config.db_path(&options)?;
+        cmds::cmd_git_credential(&db, &config, &args)
+    });
+    if let Err(err) = result {
+        eprintln!("secm: {}", err);
+        std::process::exit(1);
+    }
+}
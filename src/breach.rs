@@ -0,0 +1,162 @@
+use std::cmp::Ordering;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crypto::digest::Digest;
+use crypto::sha1::Sha1;
+
+/// below this many bytes the rest of the file is read line by line
+const SCAN_SIZE: u64 = 4096;
+
+/// a local copy of the Have I Been Pwned passwords, either the single file of
+/// `HASH:COUNT` lines ordered by hash, or a directory of range files named by
+/// the first 5 characters of the hash and holding `SUFFIX:COUNT` lines
+pub enum Dataset {
+    File(PathBuf),
+    Ranges(PathBuf),
+}
+
+/// the SHA-1 of `value` in upper case hex, as the dataset has it
+pub fn sha1_hex(value: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.input_str(value);
+    sha1.result_str().to_uppercase()
+}
+
+impl Dataset {
+    pub fn open(path: &Path) -> Result<Dataset, String> {
+        let metadata = fs::metadata(path).map_err(|e| format!("Unable to open {}: {}", path.display(), e))?;
+        match metadata.is_dir() {
+            true => Ok(Dataset::Ranges(path.to_path_buf())),
+            false => Ok(Dataset::File(path.to_path_buf())),
+        }
+    }
+
+    /// how often `value` was seen in breaches, 0 when never
+    pub fn count(&self, value: &str) -> Result<u64, String> {
+        let hash = sha1_hex(value);
+        match self {
+            Dataset::File(path) => search_file(path, &hash),
+            Dataset::Ranges(dir) => search_range(dir, &hash),
+        }
+    }
+}
+
+/// the hash and count of a `HASH:COUNT` line
+fn parse_line(line: &str) -> Option<(&str, u64)> {
+    let (hash, count) = line.trim_end().split_once(':')?;
+    Some((hash, count.parse().ok()?))
+}
+
+fn compare(hash: &str, target: &str) -> Ordering {
+    hash.to_ascii_uppercase().as_str().cmp(target)
+}
+
+/// binary search over the bytes of the ordered file, it is far too big to read
+fn search_file(path: &Path, hash: &str) -> Result<u64, String> {
+    let error = |e: std::io::Error| format!("Unable to read {}: {}", path.display(), e);
+    let file = File::open(path).map_err(error)?;
+    let len = file.metadata().map_err(error)?.len();
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+
+    // lo and hi are always at the start of a line, the hash is in between if anywhere
+    let (mut lo, mut hi) = (0, len);
+    while hi - lo > SCAN_SIZE {
+        let mid = lo + (hi - lo) / 2;
+        reader.seek(SeekFrom::Start(mid)).map_err(error)?;
+        line.clear();
+        // the rest of the line mid fell into
+        let start = mid + reader.read_line(&mut line).map_err(error)? as u64;
+        if start >= hi {
+            break;
+        }
+        line.clear();
+        let read = reader.read_line(&mut line).map_err(error)? as u64;
+        let Some((found, count)) = parse_line(&line) else {
+            return Err(format!("Invalid line in {} at byte {}", path.display(), start));
+        };
+        match compare(found, hash) {
+            Ordering::Less => lo = start + read,
+            Ordering::Equal => return Ok(count),
+            Ordering::Greater => hi = start,
+        }
+    }
+
+    reader.seek(SeekFrom::Start(lo)).map_err(error)?;
+    let mut position = lo;
+    while position < hi {
+        line.clear();
+        let read = reader.read_line(&mut line).map_err(error)?;
+        if read == 0 {
+            break;
+        }
+        position += read as u64;
+        match parse_line(&line) {
+            Some((found, count)) if compare(found, hash) == Ordering::Equal => return Ok(count),
+            _ => {}
+        }
+    }
+    Ok(0)
+}
+
+/// the range file of the hash's first 5 characters, a few hundred sorted lines
+fn search_range(dir: &Path, hash: &str) -> Result<u64, String> {
+    let (prefix, suffix) = hash.split_at(5);
+    let path = dir.join(format!("{}.txt", prefix));
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(format!("{} has no range file {}.txt", dir.display(), prefix));
+        }
+        Err(e) => return Err(format!("Unable to read {}: {}", path.display(), e)),
+    };
+    let lines: Vec<(&str, u64)> = content.lines().filter_map(parse_line).collect();
+    Ok(lines
+        .binary_search_by(|(found, _)| compare(found, suffix))
+        .map(|i| lines[i].1)
+        .unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_search() {
+        assert_eq!(sha1_hex("password"), "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8");
+
+        let dir = env::temp_dir().join(format!("secm-hibp-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // many filler lines so the search has to jump around the file
+        let mut hashes: Vec<(String, u64)> = (0..2000).map(|i| (sha1_hex(&format!("filler{}", i)), i)).collect();
+        hashes.push((sha1_hex("password"), 9545824));
+        hashes.push((sha1_hex("hunter2"), 17043));
+        hashes.sort();
+
+        let file = dir.join("pwned.txt");
+        let mut out = File::create(&file).unwrap();
+        for (hash, count) in &hashes {
+            write!(out, "{}:{}\r\n", hash, count).unwrap();
+        }
+        let dataset = Dataset::open(&file).unwrap();
+        assert_eq!(dataset.count("password").unwrap(), 9545824);
+        assert_eq!(dataset.count("hunter2").unwrap(), 17043);
+        assert_eq!(dataset.count("filler1999").unwrap(), 1999);
+        assert_eq!(dataset.count("hK8#qZ2!vT9@wL4$").unwrap(), 0);
+
+        let ranges = dir.join("ranges");
+        fs::create_dir_all(&ranges).unwrap();
+        let hash = sha1_hex("password");
+        fs::write(ranges.join(format!("{}.txt", &hash[..5])), format!("0018A45C4D1DEF81644B54AB7F969B88D65:1\n{}:9545824\n", &hash[5..])).unwrap();
+        let dataset = Dataset::open(&ranges).unwrap();
+        assert_eq!(dataset.count("password").unwrap(), 9545824);
+        assert!(dataset.count("hunter2").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,60 +1,72 @@
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::Read;
+use std::io::IsTerminal;
+use std::io::{BufRead, BufReader, Read};
 use std::io::Write;
 use std::path::Path;
 
-use crate::model::Secret;
+use crate::breach::Dataset;
+use crate::config::{expand_home, Config};
+use crate::credential::{self, Credential};
+use crate::generator::{Generator, Policy, STRONG_POLICY};
+use crate::attachment;
+use crate::model::{self, ExportedAttachment, ExportedSecret, Record, RecordKind, Secret};
+use crate::otp;
+use crate::ssh;
 use crate::storage::SqliteStorage;
-use crate::utils;
+use crate::strength::{self, Estimate};
+use crate::tree::{self, TreeRow};
 use crate::Storage;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use clipboard::ClipboardProvider;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use ssh_key::{HashAlg, PrivateKey};
 use clipboard::ClipboardContext;
-use serde_json::json;
-// use serde_json::json;
-// use std::fs::File;
-// use std::io::Write;
 
-pub fn cmd_make(args: &[String]) -> Result<(), String> {
-    let mut length = 10;
-    let mut advance = false;
+pub fn cmd_make(db: &Path, config: &Config, args: &[String]) -> Result<(), String> {
+    let usage = "usage: secm make <name> [-l=<length>] [-p=<policy>] [-a] [-v=<value>]";
+    let mut length = config.make_length();
+    let mut policy = config.make_policy().to_string();
 
-    let name = args[0].trim();
-    if name.starts_with("-") {
+    let name = args.first().ok_or(usage)?.trim();
+    if name.is_empty() || name.starts_with('-') {
         return Err("invalid name".to_string());
     }
 
     let mut value = "".to_string();
 
-    for i in 1..args.len() {
-        let arg = args[i].trim().trim_start_matches("-");
-        let arg_value: Vec<&str> = arg.split("=").collect();
-        match arg_value[0] {
+    for arg in &args[1..] {
+        let arg = arg.trim().trim_start_matches('-');
+        let (flag, arg_value) = arg.split_once('=').unwrap_or((arg, ""));
+        match flag {
             "v" | "value" => {
-                let value_arg = arg_value[1];
-                if value_arg != "" {
-                    value = value_arg.to_string();
-                } else {
+                if arg_value.is_empty() {
                     return Err("secret value is empty".to_string());
                 }
+                value = arg_value.to_string();
             },
             "l" | "length" => {
-                let length_arg = arg_value[1];
-                if let Ok(length_arg) = length_arg.parse::<usize>() {
-                // https://stackoverflow.com/questions/37936058/why-does-iterating-over-a-hashmapstr-str-yield-a-str
-                // let random_string = generate_random_string(length, *cmd_args.get("advance").unwrap_or(&"false") == "true");
-                    length = length_arg;
-                } else {
-                    return Err("length arg is not numeric".to_string());
-                }
+                length = arg_value.parse::<usize>().map_err(|_| "length arg is not numeric".to_string())?;
             },
-            "a" | "advance" => advance = true,
-            _ => return Err("invalid argument".to_string())
+            "p" | "policy" => policy = arg_value.to_string(),
+            "a" | "advance" => policy = STRONG_POLICY.to_string(),
+            _ => return Err(format!("invalid argument, {}", usage))
         }
     }
 
-    if value == "" {
-        value = utils::generate_random_string(length, advance);
+    if value.is_empty() {
+        value = Policy::parse(&policy, length)?.generate()?;
+    }
+    config.check_secret(&value)?;
+
+    let storage = open_storage(db)?;
+    if storage.read(name)?.is_some() {
+        return Err(format!("secret '{}' already exists", name));
     }
+    storage.write(name, &value)?;
+    warn_if_reused(&storage, name, &value)?;
 
     let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
 
@@ -63,52 +75,179 @@ pub fn cmd_make(args: &[String]) -> Result<(), String> {
     // set_contents()的参数是 data: String, 这意味着会发生所有权转移
     ctx.set_contents(value.to_owned()).expect("Failed to set clipboard content");
     println!("Generated secret {}: ********, copied to clipboard", name);
-    // utils::store_secret(name, &value, secret_file);
-
-    // ctx.set_contents(value).expect("Failed to set clipboard content");
-    // println!("Generated secret {}: ********, copied to clipboard", name);
-    // utils::store_secret(name, &value, secret_file);
     // ------------------------------
 
     Ok(()) // 只有写在最后的且没加分号的才会被当成返回值
 }
 
-pub fn cmd_export() -> Result<(), String> {
-    let secret_file = "secrets.json";
-    // judge if the file exists
-    if Path::new(secret_file).exists() {
-        return Err(format!("{} already exists", secret_file));
+/// list the stored secrets from the weakest, each with its strength and what makes it weak
+pub fn cmd_audit(db: &Path, config: &Config, args: &[String]) -> Result<(), String> {
+    let usage = "usage: secm audit [--reuse | --breached [<hibp file or dir>]]";
+    let storage = open_storage(db)?;
+    match args {
+        [] => {}
+        [flag] if flag == "--reuse" => return audit_reuse(&storage),
+        [flag] if flag == "--breached" => {
+            let path = config.hibp().ok_or(format!("No hibp dataset in the config, {}", usage))?;
+            return audit_breached(&storage, &path);
+        }
+        [flag, path] if flag == "--breached" => return audit_breached(&storage, &expand_home(path)),
+        [flag] if flag.starts_with("--breached=") => return audit_breached(&storage, &expand_home(&flag["--breached=".len()..])),
+        _ => return Err(usage.to_string()),
     }
-    
-    let home_dir = dirs::home_dir()
-    .ok_or("Unable to determine home directory")?;
+    let mut estimates: Vec<(String, Estimate)> = storage
+        .get_all()?
+        .into_iter()
+        .map(|(name, value)| (name, strength::estimate(&value)))
+        .collect();
+    estimates.sort_by(|a, b| a.1.guesses_log10.total_cmp(&b.1.guesses_log10).then_with(|| a.0.cmp(&b.0)));
+
+    let width = estimates.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, estimate) in &estimates {
+        let warning = estimate.warning.unwrap_or_default();
+        println!("{} {:<11} {:<width$}  {}", estimate.score, estimate.label(), name, warning, width = width);
+    }
+    let weak = estimates.iter().filter(|(_, estimate)| estimate.score < 3).count();
+    println!("{} of {} secrets are weak", weak, estimates.len());
+    Ok(())
+}
 
-    let home_dir_str = home_dir
-        .to_str()
-        .ok_or("Home directory contains invalid UTF-8")?;
+/// the groups of secrets sharing a value, one line per group
+fn audit_reuse(storage: &SqliteStorage) -> Result<(), String> {
+    let groups = storage.get_reused()?;
+    for group in &groups {
+        println!("{} secrets share a value: {}", group.len(), group.join(", "));
+    }
+    let reused: usize = groups.iter().map(Vec::len).sum();
+    println!("{} secrets reuse a value in {} groups", reused, groups.len());
+    Ok(())
+}
 
-    let storage = SqliteStorage::new(&format!("{}/.secrets.db", home_dir_str))?;
-    let secrets = storage.get_all()?;
-    // write all secrets to json file
-    let mut secrets_json = Vec::new();
-    for (name, value) in secrets {
-        secrets_json.push(json!({
-            "name": name,
-            "value:": value
-        }));
-    }
-    let mut file = File::create(secret_file).map_err(|e| format!("Unable to create secret file: {}", e))?;
-    let json_string = serde_json::to_string_pretty(&secrets_json)
-        .map_err(|e| format!("Failed to serialize secrets to JSON: {}", e))?;
-    
-    file.write_all(json_string.as_bytes())
-        .map_err(|e| format!("Failed to write to secret file: {}", e))?;
+/// the secrets found in the local Have I Been Pwned dataset at `path`, the most seen first
+fn audit_breached(storage: &SqliteStorage, path: &Path) -> Result<(), String> {
+    let dataset = Dataset::open(path)?;
+    let mut breached = vec![];
+    for (name, value) in storage.get_all()? {
+        let count = dataset.count(&value)?;
+        if count > 0 {
+            breached.push((name, count));
+        }
+    }
+    breached.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let width = breached.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, count) in &breached {
+        println!("{:<width$}  seen {} times", name, count, width = width);
+    }
+    println!("{} secrets were found in breaches", breached.len());
+    Ok(())
+}
+
+/// warn on stderr when the value of the new secret `name` is used by other secrets too
+fn warn_if_reused(storage: &SqliteStorage, name: &str, value: &str) -> Result<(), String> {
+    let others: Vec<String> = storage.names_with_value(value)?.into_iter().filter(|other| other != name).collect();
+    if !others.is_empty() {
+        eprintln!("warning: the same value is used by {}", others.join(", "));
+    }
+    Ok(())
+}
+
+/// print a new secret without saving it, how strong it is goes to stderr
+pub fn cmd_gen(config: &Config, args: &[String]) -> Result<(), String> {
+    let usage = "usage: secm gen [-l <length>] [-p <policy>] | --words <n> [--sep <s>] [--case <lower|title|upper|random>] [--digit] [--symbol]";
+    let mut words = None;
+    let mut length = config.make_length();
+    let mut policy = config.make_policy().to_string();
+    let mut options: Vec<String> = vec![];
+
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        let mut value = || inline.clone().or_else(|| rest.next().cloned()).ok_or(format!("{} needs a value, {}", flag, usage));
+        match flag {
+            "-w" | "--words" => words = Some(value()?.parse::<usize>().map_err(|_| "words arg is not numeric".to_string())?),
+            "-l" | "--length" => length = value()?.parse::<usize>().map_err(|_| "length arg is not numeric".to_string())?,
+            "-p" | "--policy" => policy = value()?,
+            "--sep" => options.push(format!("sep:{}", value()?)),
+            "--case" => options.push(format!("case:{}", value()?)),
+            "--digit" => options.push("digit".to_string()),
+            "--symbol" => options.push("symbol".to_string()),
+            _ => return Err(format!("invalid argument {}, {}", arg, usage)),
+        }
+    }
+
+    let generator = match words {
+        Some(words) => Generator::parse("words", &options.join(" "), words)?,
+        None if options.is_empty() => Generator::parse("chars", &policy, length)?,
+        None => return Err(format!("passphrase options need --words, {}", usage)),
+    };
+    let value = generator.generate()?;
+    config.check_breached(&value)?;
+    println!("{}", value);
+    eprintln!("entropy: {:.0} bits", generator.entropy_bits());
+    Ok(())
+}
+
+/// the database at `db`, see `Config::db_path`
+fn open_storage(db: &Path) -> Result<SqliteStorage, String> {
+    SqliteStorage::new(db.to_str().ok_or("Database path contains invalid UTF-8")?)
+}
+
+/// write all secrets to ./secrets.json, an existing file only with `--force`
+pub fn cmd_export(db: &Path, args: &[String]) -> Result<(), String> {
+    let secret_file = "secrets.json";
+    let force = match args {
+        [] => false,
+        [flag] if flag == "--force" || flag == "-f" => true,
+        _ => return Err("usage: secm export [--force]".to_string()),
+    };
+
+    let storage = open_storage(db)?;
+    let secrets: Vec<Secret> = storage
+        .get_all()?
+        .into_iter()
+        .map(|(name, value)| Secret { name, value })
+        .collect();
+    write_secrets_json(&storage, secret_file, &secrets, force)?;
 
     println!("Exported all secrets to secrets.json");
     Ok(())
 }
 
-pub fn cmd_import() -> Result<(), String> {
+/// write secrets and their attachments to a json file in the format `cmd_import` reads back,
+/// an existing file is only replaced with `overwrite`
+pub fn write_secrets_json<S: Storage>(storage: &S, secret_file: &str, secrets: &[Secret], overwrite: bool) -> Result<(), String> {
+    if !overwrite && Path::new(secret_file).exists() {
+        return Err(format!("{} already exists, use --force to replace it", secret_file));
+    }
+    let attachments = storage.get_attachments()?;
+    let records: HashMap<String, Record> = storage.get_records()?.into_iter().collect();
+    let mut exported = vec![];
+    for secret in secrets {
+        let mut files = vec![];
+        for (_, attachment) in attachments.iter().filter(|(key, _)| *key == secret.name) {
+            let mut data = vec![];
+            storage.read_attachment(&secret.name, &attachment.name, &mut data)?;
+            files.push(ExportedAttachment { name: attachment.name.clone(), data: BASE64.encode(data) });
+        }
+        exported.push(ExportedSecret { secret: secret.clone(), record: records.get(&secret.name).cloned(), attachments: files });
+    }
+    let json_string = serde_json::to_string_pretty(&exported)
+        .map_err(|e| format!("Failed to serialize secrets to JSON: {}", e))?;
+    // the export holds every secret in plain text, so it is created 0600 like a saved attachment
+    if overwrite && Path::new(secret_file).exists() {
+        std::fs::remove_file(secret_file).map_err(|e| format!("Unable to replace {}: {}", secret_file, e))?;
+    }
+    attachment::save_to(Path::new(secret_file), |file| {
+        file.write_all(json_string.as_bytes())
+            .map_err(|e| format!("Failed to write to secret file: {}", e))
+    })
+}
+
+pub fn cmd_import(db: &Path, config: &Config) -> Result<(), String> {
     let secret_file = "secrets.json";
     // judge if the file exists
     if !Path::new(secret_file).exists() {
@@ -119,15 +258,24 @@ pub fn cmd_import() -> Result<(), String> {
     let mut file = File::open(secret_file).map_err(|e| format!("Unable to open secret file: {}", e))?;
     let mut json_string = String::new();
     file.read_to_string(&mut json_string).map_err(|e| format!("Failed to read secret file: {}", e))?;
-    let secrets: Vec<Secret> = serde_json::from_str(&json_string)
+    let secrets: Vec<ExportedSecret> = serde_json::from_str(&json_string)
         .map_err(|e| format!("Failed to deserialize secrets from JSON: {}", e))?;
 
     // write all secrets to db
-    let home_dir = dirs::home_dir().ok_or("Unable to determine home directory")?;
-    let home_dir_str = home_dir.to_str().ok_or("Home directory contains invalid UTF-8")?;
-    let storage = SqliteStorage::new(&format!("{}/.secrets.db", home_dir_str))?;
-    for secret in secrets {
-        storage.write(&secret.name, &secret.value).map_err(|e| format!("Failed to store secret: {}", e))?;
+    let storage = open_storage(db)?;
+    for ExportedSecret { secret, record, attachments } in secrets {
+        // a secret comes in whole with its record and files or not at all
+        storage.atomic(&mut |storage| {
+            storage.write(&secret.name, &secret.value).map_err(|e| format!("Failed to store secret: {}", e))?;
+            storage.set_record(&secret.name, record.as_ref())?;
+            for attachment in &attachments {
+                let data = BASE64
+                    .decode(&attachment.data)
+                    .map_err(|e| format!("Invalid attachment {} of {}: {}", attachment.name, secret.name, e))?;
+                storage.add_attachment(&secret.name, &attachment.name, &mut data.as_slice(), config.max_attachment_size())?;
+            }
+            Ok(())
+        })?;
         println!("Stored secret: {}", &secret.name);
     }
 
@@ -135,7 +283,444 @@ pub fn cmd_import() -> Result<(), String> {
     Ok(())
 }
 
-pub fn cmd_save(args: &[String]) -> Result<(), String> {
+pub fn cmd_ls(db: &Path, args: &[String]) -> Result<(), String> {
+    let favorites = args.iter().any(|arg| arg == "-f" || arg == "--favorites");
+    let folder = args.iter().find(|arg| !arg.starts_with('-')).map(|s| s.as_str()).unwrap_or("");
+    let folder = tree::normalize_prefix(folder);
+
+    let storage = open_storage(db)?;
+
+    if favorites {
+        // numbered like in the TUI, the numbers are what `secm fav` takes
+        let favorites = storage.get_favorites()?;
+        if favorites.is_empty() {
+            return Err("no favorites, pin secrets with f in the TUI".to_string());
+        }
+        for (i, name) in favorites.iter().enumerate() {
+            if tree::in_folder(name, folder) {
+                println!("{} {}", i + 1, name);
+            }
+        }
+        return Ok(());
+    }
+    let secrets = storage.get_all()?;
+
+    // like ls, only show the direct children of the folder
+    let rows = tree::build_rows(&secrets, folder, &|_| true, &|_| 0);
+    if rows.is_empty() {
+        return Err(format!("no secrets under '{}'", folder));
+    }
+    for row in rows {
+        match row {
+            TreeRow::Folder { count, .. } => println!("{}/ ({})", row.label(), count),
+            TreeRow::Secret { .. } => println!("{}", row.label()),
+        }
+    }
+    Ok(())
+}
+
+pub fn cmd_fav(db: &Path, args: &[String]) -> Result<(), String> {
+    let usage = "usage: secm fav <n>, see secm ls --favorites for the numbers";
+    let number = args
+        .first()
+        .ok_or(usage)?
+        .parse::<usize>()
+        .map_err(|_| usage.to_string())?;
+
+    let storage = open_storage(db)?;
+
+    let favorites = storage.get_favorites()?;
+    let name = number
+        .checked_sub(1)
+        .and_then(|i| favorites.get(i))
+        .ok_or(format!("no favorite {}, there are {}", number, favorites.len()))?;
+    let value = storage.read(name)?.ok_or(format!("secret '{}' does not exist", name))?;
+
+    let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
+    ctx.set_contents(value).expect("Failed to set clipboard content");
+    storage.record_use(name)?;
+    println!("copied {} to clipboard", name);
+    Ok(())
+}
+
+/// print a field of a secret, its value when no field is given
+pub fn cmd_get(db: &Path, args: &[String]) -> Result<(), String> {
+    let usage = "usage: secm get <name> [--field <field>]";
+    let (name, field) = match args {
+        [name] => (name, "password"),
+        [name, flag, field] if flag == "--field" || flag == "-f" => (name, field.as_str()),
+        [name, flag] if flag.starts_with("--field=") => (name, &flag["--field=".len()..]),
+        _ => return Err(usage.to_string()),
+    };
+    let storage = open_storage(db)?;
+    let value = storage.read(name)?.ok_or(format!("secret '{}' does not exist", name))?;
+    let records = storage.get_records()?;
+    let record = records.iter().find(|(key, _)| key == name).map(|(_, record)| record);
+    let found = model::secret_field(record, &value, field)?.ok_or(format!("{} has no {}", name, field))?;
+    println!("{}", found);
+    storage.record_use(name)?;
+    Ok(())
+}
+
+/// print the current one-time code of a secret, and to stderr how long it stays valid
+pub fn cmd_otp(db: &Path, args: &[String]) -> Result<(), String> {
+    let [name] = args else {
+        return Err("usage: secm otp <name>".to_string());
+    };
+    let storage = open_storage(db)?;
+    let value = storage.read(name)?.ok_or(format!("secret '{}' does not exist", name))?;
+    let records = storage.get_records()?;
+    let record = records.iter().find(|(key, _)| key == name).map(|(_, record)| record);
+    let (code, remaining) = otp::use_code(&storage, name, record, &value)?;
+    println!("{}", code);
+    if let Some(remaining) = remaining {
+        eprintln!("valid for {}s", remaining);
+    }
+    storage.record_use(name)?;
+    Ok(())
+}
+
+/// generate or import an ssh key into a new secret, or print the public key of one
+pub fn cmd_ssh_key(db: &Path, args: &[String]) -> Result<(), String> {
+    let usage = format!(
+        "usage: secm ssh-key gen <name> [{}] [--bits <n>] [--comment <text>] | import <name> <file> | pub <name>",
+        ssh::KEY_TYPES.join("|")
+    );
+    let storage = open_storage(db)?;
+    match args {
+        [verb, name, rest @ ..] if verb == "gen" => {
+            let (mut kind, mut bits, mut comment) = ("ed25519", None, name.clone());
+            let mut rest = rest.iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--bits" | "-b" => bits = Some(rest.next().and_then(|bits| bits.parse().ok()).ok_or(usage.clone())?),
+                    "--comment" | "-C" => comment = rest.next().ok_or(usage.clone())?.clone(),
+                    arg if ssh::KEY_TYPES.contains(&arg) => kind = arg,
+                    _ => return Err(usage),
+                }
+            }
+            save_ssh_key(&storage, name, &ssh::generate(kind, bits, &comment)?)
+        }
+        [verb, name, file] if verb == "import" => {
+            let path = expand_home(file);
+            let pem = std::fs::read_to_string(&path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+            let passphrase = match ssh::is_encrypted(&pem) {
+                true => Some(read_hidden(&format!("Passphrase for {}: ", path.display()))?),
+                false => None,
+            };
+            save_ssh_key(&storage, name, &ssh::parse_private(&pem, passphrase.as_deref())?)
+        }
+        [verb, name] if verb == "pub" => {
+            let record = storage.get_records()?.into_iter().find(|(key, _)| key == name).map(|(_, record)| record);
+            let public = record
+                .filter(|record| record.kind == RecordKind::SshKey)
+                .and_then(|record| record.fields.get("public-key").cloned())
+                .ok_or(format!("{} is no ssh key with a public key", name))?;
+            println!("{}", public);
+            Ok(())
+        }
+        _ => Err(usage),
+    }
+}
+
+/// a new ssh-key secret holding `key`, the public key goes to stdout
+fn save_ssh_key(storage: &SqliteStorage, name: &str, key: &PrivateKey) -> Result<(), String> {
+    if storage.read(name)?.is_some() {
+        return Err(format!("secret '{}' already exists", name));
+    }
+    let (value, record) = ssh::to_secret(key)?;
+    storage.atomic(&mut |storage| {
+        storage.write(name, &value)?;
+        storage.set_record(name, Some(&record))
+    })?;
+    println!("{}", record.fields["public-key"]);
+    Ok(())
+}
+
+/// a line typed without echo, or read from stdin when that is no terminal
+fn read_hidden(prompt: &str) -> Result<String, String> {
+    eprint!("{}", prompt);
+    let mut line = String::new();
+    if !std::io::stdin().is_terminal() {
+        std::io::stdin().read_line(&mut line).map_err(|e| e.to_string())?;
+        return Ok(line.trim_end_matches(['\r', '\n']).to_string());
+    }
+    enable_raw_mode().map_err(|e| e.to_string())?;
+    let typed = loop {
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(e) => break Err(e.to_string()),
+        };
+        match key.code {
+            KeyCode::Enter => break Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break Err("Cancelled".to_string()),
+            KeyCode::Char(c) => line.push(c),
+            KeyCode::Backspace => {
+                line.pop();
+            }
+            _ => {}
+        }
+    };
+    disable_raw_mode().map_err(|e| e.to_string())?;
+    eprintln!();
+    typed.map(|_| line)
+}
+
+/// serve the ssh keys among the secrets on a unix socket until killed
+pub fn cmd_ssh_agent(db: &Path, config: &Config, args: &[String]) -> Result<(), String> {
+    let socket = match args {
+        [] => config.ssh_agent_socket()?,
+        [flag, path] if flag == "-a" || flag == "--socket" => expand_home(path),
+        _ => return Err("usage: secm ssh-agent [-a <socket>]".to_string()),
+    };
+    let policy = config.ssh_agent_policy()?;
+    for (name, key) in ssh::identities(&open_storage(db)?)? {
+        match key {
+            Ok(key) => eprintln!("{} {}", key.fingerprint(HashAlg::Sha256), name),
+            Err(err) => eprintln!("Skipping {}: {}", name, err),
+        }
+    }
+    let listener = ssh::listen(&socket)?;
+    println!("SSH_AUTH_SOCK={}; export SSH_AUTH_SOCK;", socket.display());
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("{}", err);
+                continue;
+            }
+        };
+        // a client waiting for approval mustn't hold up the others
+        let (db, policy) = (db.to_path_buf(), policy.clone());
+        std::thread::spawn(move || {
+            if let Err(err) = open_storage(&db).and_then(|storage| ssh::serve(&mut stream, &storage, &policy)) {
+                eprintln!("{}", err);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// git's credential helper, `git config credential.helper secm` runs it through `git-credential-secm`.
+/// `get` answers from the login records, `store` and `erase` only act with `store = true`
+/// under `[git_credential]` in the config and a yes on the terminal.
+pub fn cmd_git_credential(db: &Path, config: &Config, args: &[String]) -> Result<(), String> {
+    let usage = "usage: secm git-credential get|store|erase";
+    let [operation] = args else {
+        return Err(usage.to_string());
+    };
+    // helpers ignore operations git may add later
+    if !matches!(operation.as_str(), "get" | "store" | "erase") {
+        return Ok(());
+    }
+    let request = Credential::read(std::io::stdin().lock())?;
+    let storage = open_storage(db)?;
+    let found = credential::find(&storage, &request)?;
+    match (operation.as_str(), found) {
+        // an erased password is empty, git asks for a new one then
+        ("get", Some(login)) if !login.password.is_empty() => {
+            let mut out = std::io::stdout().lock();
+            if let (None, Some(username)) = (&request.username, &login.username) {
+                writeln!(out, "username={}", username).map_err(|e| e.to_string())?;
+            }
+            writeln!(out, "password={}", login.password).map_err(|e| e.to_string())?;
+            storage.record_use(&login.name)?;
+        }
+        ("get", _) => {}
+        _ if !config.git_credential_store() => {}
+        ("store", found) => {
+            let password = request.password.as_deref().ok_or("git sent no password")?;
+            match found {
+                Some(login) if login.password == password => {}
+                Some(login) => {
+                    if confirm_on_tty(&format!("secm: update the password of {} for {}?", login.name, request.url()))? {
+                        storage.update(&login.name, password)?;
+                        eprintln!("secm: updated {}", login.name);
+                    }
+                }
+                None => {
+                    let name = request.secret_name();
+                    if storage.read(&name)?.is_some() {
+                        return Err(format!("secret '{}' already exists, not storing the password for {}", name, request.url()));
+                    }
+                    if confirm_on_tty(&format!("secm: save the password for {} as {}?", request.url(), name))? {
+                        let mut record = Record::new(RecordKind::Login);
+                        record.set("username", request.username.as_deref().unwrap_or_default())?;
+                        record.set("urls", &request.url())?;
+                        storage.atomic(&mut |storage| {
+                            storage.write(&name, password)?;
+                            storage.set_record(&name, Some(&record))
+                        })?;
+                        eprintln!("secm: saved {}", name);
+                    }
+                }
+            }
+        }
+        ("erase", Some(login)) => {
+            // only the password git found wrong is erased, not one changed since, and
+            // the login keeps its username, urls, tags and attachments
+            let rejected = request.password.as_ref().is_none_or(|password| *password == login.password);
+            let question = format!(
+                "secm: git rejected the password of {} for {}, clear it? The rest of the login is kept.",
+                login.name,
+                request.url()
+            );
+            if rejected && !login.password.is_empty() && confirm_on_tty(&question)? {
+                storage.update(&login.name, "")?;
+                eprintln!("secm: cleared the password of {}", login.name);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// ask `question` on the terminal, stdin and stdout of git helpers belong to git
+fn confirm_on_tty(question: &str) -> Result<bool, String> {
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|_| "No terminal to confirm on, nothing was changed".to_string())?;
+    write!(tty, "{} [y/N] ", question).map_err(|e| e.to_string())?;
+    let mut answer = String::new();
+    BufReader::new(tty).read_line(&mut answer).map_err(|e| e.to_string())?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// attach a file to a secret, save one back, list or remove them
+pub fn cmd_attach(db: &Path, config: &Config, args: &[String]) -> Result<(), String> {
+    let usage = "usage: secm attach add <secret> <file|-> [--name <name>] | get <secret> <name> [path|-] | rm <secret> <name> | ls [secret]";
+    let storage = open_storage(db)?;
+    match args {
+        [add, secret, file, rest @ ..] if add == "add" => {
+            let name = match rest {
+                [flag, name] if flag == "--name" || flag == "-n" => name.clone(),
+                [] if file != "-" => Path::new(file)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .ok_or(format!("{} is not a file", file))?,
+                _ => return Err(usage.to_string()),
+            };
+            let max_size = config.max_attachment_size();
+            let size = if file == "-" {
+                storage.add_attachment(secret, &name, &mut std::io::stdin().lock(), max_size)?
+            } else {
+                let mut data = File::open(file).map_err(|e| format!("Unable to open {}: {}", file, e))?;
+                storage.add_attachment(secret, &name, &mut data, max_size)?
+            };
+            println!("attached {} ({}) to {}", name, attachment::format_size(size), secret);
+        }
+        [get, secret, name, rest @ ..] if get == "get" => {
+            let path = match rest {
+                [] => name,
+                [path] => path,
+                _ => return Err(usage.to_string()),
+            };
+            if path == "-" {
+                storage.read_attachment(secret, name, &mut std::io::stdout().lock())?;
+            } else {
+                let size = attachment::save_to(&expand_home(path), |file| storage.read_attachment(secret, name, file))?;
+                println!("saved {} ({}) to {}", name, attachment::format_size(size), path);
+            }
+            storage.record_use(secret)?;
+        }
+        [rm, secret, name] if rm == "rm" => {
+            storage.delete_attachment(secret, name)?;
+            println!("removed {} from {}", name, secret);
+        }
+        [ls, rest @ ..] if ls == "ls" && rest.len() < 2 => {
+            for (key, attachment) in storage.get_attachments()? {
+                if rest.first().is_none_or(|secret| *secret == key) {
+                    println!("{}  {}  {}", key, attachment.name, attachment::format_size(attachment.size));
+                }
+            }
+        }
+        _ => return Err(usage.to_string()),
+    }
+    Ok(())
+}
+
+/// show the type and fields of a secret, or give it a type and set fields like `username=octocat`
+pub fn cmd_record(db: &Path, args: &[String]) -> Result<(), String> {
+    let kinds: Vec<&str> = RecordKind::ALL.iter().map(RecordKind::name).collect();
+    let usage = format!("usage: secm record <name> [<{}|none> [<field>=<value>...]]", kinds.join("|"));
+    let (name, rest) = args.split_first().ok_or(usage.clone())?;
+    let storage = open_storage(db)?;
+    if storage.read(name)?.is_none() {
+        return Err(format!("secret '{}' does not exist", name));
+    }
+    let before = storage.get_records()?.into_iter().find(|(key, _)| key == name).map(|(_, record)| record);
+
+    let Some((kind, assignments)) = rest.split_first() else {
+        match before {
+            Some(record) => {
+                println!("type: {}", record.kind.name());
+                println!("{}: ********", record.kind.fields()[0]);
+                for field in &record.kind.fields()[1..] {
+                    let value = record.fields.get(*field).map(String::as_str).unwrap_or("");
+                    // a cvv or a totp seed is read like the value, with `secm get --field`
+                    let shown = if record.kind.is_sensitive(field) && !value.is_empty() { "********" } else { value };
+                    println!("{}: {}", field, shown);
+                }
+            }
+            None => println!("type: none, a plain secret"),
+        }
+        return Ok(());
+    };
+    if kind == "none" {
+        if !assignments.is_empty() {
+            return Err(usage);
+        }
+        return storage.set_record(name, None);
+    }
+
+    let kind = RecordKind::parse(kind)?;
+    let mut record = Record::new(kind);
+    if let Some(before) = before {
+        record.fields = before.fields;
+        record.fields.retain(|field, _| kind.fields()[1..].contains(&field.as_str()));
+    }
+    for assignment in assignments {
+        let (field, value) = assignment.split_once('=').ok_or(usage.clone())?;
+        record.set(field, value)?;
+    }
+    storage.set_record(name, Some(&record))?;
+    println!("{} is a {}", name, kind.name());
+    Ok(())
+}
+
+pub fn cmd_rm(db: &Path, args: &[String]) -> Result<(), String> {
+    let recursive = args.iter().any(|arg| arg == "-r" || arg == "-R");
+    let targets: Vec<&String> = args.iter().filter(|arg| !arg.starts_with('-')).collect();
+    if targets.is_empty() {
+        return Err("usage: secm rm [-r] <name|prefix>".to_string());
+    }
+
+    let storage = open_storage(db)?;
+
+    for target in targets {
+        if recursive {
+            let folder = tree::normalize_prefix(target);
+            let deleted = storage.delete_prefix(folder)?;
+            if deleted == 0 {
+                return Err(format!("no secrets under '{}'", folder));
+            }
+            println!("deleted {} secrets under {}/", deleted, folder);
+        } else {
+            if storage.read(target)?.is_none() {
+                return Err(format!("secret '{}' does not exist, use -r to delete a folder", target));
+            }
+            storage.delete(target)?;
+            println!("deleted secret: {}", target);
+        }
+    }
+    Ok(())
+}
+
+pub fn cmd_save(db: &Path, config: &Config, args: &[String]) -> Result<(), String> {
     // Convert args to a single string
     let combined: String = args.join(" ");
 
@@ -147,10 +732,10 @@ pub fn cmd_save(args: &[String]) -> Result<(), String> {
     let generated_name = combined.chars().take(name_length).collect::<String>();
 
     // For demonstration, print the generated name (you can handle it as needed)
-    let home_dir = dirs::home_dir().ok_or("Unable to determine home directory")?;
-    let home_dir_str = home_dir.to_str().ok_or("Home directory contains invalid UTF-8")?;
-    let storage = SqliteStorage::new(&format!("{}/.secrets.db", home_dir_str))?;
+    config.check_secret(&combined)?;
+    let storage = open_storage(db)?;
     storage.write(&generated_name, &combined).map_err(|e| format!("Failed to save: {}", e))?;
+    warn_if_reused(&storage, &generated_name, &combined)?;
 
     println!("saved secret string: {}", combined);
     Ok(())
@@ -0,0 +1,360 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::attachment;
+use crate::breach::Dataset;
+use crate::generator::DEFAULT_POLICY;
+use crate::keymap::{Keymap, KeymapConfig};
+use crate::ssh;
+use crate::strength::{self, LABELS};
+use crate::theme::{Theme, ThemeConfig};
+
+/// the config file, every key is optional:
+///
+/// ```toml
+/// db = "~/sync/secm/secrets.db"
+/// message_timeout = 5
+/// min_score = 3
+/// hibp = "~/hibp/pwned-passwords-sha1-ordered-by-hash.txt"
+/// max_attachment_size = 16
+///
+/// [make]
+/// length = 20
+/// policy = "lud2s -ambiguous"
+///
+/// [ssh_agent]
+/// socket = "~/.ssh/secm-agent.sock"
+/// approve = "ask"
+/// allow = ["ssh/ci"]
+/// timeout = 30
+///
+/// [git_credential]
+/// store = true
+///
+/// [keymap]
+/// preset = "emacs"
+///
+/// [keymap.normal]
+/// quit = ["q", "ctrl-c"]
+///
+/// [theme]
+/// base = "light"
+/// ```
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    db: Option<String>,
+    message_timeout: Option<u64>,
+    min_score: Option<u8>,
+    hibp: Option<String>,
+    max_attachment_size: Option<u64>,
+    #[serde(default)]
+    make: MakeConfig,
+    #[serde(default)]
+    ssh_agent: SshAgentConfig,
+    #[serde(default)]
+    git_credential: GitCredentialConfig,
+    keymap: Option<KeymapConfig>,
+    theme: Option<ThemeConfig>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct MakeConfig {
+    length: Option<usize>,
+    policy: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct SshAgentConfig {
+    socket: Option<String>,
+    approve: Option<String>,
+    allow: Option<Vec<String>>,
+    timeout: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct GitCredentialConfig {
+    store: Option<bool>,
+}
+
+/// where the config and the database are, from `--config`/`--db` before the command
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub config: Option<String>,
+    pub db: Option<String>,
+}
+
+impl Options {
+    /// take `--config <path>` and `--db <path>`, also written as `--db=<path>`,
+    /// from the front of `args` and return the rest
+    pub fn parse(args: &[String]) -> Result<(Options, &[String]), String> {
+        let mut options = Options::default();
+        let mut rest = args;
+        while let Some((arg, tail)) = rest.split_first() {
+            let (flag, value, tail) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, value.to_string(), tail),
+                None => match tail.split_first() {
+                    Some((value, tail)) => (arg.as_str(), value.clone(), tail),
+                    None => (arg.as_str(), String::new(), tail),
+                },
+            };
+            let target = match flag {
+                "--config" => &mut options.config,
+                "--db" => &mut options.db,
+                _ => break,
+            };
+            if value.is_empty() {
+                return Err(format!("{} needs a path", flag));
+            }
+            *target = Some(value);
+            rest = tail;
+        }
+        Ok((options, rest))
+    }
+}
+
+/// `$XDG_CONFIG_HOME`, `~/.config` when it isn't set
+pub fn config_home() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME").or_else(|| dirs::home_dir().map(|home| home.join(".config")))
+}
+
+/// `$XDG_DATA_HOME`, `~/.local/share` when it isn't set
+fn data_home() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME").or_else(|| dirs::home_dir().map(|home| home.join(".local").join("share")))
+}
+
+/// `$XDG_DATA_HOME/secm`, where the database and the other vaults of `:vault` are kept
+pub fn vault_dir() -> Result<PathBuf, String> {
+    let dir = data_home().ok_or("Unable to determine home directory")?.join("secm");
+    fs::create_dir_all(&dir).map_err(|e| format!("Unable to create {}: {}", dir.display(), e))?;
+    Ok(dir)
+}
+
+/// the spec says relative paths are invalid and must be ignored
+fn xdg_dir(var: &str) -> Option<PathBuf> {
+    env::var_os(var).map(PathBuf::from).filter(|path| path.is_absolute())
+}
+
+/// `~/` at the start of a path from the config or the command line
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// the database of versions that kept it in the home directory
+fn legacy_db() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".secrets.db"))
+}
+
+/// move the old `~/.secrets.db` to `db`, a notice for the user when it was moved
+fn migrate_legacy_db(db: &Path) -> Result<Option<String>, String> {
+    let Some(legacy) = legacy_db().filter(|legacy| legacy.exists()) else {
+        return Ok(None);
+    };
+    if db.exists() {
+        return Ok(None);
+    }
+    // rename fails across file systems, copy and remove instead
+    if fs::rename(&legacy, db).is_err() {
+        fs::copy(&legacy, db).map_err(|e| format!("Unable to move {} to {}: {}", legacy.display(), db.display(), e))?;
+        fs::remove_file(&legacy).map_err(|e| format!("Unable to remove {}: {}", legacy.display(), e))?;
+    }
+    Ok(Some(format!("Moved {} to {}", legacy.display(), db.display())))
+}
+
+impl Config {
+    /// `--config`, then `$SECM_CONFIG`, then `$XDG_CONFIG_HOME/secm/config.toml`
+    pub fn path(options: &Options) -> Option<PathBuf> {
+        options
+            .config
+            .clone()
+            .or_else(|| env::var("SECM_CONFIG").ok().filter(|path| !path.is_empty()))
+            .map(|path| expand_home(&path))
+            .or_else(|| config_home().map(|dir| dir.join("secm").join("config.toml")))
+    }
+
+    /// the config file, the defaults when there is none. A config file named
+    /// by `--config` or `$SECM_CONFIG` has to exist.
+    pub fn load(options: &Options) -> Result<Config, String> {
+        let named = options.config.is_some() || env::var_os("SECM_CONFIG").is_some_and(|path| !path.is_empty());
+        let Some(path) = Config::path(options).filter(|path| named || path.exists()) else {
+            return Ok(Config::default());
+        };
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        Config::parse(&content).map_err(|e| format!("Invalid config {}: {}", path.display(), e))
+    }
+
+    pub fn parse(content: &str) -> Result<Config, String> {
+        toml::from_str(content).map_err(|e| e.to_string())
+    }
+
+    /// `--db`, then `$SECM_DB`, then `db` in the config, then `$XDG_DATA_HOME/secm/secrets.db`.
+    /// The default location is created, and takes over `~/.secrets.db` of older versions
+    /// with a notice about it.
+    pub fn db_path(&self, options: &Options) -> Result<(PathBuf, Option<String>), String> {
+        let chosen = options
+            .db
+            .clone()
+            .or_else(|| env::var("SECM_DB").ok().filter(|path| !path.is_empty()))
+            .or_else(|| self.db.clone());
+        if let Some(path) = chosen {
+            return Ok((expand_home(&path), None));
+        }
+
+        let db = vault_dir()?.join("secrets.db");
+        let notice = migrate_legacy_db(&db)?;
+        Ok((db, notice))
+    }
+
+    /// how long errors and notices stay in the guide line
+    pub fn message_timeout(&self) -> Duration {
+        Duration::from_secs(self.message_timeout.unwrap_or(3))
+    }
+
+    /// the length of generated secrets when none is given
+    pub fn make_length(&self) -> usize {
+        self.make.length.unwrap_or(10)
+    }
+
+    /// what generated secrets are made of, see `generator::Policy`
+    pub fn make_policy(&self) -> &str {
+        self.make.policy.as_deref().unwrap_or(DEFAULT_POLICY)
+    }
+
+    /// the largest file that can be attached to a secret, `max_attachment_size` is in MiB
+    pub fn max_attachment_size(&self) -> u64 {
+        self.max_attachment_size.unwrap_or(16).saturating_mul(attachment::MIB)
+    }
+
+    /// where `secm ssh-agent` listens, `$XDG_RUNTIME_DIR/secm/agent.sock` by default
+    pub fn ssh_agent_socket(&self) -> Result<PathBuf, String> {
+        if let Some(socket) = &self.ssh_agent.socket {
+            return Ok(expand_home(socket));
+        }
+        let dir = xdg_dir("XDG_RUNTIME_DIR").or_else(data_home).ok_or("Unable to determine home directory")?;
+        Ok(dir.join("secm").join("agent.sock"))
+    }
+
+    /// which signatures `secm ssh-agent` makes without asking: with `approve = "ask"`,
+    /// the default, only those of keys in `allow`, with `approve = "allow"` all of them
+    pub fn ssh_agent_policy(&self) -> Result<ssh::Policy, String> {
+        let ask = match self.ssh_agent.approve.as_deref().unwrap_or("ask") {
+            "ask" => true,
+            "allow" => false,
+            other => return Err(format!("Invalid approve '{}' in [ssh_agent], expected ask or allow", other)),
+        };
+        Ok(ssh::Policy {
+            ask,
+            allow: self.ssh_agent.allow.clone().unwrap_or_default(),
+            timeout: Duration::from_secs(self.ssh_agent.timeout.unwrap_or(30)),
+        })
+    }
+
+    /// whether `secm git-credential` keeps and erases what git hands it, after asking,
+    /// off by default so git only ever reads
+    pub fn git_credential_store(&self) -> bool {
+        self.git_credential.store.unwrap_or(false)
+    }
+
+    /// the checks new secrets have to pass, `min_score` and `hibp`
+    pub fn check_secret(&self, value: &str) -> Result<(), String> {
+        self.check_strength(value)?;
+        self.check_breached(value)
+    }
+
+    /// the local Have I Been Pwned dataset, see `breach::Dataset`
+    pub fn hibp(&self) -> Option<PathBuf> {
+        self.hibp.as_deref().map(expand_home)
+    }
+
+    /// refuse secrets found in the `hibp` dataset, when it is set
+    pub fn check_breached(&self, value: &str) -> Result<(), String> {
+        let Some(path) = self.hibp() else {
+            return Ok(());
+        };
+        match Dataset::open(&path)?.count(value)? {
+            0 => Ok(()),
+            count => Err(format!("Secret was found {} times in breaches, pick another", count)),
+        }
+    }
+
+    /// refuse secrets below `min_score`, 0 very weak to 4 very strong, when it is set
+    pub fn check_strength(&self, value: &str) -> Result<(), String> {
+        let Some(min_score) = self.min_score.filter(|min_score| *min_score > 0) else {
+            return Ok(());
+        };
+        let estimate = strength::estimate(value);
+        if estimate.score >= min_score {
+            return Ok(());
+        }
+        let min_label = LABELS[(min_score as usize).min(LABELS.len() - 1)];
+        Err(format!("Secret is {}, the config asks for at least {}", estimate.label(), min_label))
+    }
+
+    /// the `[keymap]` section, or the older `keymap.toml` when there is none
+    pub fn keymap(&mut self) -> Result<Keymap, String> {
+        match self.keymap.take() {
+            Some(keymap) => Keymap::from_config(keymap).map_err(|e| format!("Invalid [keymap] in config: {}", e)),
+            None => Keymap::load(),
+        }
+    }
+
+    /// the `[theme]` section, or the older `theme.toml` when there is none
+    pub fn theme(&mut self) -> Result<Theme, String> {
+        match self.theme.take() {
+            Some(theme) => Theme::from_config(theme).map_err(|e| format!("Invalid [theme] in config: {}", e)),
+            None => Theme::load(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let all = args(&["--db", "/tmp/a.db", "--config=~/secm.toml", "ls", "--db", "x"]);
+        let (options, rest) = Options::parse(&all).unwrap();
+        assert_eq!(options.db.as_deref(), Some("/tmp/a.db"));
+        assert_eq!(options.config.as_deref(), Some("~/secm.toml"));
+        assert_eq!(rest, &all[3..]);
+
+        let (options, rest) = Options::parse(&all[3..]).unwrap();
+        assert_eq!(options, Options::default());
+        assert_eq!(rest.len(), 3);
+        assert!(Options::parse(&args(&["--db"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse("message_timeout = 5\n[make]\nlength = 20\n[keymap]\npreset = \"emacs\"\n").unwrap();
+        assert_eq!(config.message_timeout(), Duration::from_secs(5));
+        assert_eq!(config.make_length(), 20);
+        assert_eq!(config.make_policy(), DEFAULT_POLICY);
+        assert!(config.keymap.is_some());
+        assert!(Config::parse("colour = \"red\"\n").is_err());
+
+        let config = Config::parse("min_score = 3\n").unwrap();
+        assert!(config.check_strength("password123").is_err());
+        assert!(config.check_strength("hK8#qZ2!vT9@wL4$").is_ok());
+
+        let config = Config::default();
+        assert_eq!(config.message_timeout(), Duration::from_secs(3));
+        assert_eq!(config.make_length(), 10);
+    }
+}
@@ -0,0 +1,195 @@
+use std::io::BufRead;
+
+use crate::model::RecordKind;
+use crate::Storage;
+
+/// what git asks a credential helper about or hands it to keep, see gitcredentials(7)
+#[derive(Debug, Default, PartialEq)]
+pub struct Credential {
+    pub protocol: String,
+    pub host: String,
+    pub path: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// a login record that answers a credential request, its password is empty once git rejected it
+#[derive(Debug, PartialEq)]
+pub struct Login {
+    pub name: String,
+    pub username: Option<String>,
+    pub password: String,
+}
+
+impl Credential {
+    /// the `key=value` lines git writes, up to an empty line or the end of the input.
+    /// Keys secm has no use for, like `capability[]` or `wwwauth[]`, are skipped.
+    pub fn read(input: impl BufRead) -> Result<Credential, String> {
+        let mut credential = Credential::default();
+        for line in input.lines() {
+            let line = line.map_err(|e| format!("Unable to read credential: {}", e))?;
+            if line.is_empty() {
+                break;
+            }
+            let (key, value) = line.split_once('=').ok_or(format!("Invalid credential line '{}'", line))?;
+            match key {
+                "protocol" => credential.protocol = value.to_string(),
+                "host" => credential.host = value.to_string(),
+                "path" => credential.path = value.to_string(),
+                "username" => credential.username = Some(value.to_string()),
+                "password" => credential.password = Some(value.to_string()),
+                "url" => {
+                    let (protocol, host, path) = split_url(value);
+                    credential.protocol = protocol.unwrap_or_default().to_string();
+                    credential.host = host.to_string();
+                    credential.path = path.to_string();
+                }
+                _ => {}
+            }
+        }
+        if credential.protocol.is_empty() {
+            return Err("git sent no protocol".to_string());
+        }
+        Ok(credential)
+    }
+
+    /// `protocol://host/path`, the url a login saved for this credential gets
+    pub fn url(&self) -> String {
+        match trim_path(&self.path) {
+            "" => format!("{}://{}", self.protocol, self.host),
+            path => format!("{}://{}/{}", self.protocol, self.host, path),
+        }
+    }
+
+    /// `git/host` or `git/host/path`, where a new login for this credential is saved
+    pub fn secret_name(&self) -> String {
+        match trim_path(&self.path) {
+            "" => format!("git/{}", self.host),
+            path => format!("git/{}/{}", self.host, path),
+        }
+    }
+}
+
+/// the login whose `urls` match `credential` best: same protocol and host, a path the
+/// path of the request is in, and the username git asks for. The longest path wins.
+pub fn find<S: Storage>(storage: &S, credential: &Credential) -> Result<Option<Login>, String> {
+    let mut best: Option<(usize, String, Option<String>)> = None;
+    for (name, record) in storage.get_records()? {
+        if record.kind != RecordKind::Login {
+            continue;
+        }
+        let username = record.fields.get("username").cloned();
+        if credential.username.is_some() && username.is_some() && credential.username != username {
+            continue;
+        }
+        let urls = record.fields.get("urls").map(String::as_str).unwrap_or_default();
+        let score = urls
+            .split(|ch: char| ch == ',' || ch.is_whitespace())
+            .filter_map(|url| matches(url, credential))
+            .max();
+        if let Some(score) = score {
+            if best.as_ref().is_none_or(|(best, _, _)| score > *best) {
+                best = Some((score, name, username));
+            }
+        }
+    }
+    let Some((_, name, username)) = best else {
+        return Ok(None);
+    };
+    let password = storage.read(&name)?.ok_or(format!("secret '{}' does not exist", name))?;
+    Ok(Some(Login { name, username, password }))
+}
+
+/// how well `url` matches, the length of its path, `None` when it doesn't
+fn matches(url: &str, credential: &Credential) -> Option<usize> {
+    if url.is_empty() {
+        return None;
+    }
+    let (protocol, host, path) = split_url(url);
+    let protocol = match protocol {
+        Some(protocol) => protocol.eq_ignore_ascii_case(&credential.protocol),
+        // never hand a password out over plain http because a url left the protocol out
+        None => ENCRYPTED.iter().any(|protocol| protocol.eq_ignore_ascii_case(&credential.protocol)),
+    };
+    if !protocol || !host.eq_ignore_ascii_case(&credential.host) {
+        return None;
+    }
+    let path = trim_path(path);
+    let requested = trim_path(&credential.path);
+    let inside = path.is_empty()
+        || requested == path
+        || requested.strip_prefix(path).is_some_and(|rest| rest.starts_with('/'));
+    inside.then_some(path.len())
+}
+
+/// the protocols urls without one like `github.com/org` match
+const ENCRYPTED: [&str; 2] = ["https", "ssh"];
+
+/// protocol, host with port and path of `https://host:port/path`
+fn split_url(url: &str) -> (Option<&str>, &str, &str) {
+    let (protocol, rest) = match url.split_once("://") {
+        Some((protocol, rest)) => (Some(protocol), rest),
+        None => (None, url),
+    };
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    // user@host as in https://octocat@github.com
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    (protocol, host, path)
+}
+
+/// `org/repo` for `/org/repo.git/`, repositories are named with and without `.git`
+fn trim_path(path: &str) -> &str {
+    let path = path.trim_matches('/');
+    path.strip_suffix(".git").unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Record;
+    use crate::storage::SqliteStorage;
+
+    fn login(storage: &SqliteStorage, name: &str, password: &str, username: &str, urls: &str) {
+        let mut record = Record::new(RecordKind::Login);
+        record.set("username", username).unwrap();
+        record.set("urls", urls).unwrap();
+        storage.write(name, password).unwrap();
+        storage.set_record(name, Some(&record)).unwrap();
+    }
+
+    #[test]
+    fn test_find() {
+        let storage = SqliteStorage::new(":memory:").unwrap();
+        login(&storage, "github", "ghp_any", "octocat", "https://github.com");
+        login(&storage, "work/github", "ghp_work", "octocat", "https://github.com/acme, github.com/acme-labs");
+        login(&storage, "gitlab", "glpat", "dev", "https://gitlab.example.com:8443/");
+        storage.write("plain", "not a login").unwrap();
+
+        let input = "protocol=https\nhost=github.com\npath=acme/api.git\ncapability[]=authtype\n\nignored=1\n";
+        let credential = Credential::read(input.as_bytes()).unwrap();
+        assert_eq!(credential.url(), "https://github.com/acme/api");
+        assert_eq!(credential.secret_name(), "git/github.com/acme/api");
+        let found = find(&storage, &credential).unwrap().unwrap();
+        assert_eq!((found.name.as_str(), found.password.as_str()), ("work/github", "ghp_work"));
+        assert_eq!(found.username.as_deref(), Some("octocat"));
+
+        let find_url = |url: &str| {
+            let credential = Credential::read(format!("url={}\n", url).as_bytes()).unwrap();
+            find(&storage, &credential).unwrap().map(|login| login.name)
+        };
+        assert_eq!(find_url("https://github.com/acme-labs/x").as_deref(), Some("work/github"));
+        assert_eq!(find_url("https://github.com/acmecorp/x").as_deref(), Some("github"));
+        assert_eq!(find_url("https://octocat@github.com").as_deref(), Some("github"));
+        assert_eq!(find_url("https://gitlab.example.com:8443/group/repo").as_deref(), Some("gitlab"));
+        assert_eq!(find_url("http://github.com/acme/api"), None);
+        // github.com/acme-labs has no protocol, that is https or ssh but never http
+        assert_eq!(find_url("http://github.com/acme-labs/x"), None);
+        assert_eq!(find_url("ssh://github.com/acme-labs/x").as_deref(), Some("work/github"));
+        assert_eq!(find_url("https://gitlab.example.com/group/repo"), None);
+
+        // a username git asks for has to be the one of the login
+        let other = Credential { username: Some("hubot".to_string()), ..credential };
+        assert_eq!(find(&storage, &other).unwrap(), None);
+        assert!(Credential::read("host=github.com\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,379 @@
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGIT: &str = "0123456789";
+const SYMBOL: &str = "!@#$%^&*-_=+;:,./?";
+
+/// characters that are easily mistaken for each other when a password is read or typed
+const AMBIGUOUS: &str = "0O1lI|";
+
+/// the policy of the make popup when the config has none: letters only
+pub const DEFAULT_POLICY: &str = "lu";
+
+/// every class, what `--symbols` and `-a` ask for
+pub const STRONG_POLICY: &str = "luds";
+
+/// the words passphrases are made of, one per line. The diceware format of
+/// lines like `11111<tab>abacus` works as well, only the last column is used.
+const WORDLIST: &str = include_str!("wordlists/bip39_english.txt");
+
+/// the passphrase options of the make popup: words joined by `-`, lower case
+pub const DEFAULT_PASSPHRASE: &str = "sep:-";
+pub const DEFAULT_WORDS: usize = 6;
+
+/// the kinds of secrets the make popup can generate
+pub const KINDS: [&str; 2] = ["chars", "words"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CharClass {
+    Lower,
+    Upper,
+    Digit,
+    Symbol,
+}
+
+impl CharClass {
+    const ALL: [CharClass; 4] = [CharClass::Lower, CharClass::Upper, CharClass::Digit, CharClass::Symbol];
+
+    /// the letter of the class in a policy, `l`, `u`, `d` or `s`
+    fn letter(&self) -> char {
+        match self {
+            CharClass::Lower => 'l',
+            CharClass::Upper => 'u',
+            CharClass::Digit => 'd',
+            CharClass::Symbol => 's',
+        }
+    }
+
+    fn chars(&self) -> &'static str {
+        match self {
+            CharClass::Lower => LOWER,
+            CharClass::Upper => UPPER,
+            CharClass::Digit => DIGIT,
+            CharClass::Symbol => SYMBOL,
+        }
+    }
+}
+
+/// what a generated secret is made of, written like `lud2s -ambiguous forbid:<>`:
+///
+/// - `l`, `u`, `d` and `s` pick lower case, upper case, digits and symbols, a number
+///   after a letter is the least count of that class, e.g. `d2`, otherwise it is 1
+/// - `-ambiguous` leaves out characters like `0`, `O`, `l` and `1`
+/// - `alphabet:<chars>` picks from the given characters instead of classes
+/// - `forbid:<chars>` never uses the given characters, for sites that reject them
+#[derive(Clone, Debug, PartialEq)]
+pub struct Policy {
+    pub length: usize,
+    pub classes: Vec<(CharClass, usize)>,
+    pub alphabet: Option<String>,
+    pub forbidden: String,
+    pub no_ambiguous: bool,
+}
+
+impl Policy {
+    /// letters only, for names and keys that aren't shown to anyone
+    pub fn letters(length: usize) -> Policy {
+        Policy::parse("l0u0", length).unwrap()
+    }
+
+    pub fn parse(spec: &str, length: usize) -> Result<Policy, String> {
+        let mut policy = Policy { length, classes: vec![], alphabet: None, forbidden: String::new(), no_ambiguous: false };
+        for token in spec.split_whitespace() {
+            if token == "-ambiguous" {
+                policy.no_ambiguous = true;
+            } else if let Some(chars) = token.strip_prefix("alphabet:") {
+                policy.alphabet = Some(chars.to_string());
+            } else if let Some(chars) = token.strip_prefix("forbid:") {
+                policy.forbidden.push_str(chars);
+            } else {
+                policy.parse_classes(token)?;
+            }
+        }
+        if policy.classes.is_empty() && policy.alphabet.is_none() {
+            return Err("Policy needs character classes like `lud` or an alphabet:".to_string());
+        }
+        Ok(policy)
+    }
+
+    /// class letters with optional least counts, e.g. `lud2s`
+    fn parse_classes(&mut self, token: &str) -> Result<(), String> {
+        let mut chars = token.chars().peekable();
+        while let Some(letter) = chars.next() {
+            let class = CharClass::ALL
+                .into_iter()
+                .find(|class| class.letter() == letter)
+                .ok_or(format!("Unknown character class '{}', expected l, u, d or s", letter))?;
+            let mut count = String::new();
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                count.push(digit);
+            }
+            let min = if count.is_empty() { 1 } else { count.parse().map_err(|_| format!("Invalid count '{}'", count))? };
+            self.classes.retain(|(other, _)| *other != class);
+            self.classes.push((class, min));
+        }
+        Ok(())
+    }
+
+    fn excluded(&self, ch: char) -> bool {
+        self.forbidden.contains(ch) || (self.no_ambiguous && AMBIGUOUS.contains(ch))
+    }
+
+    fn pool(&self, chars: &str) -> Vec<char> {
+        let mut pool: Vec<char> = chars.chars().filter(|ch| !self.excluded(*ch)).collect();
+        pool.sort_unstable();
+        pool.dedup();
+        pool
+    }
+
+    /// each class with its least count and what is left of it after the exclusions
+    fn pools(&self) -> Vec<(usize, Vec<char>)> {
+        match &self.alphabet {
+            Some(alphabet) => vec![(0, self.pool(alphabet))],
+            None => self.classes.iter().map(|(class, min)| (*min, self.pool(class.chars()))).collect(),
+        }
+    }
+
+    /// a random secret from the operating system's CSPRNG, the least counts first,
+    /// the rest from all allowed characters, shuffled
+    pub fn generate(&self) -> Result<String, String> {
+        let pools = self.pools();
+        let required: usize = pools.iter().map(|(min, _)| min).sum();
+        if self.length == 0 {
+            return Err("Length must be at least 1".to_string());
+        }
+        if required > self.length {
+            return Err(format!("Length must be at least {} for this policy", required));
+        }
+        if pools.iter().any(|(min, pool)| *min > 0 && pool.is_empty()) {
+            return Err("Policy excludes every character of a required class".to_string());
+        }
+        let all: Vec<char> = pools.iter().flat_map(|(_, pool)| pool.iter().copied()).collect();
+        if all.is_empty() {
+            return Err("Policy excludes every character".to_string());
+        }
+
+        let mut chars: Vec<char> = pools
+            .iter()
+            .flat_map(|(min, pool)| (0..*min).map(|_| *pool.choose(&mut OsRng).unwrap()))
+            .collect();
+        while chars.len() < self.length {
+            chars.push(*all.choose(&mut OsRng).unwrap());
+        }
+        chars.shuffle(&mut OsRng);
+        Ok(chars.into_iter().collect())
+    }
+
+    /// about how many bits of randomness a secret has, the least counts lower it a little
+    pub fn entropy_bits(&self) -> f64 {
+        let size: usize = self.pools().iter().map(|(_, pool)| pool.len()).sum();
+        self.length as f64 * (size.max(1) as f64).log2()
+    }
+}
+
+pub(crate) fn words() -> Vec<&'static str> {
+    WORDLIST.lines().filter_map(|line| line.split_whitespace().last()).collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Case {
+    Lower,
+    Title,
+    Upper,
+    /// title case for a random half of the words, one more bit each
+    Random,
+}
+
+/// a diceware style passphrase, written like `sep:. case:title digit symbol`:
+///
+/// - `sep:<chars>` joins the words, `sep:space` with a space, `sep:` without anything
+/// - `case:lower`, `case:title`, `case:upper` or `case:random` for the words
+/// - `digit` and `symbol` add one of them to the end of a random word
+#[derive(Clone, Debug, PartialEq)]
+pub struct Passphrase {
+    pub words: usize,
+    pub separator: String,
+    pub case: Case,
+    pub digit: bool,
+    pub symbol: bool,
+}
+
+impl Passphrase {
+    pub fn parse(spec: &str, words: usize) -> Result<Passphrase, String> {
+        let mut passphrase = Passphrase { words, separator: "-".to_string(), case: Case::Lower, digit: false, symbol: false };
+        for token in spec.split_whitespace() {
+            match token.split_once(':') {
+                Some(("sep", "space")) => passphrase.separator = " ".to_string(),
+                Some(("sep", separator)) => passphrase.separator = separator.to_string(),
+                Some(("case", case)) => {
+                    passphrase.case = match case {
+                        "lower" => Case::Lower,
+                        "title" => Case::Title,
+                        "upper" => Case::Upper,
+                        "random" => Case::Random,
+                        _ => return Err(format!("Unknown case '{}', expected lower, title, upper or random", case)),
+                    }
+                }
+                None if token == "digit" => passphrase.digit = true,
+                None if token == "symbol" => passphrase.symbol = true,
+                _ => return Err(format!("Unknown passphrase option '{}'", token)),
+            }
+        }
+        Ok(passphrase)
+    }
+
+    pub fn generate(&self) -> Result<String, String> {
+        if self.words == 0 {
+            return Err("Passphrase needs at least 1 word".to_string());
+        }
+        let list = words();
+        let mut words: Vec<String> = (0..self.words)
+            .map(|_| {
+                let word = *list.choose(&mut OsRng).unwrap();
+                let title = || word[..1].to_uppercase() + &word[1..];
+                match self.case {
+                    Case::Lower => word.to_string(),
+                    Case::Title => title(),
+                    Case::Upper => word.to_uppercase(),
+                    Case::Random if OsRng.gen_bool(0.5) => title(),
+                    Case::Random => word.to_string(),
+                }
+            })
+            .collect();
+        if self.digit {
+            let i = OsRng.gen_range(0..words.len());
+            words[i].push(*DIGIT.as_bytes().choose(&mut OsRng).unwrap() as char);
+        }
+        if self.symbol {
+            let i = OsRng.gen_range(0..words.len());
+            words[i].push(*SYMBOL.as_bytes().choose(&mut OsRng).unwrap() as char);
+        }
+        Ok(words.join(&self.separator))
+    }
+
+    /// bits of randomness: the words, the random case and which word got the digit or symbol
+    pub fn entropy_bits(&self) -> f64 {
+        let words = self.words as f64;
+        let mut bits = words * (self.list_len() as f64).log2();
+        if self.case == Case::Random {
+            bits += words;
+        }
+        if self.digit {
+            bits += (DIGIT.len() as f64 * words).log2();
+        }
+        if self.symbol {
+            bits += (SYMBOL.len() as f64 * words).log2();
+        }
+        bits
+    }
+
+    fn list_len(&self) -> usize {
+        words().len()
+    }
+}
+
+/// what the make popup, `secm make`, `secm gen` and `:gen` generate
+pub enum Generator {
+    Chars(Policy),
+    Words(Passphrase),
+}
+
+impl Generator {
+    /// `kind` is `chars` or `words`, `length` counts characters or words
+    pub fn parse(kind: &str, spec: &str, length: usize) -> Result<Generator, String> {
+        match kind.trim() {
+            "chars" => Ok(Generator::Chars(Policy::parse(spec, length)?)),
+            "words" => Ok(Generator::Words(Passphrase::parse(spec, length)?)),
+            kind => Err(format!("Unknown kind '{}', expected {}", kind, KINDS.join(" or "))),
+        }
+    }
+
+    pub fn generate(&self) -> Result<String, String> {
+        match self {
+            Generator::Chars(policy) => policy.generate(),
+            Generator::Words(passphrase) => passphrase.generate(),
+        }
+    }
+
+    pub fn entropy_bits(&self) -> f64 {
+        match self {
+            Generator::Chars(policy) => policy.entropy_bits(),
+            Generator::Words(passphrase) => passphrase.entropy_bits(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let policy = Policy::parse("lud2s -ambiguous forbid:<>", 16).unwrap();
+        assert_eq!(
+            policy.classes,
+            vec![(CharClass::Lower, 1), (CharClass::Upper, 1), (CharClass::Digit, 2), (CharClass::Symbol, 1)]
+        );
+        assert!(policy.no_ambiguous);
+        assert_eq!(policy.forbidden, "<>");
+        assert!(Policy::parse("lux", 8).is_err());
+        assert!(Policy::parse("-ambiguous", 8).is_err());
+    }
+
+    #[test]
+    fn test_generate() {
+        let policy = Policy::parse("ud3s2 -ambiguous forbid:!@#$%^&*-_=+;:,.", 12).unwrap();
+        for _ in 0..50 {
+            let secret = policy.generate().unwrap();
+            assert_eq!(secret.chars().count(), 12);
+            assert!(secret.chars().filter(char::is_ascii_digit).count() >= 3);
+            assert_eq!(secret.chars().filter(|ch| "/?".contains(*ch)).count(), secret.chars().filter(|ch| !ch.is_ascii_alphanumeric()).count());
+            assert!(secret.chars().filter(|ch| "/?".contains(*ch)).count() >= 2);
+            assert!(!secret.chars().any(|ch| AMBIGUOUS.contains(ch) || ch.is_ascii_lowercase()));
+        }
+
+        // every symbol can show up, not only the first ten
+        let symbols = Policy::parse("s", 2000).unwrap().generate().unwrap();
+        assert!(SYMBOL.chars().all(|ch| symbols.contains(ch)));
+
+        let alphabet = Policy::parse("alphabet:ab forbid:b", 5).unwrap().generate().unwrap();
+        assert_eq!(alphabet, "aaaaa");
+
+        assert!(Policy::parse("luds", 3).unwrap().generate().is_err());
+        assert!(Policy::parse("d forbid:0123456789", 4).unwrap().generate().is_err());
+        assert!(Policy::letters(0).generate().is_err());
+        assert_eq!(Policy::letters(1).generate().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_wordlist() {
+        let list = words();
+        let mut unique = list.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), list.len());
+        assert_eq!(list.len(), 2048);
+        assert!(list.iter().all(|word| !word.is_empty() && word.chars().all(|ch| ch.is_ascii_lowercase())));
+    }
+
+    #[test]
+    fn test_passphrase() {
+        let passphrase = Passphrase::parse("sep:space case:title digit", 5).unwrap();
+        let phrase = passphrase.generate().unwrap();
+        let words: Vec<&str> = phrase.split(' ').collect();
+        assert_eq!(words.len(), 5);
+        assert!(words.iter().all(|word| word.starts_with(|ch: char| ch.is_ascii_uppercase())));
+        assert_eq!(phrase.chars().filter(char::is_ascii_digit).count(), 1);
+        // 5 words of 11 bits each, one of 10 digits in one of 5 words
+        assert!((passphrase.entropy_bits() - 60.64).abs() < 0.01);
+
+        assert!(Passphrase::parse("case:camel", 5).is_err());
+        assert!(Passphrase::parse("", 0).unwrap().generate().is_err());
+        let generator = Generator::parse("words", "sep:", 4).unwrap();
+        assert!(generator.generate().unwrap().chars().all(|ch| ch.is_ascii_lowercase()));
+        assert!((Generator::parse("chars", "d", 4).unwrap().entropy_bits() - 4.0 * 10f64.log2()).abs() < 0.01);
+    }
+}
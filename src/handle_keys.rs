@@ -1,34 +1,72 @@
 use std::time::Instant;
 
-use ratatui::crossterm::event::{KeyCode, KeyEvent};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
-use crate::app::{App, AppErr, Mode};
+use crate::app::{App, AppErr, Bulk, Mode, EXPORT_FILE};
+use crate::input::Input;
+use crate::keymap::Action;
 use crate::panel::PanelName;
-use crate::{utils, Storage};
+use crate::generator::Generator;
+use crate::Storage;
 
-pub fn handle_key_in_filter_mode<S: Storage>(app: &mut App<S>, key: KeyEvent) {
-    let panel = app.get_panel(PanelName::Filter);
+/// the char to insert into an input field, keys with ctrl or alt are never typed
+fn typed_char(key: KeyEvent) -> Option<char> {
     match key.code {
-        KeyCode::Char(ch) => panel.content[0].push(ch),
-        KeyCode::Backspace => _ = panel.content[0].pop(),
-        KeyCode::Esc => app.switch_mode(Mode::Normal),
-        KeyCode::Enter => app.mode = Mode::Normal,
-        KeyCode::Down => app.select_next(),
-        KeyCode::Up => app.select_previous(),
+        KeyCode::Char(ch) if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => Some(ch),
+        _ => None,
+    }
+}
+
+/// the line editing keys shared by every input field, anything unbound is typed
+fn edit_input(input: &mut Input, action: Option<Action>, key: KeyEvent) {
+    match action {
+        Some(Action::DeleteChar) => input.delete_back(),
+        Some(Action::DeleteCharForward) => input.delete_forward(),
+        Some(Action::DeleteWord) => input.delete_word(),
+        Some(Action::DeleteToStart) => input.delete_to_start(),
+        Some(Action::Left) => input.left(),
+        Some(Action::Right) => input.right(),
+        Some(Action::WordLeft) => input.word_left(),
+        Some(Action::WordRight) => input.word_right(),
+        Some(Action::LineStart) => input.home(),
+        Some(Action::LineEnd) => input.end(),
+        Some(Action::NewLine) => input.newline(),
+        Some(Action::Up) => input.up(),
+        Some(Action::Down) => input.down(),
+        None => {
+            if let Some(ch) = typed_char(key) {
+                input.insert(ch)
+            }
+        }
         _ => {}
     }
 }
 
+pub fn handle_key_in_filter_mode<S: Storage>(app: &mut App<S>, key: KeyEvent) {
+    let action = app.keymap.action(&Mode::Filter, key);
+    let panel = app.get_panel(PanelName::Filter);
+    match action {
+        Some(Action::Cancel) => app.switch_mode(Mode::Normal),
+        Some(Action::Confirm) => {
+            // keep the filter applied
+            app.mode = Mode::Normal;
+            app.update_guide();
+        }
+        Some(Action::Down) => app.select_next(),
+        Some(Action::Up) => app.select_previous(),
+        _ => edit_input(&mut panel.content[0], action, key),
+    }
+}
+
 pub fn handle_key_in_make_mode<S: Storage>(app: &mut App<S>, key: KeyEvent) {
+    let action = app.keymap.action(&Mode::Make, key);
     let panel = app.get_panel(PanelName::MakeSecret);
 
-    match key.code {
-        KeyCode::Esc => app.switch_mode(Mode::Normal),
-        KeyCode::Char(ch) => panel.content[panel.index].push(ch),
-        KeyCode::Backspace => _ = panel.content[panel.index].pop(),
-        KeyCode::Tab => panel.index = (panel.index + 1) % 3,
-        KeyCode::Enter => {
-            let length = panel.content[1].trim();
+    match action {
+        Some(Action::Cancel) => app.switch_mode(Mode::Normal),
+        Some(Action::NextField) => panel.index = (panel.index + 1) % 4,
+        Some(Action::Confirm) => {
+            let length = panel.content[2].value().trim();
             let n = match length.parse::<usize>() {
                 Ok(num) => num,
                 Err(_) => {
@@ -37,46 +75,60 @@ pub fn handle_key_in_make_mode<S: Storage>(app: &mut App<S>, key: KeyEvent) {
                 }
             };
 
-            let name = panel.content[0].trim().to_string();
-            let advance = panel.content[2].trim();
-            let value = utils::generate_random_string(n, advance == "yes" || advance == "y");
+            let name = panel.content[0].value().trim().to_string();
+            let res = Generator::parse(panel.content[1].value(), panel.content[3].value(), n)
+                .and_then(|generator| generator.generate())
+                .and_then(|value| app.add_secret(name, value));
 
-            if let Err(err) = app.add_secret(name, value) {
+            if let Err(err) = res {
                 app.error = AppErr{msg: err, error_timer: Some(Instant::now())};
             } else {
                 app.switch_mode(Mode::Normal)
             }
         }
-        _ => {}
+        // the type is a selector, not typed in
+        Some(Action::Left | Action::Right) if panel.index == 1 => app.cycle_make_kind(),
+        None if panel.index == 1 => {
+            if typed_char(key) == Some(' ') {
+                app.cycle_make_kind()
+            }
+        }
+        _ if panel.index == 1 => {}
+        _ => edit_input(&mut panel.content[panel.index], action, key),
     }
 }
 
 pub fn handle_key_in_update_mode<S: Storage>(app: &mut App<S>, key: KeyEvent) {
+    let action = app.keymap.action(&Mode::Update, key);
+    let is_folder = app.get_selected_folder().is_some();
     let panel = app.get_panel(PanelName::UpdateSecret);
-    match key.code {
-        KeyCode::Char(ch) => panel.content[panel.index].push(ch),
-        KeyCode::Backspace => _ = panel.content[panel.index].pop(),
-        KeyCode::Esc => app.switch_mode(Mode::Normal),
-        KeyCode::Enter => {
+    match action {
+        Some(Action::Cancel) => app.switch_mode(Mode::Normal),
+        Some(Action::Confirm) => {
             if let Err(err) = app.update_selected_secret() {
                 app.error = AppErr{msg: err, error_timer: Some(Instant::now())};
             } else {
                 app.switch_mode(Mode::Normal)
             }
         },
-        KeyCode::Tab => panel.index ^= 1,
-        _ => {}
+        // a folder only has the name to edit
+        Some(Action::NextField) if !is_folder => panel.index ^= 1,
+        Some(Action::OpenEditor) if !is_folder => {
+            panel.index = 1;
+            app.open_editor = true;
+        }
+        Some(Action::Reveal) => app.toggle_reveal(),
+        _ => edit_input(&mut panel.content[panel.index], action, key),
     }
 }
 
 pub fn handle_key_in_delete_mode<S: Storage>(app: &mut App<S>, key: KeyEvent){
+    let action = app.keymap.action(&Mode::Delete, key);
     let panel = app.get_panel(PanelName::DeleteSecret);
-    match key.code {
-        KeyCode::Char(ch) => panel.content[0].push(ch),
-        KeyCode::Backspace => _ = panel.content[0].pop(),
-        KeyCode::Esc => app.switch_mode(Mode::Normal),
-        KeyCode::Enter => {
-            if panel.content[0].trim() == "y" {
+    match action {
+        Some(Action::Cancel) => app.switch_mode(Mode::Normal),
+        Some(Action::Confirm) => {
+            if panel.content[0].value().trim() == "y" {
                 if let Err(err) = app.delete_selected_secret() {
                     app.error = AppErr{msg: err, error_timer: Some(Instant::now())};
                 } else {
@@ -86,52 +138,189 @@ pub fn handle_key_in_delete_mode<S: Storage>(app: &mut App<S>, key: KeyEvent){
                 app.switch_mode(Mode::Normal)
             }
         },
-        _ => {}
+        _ => edit_input(&mut panel.content[0], action, key),
+    }
+}
+
+pub fn handle_key_in_confirm_mode<S: Storage>(app: &mut App<S>, key: KeyEvent){
+    let action = app.keymap.action(&Mode::Confirm, key);
+    let panel = app.get_panel(PanelName::Confirm);
+    match action {
+        Some(Action::Cancel) => app.switch_mode(Mode::Normal),
+        Some(Action::Confirm) => {
+            if panel.content[0].value().trim() == "y" {
+                if let Err(err) = app.run_bulk() {
+                    app.error = AppErr{msg: err, error_timer: Some(Instant::now())};
+                } else {
+                    app.switch_mode(Mode::Normal)
+                }
+            } else {
+                app.switch_mode(Mode::Normal)
+            }
+        },
+        _ => edit_input(&mut panel.content[0], action, key),
     }
 }
 
 pub fn handle_key_in_add_mode<S: Storage>(app: &mut App<S>, key: KeyEvent) {
+    let action = app.keymap.action(&Mode::Add, key);
     let panel = app.get_panel(PanelName::AddSecret);
-    match key.code {
-        KeyCode::Char(ch) => panel.content[panel.index].push(ch),
-        KeyCode::Backspace => _ = panel.content[panel.index].pop(),
-        KeyCode::Enter => {
-            let name = panel.content[0].trim().to_string();
-            let value = panel.content[1].trim().to_string();
+    match action {
+        Some(Action::Confirm) => {
+            let name = panel.content[0].value().trim().to_string();
+            // values are kept byte for byte, certificates and keys need their line breaks
+            let value = panel.content[1].value().to_string();
             if let Err(err) = app.add_secret(name, value) {
                 app.error = AppErr{msg: err, error_timer: Some(Instant::now())};
             } else {
                 app.switch_mode(Mode::Normal)
             }
         },
-        KeyCode::Esc => app.switch_mode(Mode::Normal),
-        KeyCode::Tab => panel.index ^= 1,
-        _ => {}
+        Some(Action::Cancel) => app.switch_mode(Mode::Normal),
+        Some(Action::NextField) => panel.index ^= 1,
+        Some(Action::OpenEditor) => {
+            panel.index = 1;
+            app.open_editor = true;
+        }
+        Some(Action::Reveal) => app.toggle_reveal(),
+        _ => edit_input(&mut panel.content[panel.index], action, key),
+    }
+}
+
+pub fn handle_key_in_tag_mode<S: Storage>(app: &mut App<S>, key: KeyEvent) {
+    let action = app.keymap.action(&Mode::Tag, key);
+    let panel = app.get_panel(PanelName::TagSecrets);
+    match action {
+        Some(Action::Cancel) => app.switch_mode(Mode::Normal),
+        Some(Action::Confirm) => {
+            let expr = panel.content[0].value().to_string();
+            app.confirm_bulk(Bulk::Tag(expr));
+        },
+        _ => edit_input(&mut panel.content[0], action, key),
+    }
+}
+
+pub fn handle_key_in_move_mode<S: Storage>(app: &mut App<S>, key: KeyEvent) {
+    let action = app.keymap.action(&Mode::Move, key);
+    let panel = app.get_panel(PanelName::MoveSecrets);
+    match action {
+        Some(Action::Cancel) => app.switch_mode(Mode::Normal),
+        Some(Action::Confirm) => {
+            let folder = panel.content[0].value().to_string();
+            app.confirm_bulk(Bulk::Move(folder));
+        },
+        _ => edit_input(&mut panel.content[0], action, key),
+    }
+}
+
+pub fn handle_key_in_command_mode<S: Storage>(app: &mut App<S>, key: KeyEvent) {
+    let action = app.keymap.action(&Mode::Command, key);
+    let panel = app.get_panel(PanelName::Command);
+    match action {
+        Some(Action::Cancel) => app.switch_mode(Mode::Normal),
+        Some(Action::Confirm) => {
+            let line = panel.content[0].value().to_string();
+            // like vim the command line closes first, errors show in the guide line
+            app.switch_mode(Mode::Normal);
+            if let Err(err) = app.run_command(&line) {
+                app.error = AppErr{msg: err, error_timer: Some(Instant::now())};
+            }
+        },
+        Some(Action::Complete) => app.complete_command(),
+        Some(Action::Up) => app.step_command_history(true),
+        Some(Action::Down) => app.step_command_history(false),
+        _ => edit_input(&mut panel.content[0], action, key),
+    }
+}
+
+/// scroll or close the help overlay, the keys work the same in every mode
+pub fn handle_key_in_help<S: Storage>(app: &mut App<S>, key: KeyEvent) {
+    let scroll = app.help_scroll.unwrap_or(0);
+    app.help_scroll = match key.code {
+        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('?') | KeyCode::F(1) => None,
+        KeyCode::Down | KeyCode::Char('j') => Some(scroll.saturating_add(1)),
+        KeyCode::Up | KeyCode::Char('k') => Some(scroll.saturating_sub(1)),
+        KeyCode::PageDown | KeyCode::Char(' ') => Some(scroll.saturating_add(10)),
+        KeyCode::PageUp => Some(scroll.saturating_sub(10)),
+        KeyCode::Home | KeyCode::Char('g') => Some(0),
+        _ if app.keymap.action(&app.mode, key) == Some(Action::Cancel) => None,
+        _ => Some(scroll),
+    };
+}
+
+/// y or n to a signing request of `secm ssh-agent`, it comes before anything else
+pub fn handle_key_in_approval<S: Storage>(app: &mut App<S>, key: KeyEvent) {
+    let allow = match key.code {
+        KeyCode::Char('y') => true,
+        KeyCode::Char('n') | KeyCode::Esc => false,
+        _ if app.keymap.action(&app.mode, key) == Some(Action::Cancel) => false,
+        _ => return,
+    };
+    if let Err(err) = app.answer_approval(allow) {
+        app.error = AppErr{msg: err, error_timer: Some(Instant::now())};
     }
 }
 
 pub fn handle_key_in_normal_mode<S: Storage>(app: &mut App<S>, key: KeyEvent){
-    match key.code {
-        KeyCode::Char(ch) => {
-            match ch {
-                'q' => app.should_exit = true,
-                'j' => app.select_next(),
-                'k' => app.select_previous(),
-                'r' => app.switch_mode(Mode::Update),
-                'm' => app.switch_mode(Mode::Make),
-                'a' => app.switch_mode(Mode::Add),
-                '/' => app.switch_mode(Mode::Filter),
-                'd' => app.switch_mode(Mode::Delete),
-                _ => {}
+    let Some(action) = app.keymap.action(&Mode::Normal, key) else {
+        return;
+    };
+    let marked = !app.marked.is_empty();
+    match action {
+        Action::Quit => app.should_exit = true,
+        Action::Down => app.select_next(),
+        Action::Up => app.select_previous(),
+        Action::Update if marked => app.switch_mode(Mode::Move),
+        Action::Update => app.switch_mode(Mode::Update),
+        Action::Make => app.switch_mode(Mode::Make),
+        Action::Add => app.switch_mode(Mode::Add),
+        Action::Filter => app.switch_mode(Mode::Filter),
+        Action::Command => app.switch_mode(Mode::Command),
+        Action::Sort => app.cycle_sort(),
+        Action::Favorite => {
+            if let Err(err) = app.toggle_favorite() {
+                app.error = AppErr{msg: err, error_timer: Some(Instant::now())};
             }
         }
-        KeyCode::Down => app.select_next(),
-        KeyCode::Up => app.select_previous(),
-        KeyCode::Esc => app.should_exit = true,
-        KeyCode::Enter => {
-            app.copy_selected_to_clipboard();
-            app.should_exit = true;
+        Action::CopyUsername | Action::CopyPassword => {
+            let field = if action == Action::CopyUsername { "username" } else { "password" };
+            if let Err(err) = app.copy_selected_field(field) {
+                app.error = AppErr{msg: err, error_timer: Some(Instant::now())};
+            }
+        }
+        Action::CopyOtp => {
+            if let Err(err) = app.copy_selected_otp() {
+                app.error = AppErr{msg: err, error_timer: Some(Instant::now())};
+            }
+        }
+        Action::SaveAttachment => {
+            if let Err(err) = app.start_save_attachment() {
+                app.error = AppErr{msg: err, error_timer: Some(Instant::now())};
+            }
+        }
+        Action::Delete if marked => app.confirm_bulk(Bulk::Delete),
+        Action::Delete => app.switch_mode(Mode::Delete),
+        Action::Undo => app.undo(),
+        Action::Redo => app.redo(),
+        Action::Collapse => app.collapse_selected(),
+        Action::Expand => app.expand_selected(),
+        Action::Mark => app.toggle_mark(),
+        Action::MarkRange => app.mark_range(),
+        Action::MarkAll => app.toggle_mark_all(),
+        Action::ClearMarks => app.clear_marks(),
+        Action::Tag if marked => app.switch_mode(Mode::Tag),
+        Action::CopyNames if marked => app.confirm_bulk(Bulk::CopyNames),
+        Action::Export if marked => app.confirm_bulk(Bulk::Export(EXPORT_FILE.to_string())),
+        Action::Copy => {
+            if app.get_selected_folder().is_some() {
+                app.toggle_selected_folder();
+                return;
+            }
+            match app.copy_selected_to_clipboard() {
+                Ok(()) => app.should_exit = true,
+                Err(err) => app.error = AppErr{msg: err, error_timer: Some(Instant::now())},
+            }
         },
         _ => {}
     }
-}
\ No newline at end of file
+}
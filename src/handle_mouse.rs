@@ -0,0 +1,104 @@
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{KeyEvent, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::{Position, Rect};
+
+use crate::app::{App, Mode};
+use crate::keymap::Action;
+use crate::panel::PanelName;
+use crate::Storage;
+
+/// two clicks on the same row within this time copy it, like enter does
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// where things were drawn in the last frame, filled in by `ui::ui`
+#[derive(Default)]
+pub struct ClickAreas {
+    pub list: Rect,
+    pub fields: Vec<Rect>,
+    pub guide: Vec<(Rect, Action)>,
+}
+
+/// the last click on the list, to detect double clicks
+pub struct LastClick {
+    pub row: usize,
+    pub at: Instant,
+}
+
+fn contains(area: Rect, event: &MouseEvent) -> bool {
+    area.contains(Position { x: event.column, y: event.row })
+}
+
+pub fn handle_mouse_in_help<S: Storage>(app: &mut App<S>, event: MouseEvent) {
+    let scroll = app.help_scroll.unwrap_or(0);
+    app.help_scroll = match event.kind {
+        MouseEventKind::ScrollDown => Some(scroll.saturating_add(1)),
+        MouseEventKind::ScrollUp => Some(scroll.saturating_sub(1)),
+        _ => Some(scroll),
+    };
+}
+
+pub fn handle_mouse_event<S: Storage>(app: &mut App<S>, event: MouseEvent) {
+    match event.kind {
+        MouseEventKind::ScrollDown if matches!(app.mode, Mode::Normal | Mode::Filter) => app.select_next(),
+        MouseEventKind::ScrollUp if matches!(app.mode, Mode::Normal | Mode::Filter) => app.select_previous(),
+        MouseEventKind::Down(MouseButton::Left) => {
+            let clicked_action = app.click_areas.guide
+                .iter()
+                .find(|(area, _)| contains(*area, &event))
+                .map(|(_, action)| *action);
+            if let Some(action) = clicked_action {
+                return trigger_action(app, action);
+            }
+            if let Some(index) = app.click_areas.fields.iter().position(|area| contains(*area, &event)) {
+                return focus_field(app, index);
+            }
+            if matches!(app.mode, Mode::Normal | Mode::Filter) && contains(app.click_areas.list, &event) {
+                click_list(app, event);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// run an action as if its first bound key was pressed
+fn trigger_action<S: Storage>(app: &mut App<S>, action: Action) {
+    if let Some(chord) = app.keymap.keys(&app.mode, action).first() {
+        app.handle_key(KeyEvent::new(chord.code, chord.modifiers));
+    }
+}
+
+fn focus_field<S: Storage>(app: &mut App<S>, index: usize) {
+    let panel_name = match app.mode {
+        Mode::Add => PanelName::AddSecret,
+        Mode::Make => PanelName::MakeSecret,
+        Mode::Update => PanelName::UpdateSecret,
+        _ => return,
+    };
+    app.get_panel(panel_name).index = index;
+}
+
+fn click_list<S: Storage>(app: &mut App<S>, event: MouseEvent) {
+    // the first line of the list is its title
+    let list = app.click_areas.list;
+    let Some(line) = event.row.checked_sub(list.y + 1) else {
+        return;
+    };
+    let row = app.secret_list.state.offset() + line as usize;
+    if row >= app.secret_list.rows.len() {
+        return;
+    }
+    app.secret_list.state.select(Some(row));
+
+    let double = app.last_click
+        .as_ref()
+        .is_some_and(|last| last.row == row && last.at.elapsed() <= DOUBLE_CLICK);
+    if double {
+        app.last_click = None;
+        if app.mode == Mode::Normal {
+            trigger_action(app, Action::Copy);
+        }
+    } else {
+        app.last_click = Some(LastClick { row, at: Instant::now() });
+    }
+}
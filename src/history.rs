@@ -0,0 +1,172 @@
+use crate::model::{Record, SealedAttachment};
+use crate::Storage;
+
+/// how many commands `u` can walk back
+const MAX_HISTORY: usize = 100;
+
+/// a single change to the storage that knows how to revert itself
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    Write { name: String, value: String, tags: Vec<String>, record: Option<Record>, favorite: bool, attachments: Vec<SealedAttachment> },
+    Delete { name: String, value: String, tags: Vec<String>, record: Option<Record>, favorite: bool, attachments: Vec<SealedAttachment> },
+    Update { name: String, before: String, after: String },
+    Rename { from: String, to: String },
+    SetTags { name: String, before: Vec<String>, after: Vec<String> },
+    SetFavorite { name: String, before: bool, after: bool },
+    SetRecord { name: String, before: Option<Record>, after: Option<Record> },
+}
+
+impl Op {
+    pub fn inverse(&self) -> Op {
+        match self.clone() {
+            Op::Write { name, value, tags, record, favorite, attachments } => Op::Delete { name, value, tags, record, favorite, attachments },
+            Op::Delete { name, value, tags, record, favorite, attachments } => Op::Write { name, value, tags, record, favorite, attachments },
+            Op::Update { name, before, after } => Op::Update { name, before: after, after: before },
+            Op::Rename { from, to } => Op::Rename { from: to, to: from },
+            Op::SetTags { name, before, after } => Op::SetTags { name, before: after, after: before },
+            Op::SetFavorite { name, before, after } => Op::SetFavorite { name, before: after, after: before },
+            Op::SetRecord { name, before, after } => Op::SetRecord { name, before: after, after: before },
+        }
+    }
+
+    fn apply<S: Storage>(&self, storage: &S) -> Result<(), String> {
+        match self {
+            Op::Write { name, value, tags, record, favorite, attachments } => {
+                storage.write(name, value)?;
+                storage.set_tags(name, tags)?;
+                storage.set_record(name, record.as_ref())?;
+                // pinned again after the other favorites
+                storage.set_favorite(name, *favorite)?;
+                storage.restore_attachments(name, attachments)
+            }
+            Op::Delete { name, .. } => storage.delete(name),
+            Op::Update { name, after, .. } => storage.update(name, after),
+            Op::Rename { from, to } => storage.rename(from, to),
+            Op::SetTags { name, after, .. } => storage.set_tags(name, after),
+            Op::SetFavorite { name, after, .. } => storage.set_favorite(name, *after),
+            Op::SetRecord { name, after, .. } => storage.set_record(name, after.as_ref()),
+        }
+    }
+}
+
+/// a user level operation, e.g. deleting every marked secret
+#[derive(Clone, Debug, PartialEq)]
+pub struct Command {
+    pub description: String,
+    pub ops: Vec<Op>,
+}
+
+impl Command {
+    pub fn new(description: String, ops: Vec<Op>) -> Self {
+        Self { description, ops }
+    }
+
+    fn inverse(&self) -> Command {
+        Command {
+            description: self.description.clone(),
+            ops: self.ops.iter().rev().map(Op::inverse).collect(),
+        }
+    }
+
+    fn apply<S: Storage>(&self, storage: &S) -> Result<(), String> {
+        storage.atomic(&mut |storage| self.ops.iter().try_for_each(|op| op.apply(storage)))
+    }
+}
+
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Command>,
+    redo: Vec<Command>,
+}
+
+impl History {
+    /// remember a command that was just applied, anything undone before can't be redone anymore
+    pub fn record(&mut self, command: Command) {
+        if command.ops.is_empty() {
+            return;
+        }
+        self.undo.push(command);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// revert the last command, returns its description
+    pub fn undo<S: Storage>(&mut self, storage: &S) -> Result<String, String> {
+        let command = self.undo.pop().ok_or("Nothing to undo")?;
+        if let Err(err) = command.inverse().apply(storage) {
+            self.undo.push(command);
+            return Err(err);
+        }
+        let description = command.description.clone();
+        self.redo.push(command);
+        Ok(description)
+    }
+
+    /// apply the last undone command again, returns its description
+    pub fn redo<S: Storage>(&mut self, storage: &S) -> Result<String, String> {
+        let command = self.redo.pop().ok_or("Nothing to redo")?;
+        if let Err(err) = command.apply(storage) {
+            self.redo.push(command);
+            return Err(err);
+        }
+        let description = command.description.clone();
+        self.undo.push(command);
+        Ok(description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SqliteStorage;
+
+    #[test]
+    fn test_undo_redo() {
+        let storage = SqliteStorage::new(":memory:").unwrap();
+        let mut history = History::default();
+
+        storage.write("github", "old").unwrap();
+        history.record(Command::new("add github".to_string(), vec![
+            Op::Write { name: "github".to_string(), value: "old".to_string(), tags: vec![], record: None, favorite: false, attachments: vec![] },
+        ]));
+        storage.rename("github", "git/hub").unwrap();
+        storage.update("git/hub", "new").unwrap();
+        history.record(Command::new("update git/hub".to_string(), vec![
+            Op::Rename { from: "github".to_string(), to: "git/hub".to_string() },
+            Op::Update { name: "git/hub".to_string(), before: "old".to_string(), after: "new".to_string() },
+        ]));
+
+        assert_eq!(history.undo(&storage).unwrap(), "update git/hub");
+        assert_eq!(storage.read("github").unwrap(), Some("old".to_string()));
+        assert_eq!(history.undo(&storage).unwrap(), "add github");
+        assert!(storage.get_all().unwrap().is_empty());
+        assert!(history.undo(&storage).is_err());
+
+        history.redo(&storage).unwrap();
+        history.redo(&storage).unwrap();
+        assert_eq!(storage.read("git/hub").unwrap(), Some("new".to_string()));
+        assert!(history.redo(&storage).is_err());
+
+        // a deleted secret comes back with its files
+        storage.add_attachment("git/hub", "id_rsa", &mut &b"key"[..], 1024).unwrap();
+        let delete = Op::Delete {
+            name: "git/hub".to_string(),
+            value: "new".to_string(),
+            tags: vec![],
+            record: None,
+            favorite: false,
+            attachments: storage.get_sealed_attachments("git/hub").unwrap(),
+        };
+        storage.delete("git/hub").unwrap();
+        // the id of the deleted file isn't given to the next one
+        storage.write("gitlab", "token").unwrap();
+        storage.add_attachment("gitlab", "id_rsa", &mut &b"other"[..], 1024).unwrap();
+        history.record(Command::new("delete git/hub".to_string(), vec![delete]));
+        history.undo(&storage).unwrap();
+        let mut data = vec![];
+        storage.read_attachment("git/hub", "id_rsa", &mut data).unwrap();
+        assert_eq!(data, b"key");
+    }
+}
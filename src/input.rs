@@ -0,0 +1,318 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// a text field with a cursor, used by every input of the TUI
+///
+/// The cursor is a byte offset that always sits between two graphemes, so an
+/// emoji or a letter with combining accents is moved over and deleted as one.
+/// Only a multi-line input keeps line breaks, e.g. for certificates or JSON.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Input {
+    value: String,
+    cursor: usize,
+    multiline: bool,
+}
+
+/// letters and digits make up words, anything else like ` `, `/` or `.` separates them
+fn is_word(grapheme: &str) -> bool {
+    grapheme.chars().any(char::is_alphanumeric)
+}
+
+impl Input {
+    pub fn new(value: &str) -> Input {
+        Input { value: value.to_string(), cursor: value.len(), multiline: false }
+    }
+
+    pub fn multiline() -> Input {
+        Input { multiline: true, ..Input::default() }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// replace the text, the cursor goes to the end
+    pub fn set(&mut self, value: &str) {
+        self.value = value.to_string();
+        self.cursor = value.len();
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.value.split('\n').count()
+    }
+
+    pub fn clear(&mut self) {
+        self.set("");
+    }
+
+    pub fn insert(&mut self, ch: char) {
+        self.value.insert(self.cursor, ch);
+        self.cursor += ch.len_utf8();
+    }
+
+    /// insert pasted text, line breaks can't be part of a single line
+    pub fn insert_str(&mut self, text: &str) {
+        let text = if self.multiline {
+            // terminals paste line breaks as `\r`
+            text.replace("\r\n", "\n").replace('\r', "\n")
+        } else {
+            text.chars().filter(|ch| *ch != '\n' && *ch != '\r').collect()
+        };
+        self.value.insert_str(self.cursor, &text);
+        self.cursor += text.len();
+    }
+
+    pub fn newline(&mut self) {
+        if self.multiline {
+            self.insert('\n');
+        }
+    }
+
+    fn prev_boundary(&self, from: usize) -> usize {
+        self.value[..from].grapheme_indices(true).next_back().map(|(i, _)| i).unwrap_or(0)
+    }
+
+    fn next_boundary(&self, from: usize) -> usize {
+        self.value[from..].graphemes(true).next().map(|g| from + g.len()).unwrap_or(from)
+    }
+
+    /// where the word before the cursor starts, skipping separators first
+    fn word_start(&self) -> usize {
+        let mut graphemes = self.value[..self.cursor].grapheme_indices(true).rev().peekable();
+        while graphemes.next_if(|(_, g)| !is_word(g)).is_some() {}
+        let mut start = graphemes.peek().map(|(i, g)| i + g.len()).unwrap_or(0);
+        while let Some((i, _)) = graphemes.next_if(|(_, g)| is_word(g)) {
+            start = i;
+        }
+        start
+    }
+
+    /// where the word after the cursor ends, skipping separators first
+    fn word_end(&self) -> usize {
+        let mut graphemes = self.value[self.cursor..].graphemes(true).peekable();
+        let mut end = self.cursor;
+        while let Some(g) = graphemes.next_if(|g| !is_word(g)) {
+            end += g.len();
+        }
+        while let Some(g) = graphemes.next_if(|g| is_word(g)) {
+            end += g.len();
+        }
+        end
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.prev_boundary(self.cursor);
+    }
+
+    pub fn right(&mut self) {
+        self.cursor = self.next_boundary(self.cursor);
+    }
+
+    fn line_start(&self) -> usize {
+        self.value[..self.cursor].rfind('\n').map(|i| i + 1).unwrap_or(0)
+    }
+
+    fn line_end(&self) -> usize {
+        self.value[self.cursor..].find('\n').map(|i| self.cursor + i).unwrap_or(self.value.len())
+    }
+
+    /// start of the line the cursor is on
+    pub fn home(&mut self) {
+        self.cursor = self.line_start();
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.line_end();
+    }
+
+    /// move to the same column of the line above, or to the start of the text
+    pub fn up(&mut self) {
+        let start = self.line_start();
+        if start == 0 {
+            self.cursor = 0;
+            return;
+        }
+        let column = self.value[start..self.cursor].width();
+        self.cursor = start - 1;
+        self.cursor = self.line_start();
+        self.move_to_column(column);
+    }
+
+    /// move to the same column of the line below, or to the end of the text
+    pub fn down(&mut self) {
+        let end = self.line_end();
+        if end == self.value.len() {
+            self.cursor = end;
+            return;
+        }
+        let column = self.value[self.line_start()..self.cursor].width();
+        self.cursor = end + 1;
+        self.move_to_column(column);
+    }
+
+    /// from the start of a line, move right until `column` or the end of the line
+    fn move_to_column(&mut self, column: usize) {
+        let end = self.line_end();
+        let mut width = 0;
+        for g in self.value[self.cursor..end].graphemes(true) {
+            if width + g.width() > column {
+                break;
+            }
+            width += g.width();
+            self.cursor += g.len();
+        }
+    }
+
+    pub fn word_left(&mut self) {
+        self.cursor = self.word_start();
+    }
+
+    pub fn word_right(&mut self) {
+        self.cursor = self.word_end();
+    }
+
+    /// backspace
+    pub fn delete_back(&mut self) {
+        let start = self.prev_boundary(self.cursor);
+        self.value.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// delete, the grapheme under the cursor
+    pub fn delete_forward(&mut self) {
+        let end = self.next_boundary(self.cursor);
+        self.value.replace_range(self.cursor..end, "");
+    }
+
+    pub fn delete_word(&mut self) {
+        let start = self.word_start();
+        self.value.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// delete to the start of the line
+    pub fn delete_to_start(&mut self) {
+        let start = self.line_start();
+        self.value.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// the part of the text that fits in `width` columns and the cursor column in it,
+    /// scrolled so the cursor stays visible. `masked` shows every grapheme as `*`.
+    pub fn view(&self, width: u16, masked: bool) -> (String, u16) {
+        fit(&self.value, self.cursor, width, masked)
+    }
+
+    /// like `view` for each line that fits in `height` rows, scrolled down to the
+    /// cursor line, with the cursor as (column, row)
+    pub fn view_lines(&self, width: u16, height: u16, masked: bool) -> (Vec<String>, (u16, u16)) {
+        let cursor_row = self.value[..self.cursor].matches('\n').count();
+        let first = (cursor_row + 1).saturating_sub(height.max(1) as usize);
+        let mut offset = 0;
+        let mut lines = vec![];
+        let mut cursor = (0, 0);
+        for (row, line) in self.value.split('\n').enumerate() {
+            if row >= first && row < first + height as usize {
+                let line_cursor = if row == cursor_row { self.cursor - offset } else { 0 };
+                let (visible, column) = fit(line, line_cursor, width, masked);
+                if row == cursor_row {
+                    cursor = (column, (row - first) as u16);
+                }
+                lines.push(visible);
+            }
+            offset += line.len() + 1;
+        }
+        (lines, cursor)
+    }
+}
+
+/// the graphemes of `text` that fit in `width` with the one at byte `cursor` among them
+fn fit(text: &str, cursor: usize, width: u16, masked: bool) -> (String, u16) {
+    let width = width as usize;
+    let graphemes: Vec<(usize, &str)> = text
+        .grapheme_indices(true)
+        .map(|(i, g)| (i, if masked { "*" } else { g }))
+        .collect();
+    let cursor_index = graphemes.iter().take_while(|(i, _)| *i < cursor).count();
+
+    // scroll right until the cursor and the cell it sits on fit
+    let mut start = 0;
+    let column = |start: usize| graphemes[start..cursor_index].iter().map(|(_, g)| g.width()).sum::<usize>();
+    while start < cursor_index && column(start) >= width.max(1) {
+        start += 1;
+    }
+
+    let mut visible = String::new();
+    let mut used = 0;
+    for (_, g) in &graphemes[start..] {
+        if used + g.width() > width {
+            break;
+        }
+        used += g.width();
+        visible.push_str(g);
+    }
+    (visible, column(start) as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editing() {
+        let mut input = Input::new("aws/prod");
+        input.word_left();
+        input.insert_str("new-\n");
+        assert_eq!(input.value(), "aws/new-prod");
+        input.delete_word();
+        assert_eq!(input.value(), "aws/prod");
+        input.end();
+        input.delete_word();
+        assert_eq!(input.value(), "aws/");
+        input.home();
+        input.word_right();
+        input.delete_to_start();
+        assert_eq!(input.value(), "/");
+
+        // combining accent and emoji are single graphemes
+        let mut input = Input::new("e\u{301}🔑");
+        input.left();
+        input.delete_back();
+        assert_eq!(input.value(), "🔑");
+        input.delete_forward();
+        assert_eq!(input.value(), "");
+    }
+
+    #[test]
+    fn test_multiline() {
+        let mut input = Input::multiline();
+        input.insert_str("-----BEGIN KEY-----\r\nabc\r\n-----END KEY-----\n");
+        assert_eq!(input.value(), "-----BEGIN KEY-----\nabc\n-----END KEY-----\n");
+        input.up();
+        input.up();
+        input.end();
+        input.newline();
+        input.insert('d');
+        assert_eq!(input.line_count(), 5);
+        assert_eq!(input.view_lines(10, 2, true), (vec!["***".to_string(), "*".to_string()], (1, 1)));
+
+        let mut input = Input::new("");
+        input.insert_str("one\ntwo");
+        input.newline();
+        assert_eq!(input.value(), "onetwo");
+    }
+
+    #[test]
+    fn test_view_scrolls() {
+        let input = Input::new("abcdefgh");
+        assert_eq!(input.view(4, false), ("fgh".to_string(), 3));
+        let mut input = Input::new("界界界界");
+        input.home();
+        assert_eq!(input.view(5, false), ("界界".to_string(), 0));
+        input.right();
+        input.right();
+        input.right();
+        assert_eq!(input.view(5, false), ("界界".to_string(), 4));
+        assert_eq!(input.view(3, true), ("***".to_string(), 2));
+    }
+}
@@ -0,0 +1,626 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+use crate::app::Mode;
+use crate::config;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Action {
+    Quit,
+    Down,
+    Up,
+    Copy,
+    Add,
+    Make,
+    Update,
+    Delete,
+    Filter,
+    Collapse,
+    Expand,
+    Mark,
+    MarkRange,
+    MarkAll,
+    ClearMarks,
+    Tag,
+    Export,
+    CopyNames,
+    Undo,
+    Redo,
+    Confirm,
+    Cancel,
+    NextField,
+    DeleteChar,
+    DeleteCharForward,
+    DeleteWord,
+    DeleteToStart,
+    Left,
+    Right,
+    WordLeft,
+    WordRight,
+    LineStart,
+    LineEnd,
+    NewLine,
+    OpenEditor,
+    Reveal,
+    Command,
+    Complete,
+    Sort,
+    Favorite,
+    CopyUsername,
+    CopyPassword,
+    CopyOtp,
+    SaveAttachment,
+    Help,
+}
+
+impl Action {
+    const ALL: [Action; 45] = [
+        Action::Quit, Action::Down, Action::Up, Action::Copy, Action::Add, Action::Make,
+        Action::Update, Action::Delete, Action::Filter, Action::Collapse, Action::Expand,
+        Action::Mark, Action::MarkRange, Action::MarkAll, Action::ClearMarks, Action::Tag,
+        Action::Export, Action::CopyNames, Action::Undo, Action::Redo, Action::Confirm,
+        Action::Cancel, Action::NextField, Action::DeleteChar, Action::DeleteCharForward,
+        Action::DeleteWord, Action::DeleteToStart, Action::Left, Action::Right, Action::WordLeft,
+        Action::WordRight, Action::LineStart, Action::LineEnd, Action::NewLine, Action::OpenEditor,
+        Action::Reveal, Action::Command, Action::Complete, Action::Sort, Action::Favorite,
+        Action::CopyUsername, Action::CopyPassword, Action::CopyOtp, Action::SaveAttachment,
+        Action::Help,
+    ];
+
+    /// the name used for the action in the keymap file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Down => "down",
+            Action::Up => "up",
+            Action::Copy => "copy",
+            Action::Add => "add",
+            Action::Make => "make",
+            Action::Update => "update",
+            Action::Delete => "delete",
+            Action::Filter => "filter",
+            Action::Collapse => "collapse",
+            Action::Expand => "expand",
+            Action::Mark => "mark",
+            Action::MarkRange => "mark-range",
+            Action::MarkAll => "mark-all",
+            Action::ClearMarks => "clear-marks",
+            Action::Tag => "tag",
+            Action::Export => "export",
+            Action::CopyNames => "copy-names",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Confirm => "confirm",
+            Action::Cancel => "cancel",
+            Action::NextField => "next-field",
+            Action::DeleteChar => "delete-char",
+            Action::DeleteCharForward => "delete-char-forward",
+            Action::DeleteWord => "delete-word",
+            Action::DeleteToStart => "delete-to-start",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::WordLeft => "word-left",
+            Action::WordRight => "word-right",
+            Action::LineStart => "line-start",
+            Action::LineEnd => "line-end",
+            Action::NewLine => "new-line",
+            Action::OpenEditor => "open-editor",
+            Action::Reveal => "reveal",
+            Action::Command => "command",
+            Action::Complete => "complete",
+            Action::Sort => "sort",
+            Action::Favorite => "favorite",
+            Action::CopyUsername => "copy-username",
+            Action::CopyPassword => "copy-password",
+            Action::CopyOtp => "copy-otp",
+            Action::SaveAttachment => "save-attachment",
+            Action::Help => "help",
+        }
+    }
+
+    /// what the action does, as shown in the guide line
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Down => "next",
+            Action::Up => "previous",
+            Action::Copy => "copy to clipboard",
+            Action::Add => "add secret",
+            Action::Make => "make secret",
+            Action::Update => "update/move",
+            Action::Delete => "delete",
+            Action::Filter => "filter secrets",
+            Action::Collapse => "collapse",
+            Action::Expand => "expand",
+            Action::Mark => "mark",
+            Action::MarkRange => "mark range",
+            Action::MarkAll => "mark all",
+            Action::ClearMarks => "clear marks",
+            Action::Tag => "tag",
+            Action::Export => "export",
+            Action::CopyNames => "copy names",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Confirm => "confirm",
+            Action::Cancel => "cancel",
+            Action::NextField => "switch input",
+            Action::DeleteChar => "delete char",
+            Action::DeleteCharForward => "delete char under cursor",
+            Action::DeleteWord => "delete word",
+            Action::DeleteToStart => "delete to start",
+            Action::Left => "cursor left",
+            Action::Right => "cursor right",
+            Action::WordLeft => "word left",
+            Action::WordRight => "word right",
+            Action::LineStart => "start of line",
+            Action::LineEnd => "end of line",
+            Action::NewLine => "new line",
+            Action::OpenEditor => "edit in $EDITOR",
+            Action::Reveal => "show/hide secret",
+            Action::Command => "command line",
+            Action::Complete => "complete",
+            Action::Sort => "cycle sort order",
+            Action::Favorite => "pin/unpin favorite",
+            Action::CopyUsername => "copy username",
+            Action::CopyPassword => "copy password",
+            Action::CopyOtp => "copy one-time code",
+            Action::SaveAttachment => "save attachment to a file",
+            Action::Help => "help",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+}
+
+/// a key together with its modifiers, written like `ctrl-r` or `alt-enter` in the keymap file
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn parse(s: &str) -> Result<KeyChord, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s.trim();
+        // a lone `-` is a key, not a separator
+        while let Some((modifier, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "c" => KeyModifiers::CONTROL,
+                "alt" | "m" => KeyModifiers::ALT,
+                "shift" | "s" => KeyModifiers::SHIFT,
+                _ => break,
+            };
+            rest = key;
+        }
+        let code = match rest.to_lowercase().as_str() {
+            "enter" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "space" => KeyCode::Char(' '),
+            "backspace" => KeyCode::Backspace,
+            "delete" => KeyCode::Delete,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            f if f.len() > 1 && f.starts_with('f') && f[1..].parse::<u8>().is_ok() => {
+                KeyCode::F(f[1..].parse().unwrap())
+            }
+            _ if rest.chars().count() == 1 => KeyCode::Char(rest.chars().next().unwrap()),
+            _ => return Err(format!("unknown key '{}'", s)),
+        };
+        Ok(KeyChord { code, modifiers }.normalized())
+    }
+
+    fn from_event(key: KeyEvent) -> KeyChord {
+        KeyChord { code: key.code, modifiers: key.modifiers }.normalized()
+    }
+
+    // shift is already part of the char, `*` arrives as shift-* on most terminals
+    fn normalized(mut self) -> KeyChord {
+        if let KeyCode::Char(ch) = self.code {
+            self.modifiers.remove(KeyModifiers::SHIFT);
+            if self.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+                self.code = KeyCode::Char(ch.to_ascii_lowercase());
+            }
+        }
+        self
+    }
+
+    pub fn display(&self) -> String {
+        let mut s = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            s.push_str("ctrl-");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            s.push_str("alt-");
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            s.push_str("shift-");
+        }
+        let key = match self.code {
+            KeyCode::Char(' ') => "space".to_string(),
+            KeyCode::Char(ch) => ch.to_string(),
+            KeyCode::F(n) => format!("f{}", n),
+            KeyCode::PageUp => "pageup".to_string(),
+            KeyCode::PageDown => "pagedown".to_string(),
+            KeyCode::BackTab => "backtab".to_string(),
+            code => format!("{:?}", code).to_lowercase(),
+        };
+        s.push_str(&key);
+        s
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// the keymap file, a preset plus per mode overrides:
+///
+/// ```toml
+/// preset = "vim"
+///
+/// [normal]
+/// quit = ["q", "ctrl-c"]
+/// undo = "ctrl-z"
+/// ```
+#[derive(Deserialize, Default)]
+pub struct KeymapConfig {
+    preset: Option<String>,
+    #[serde(flatten)]
+    modes: HashMap<String, HashMap<String, Keys>>,
+}
+
+pub const PRESETS: [&str; 2] = ["vim", "emacs"];
+
+/// the actions shown in the guide line of each mode, in order
+const NORMAL_GUIDE: [Action; 12] = [
+    Action::Delete, Action::Add, Action::Make, Action::Copy, Action::Filter, Action::Command,
+    Action::Update, Action::Collapse, Action::Undo, Action::Redo, Action::Help, Action::Quit,
+];
+const MARKED_GUIDE: [Action; 9] = [
+    Action::Mark, Action::MarkRange, Action::MarkAll, Action::Delete, Action::Tag,
+    Action::Update, Action::Export, Action::CopyNames, Action::ClearMarks,
+];
+const INPUT_GUIDE: [Action; 3] = [Action::Confirm, Action::NextField, Action::Cancel];
+const VALUE_GUIDE: [Action; 5] = [Action::Confirm, Action::NextField, Action::NewLine, Action::OpenEditor, Action::Cancel];
+const CONFIRM_GUIDE: [Action; 2] = [Action::Confirm, Action::Cancel];
+const COMMAND_GUIDE: [Action; 3] = [Action::Confirm, Action::Complete, Action::Cancel];
+pub const GUIDE_SEPARATOR: &str = ", ";
+
+pub struct Keymap {
+    pub preset: &'static str,
+    bindings: HashMap<Mode, Vec<(KeyChord, Action)>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::preset("vim").unwrap()
+    }
+}
+
+impl Keymap {
+    pub const MODES: [(Mode, &'static str); 10] = [
+        (Mode::Normal, "normal"),
+        (Mode::Filter, "filter"),
+        (Mode::Make, "make"),
+        (Mode::Add, "add"),
+        (Mode::Update, "update"),
+        (Mode::Delete, "delete"),
+        (Mode::Confirm, "confirm"),
+        (Mode::Tag, "tag"),
+        (Mode::Move, "move"),
+        (Mode::Command, "command"),
+    ];
+
+    pub fn preset(name: &str) -> Result<Keymap, String> {
+        let (preset, normal, input, filter, value, command) = match name {
+            "vim" => (
+                "vim",
+                vec![
+                    ("q", Action::Quit),
+                    ("j", Action::Down), ("down", Action::Down),
+                    ("k", Action::Up), ("up", Action::Up),
+                    ("enter", Action::Copy),
+                    ("a", Action::Add),
+                    ("m", Action::Make),
+                    ("r", Action::Update),
+                    ("d", Action::Delete),
+                    ("/", Action::Filter),
+                    (":", Action::Command),
+                    ("s", Action::Sort),
+                    ("f", Action::Favorite),
+                    ("h", Action::Collapse), ("left", Action::Collapse),
+                    ("l", Action::Expand), ("right", Action::Expand),
+                    ("space", Action::Mark),
+                    ("v", Action::MarkRange),
+                    ("*", Action::MarkAll),
+                    ("esc", Action::ClearMarks),
+                    ("t", Action::Tag),
+                    ("e", Action::Export),
+                    ("c", Action::CopyNames),
+                    ("y", Action::CopyUsername),
+                    ("p", Action::CopyPassword),
+                    ("o", Action::CopyOtp),
+                    ("w", Action::SaveAttachment),
+                    ("u", Action::Undo), ("ctrl-z", Action::Undo),
+                    ("ctrl-r", Action::Redo),
+                    ("?", Action::Help), ("f1", Action::Help),
+                ],
+                vec![
+                    ("enter", Action::Confirm),
+                    ("esc", Action::Cancel),
+                    ("tab", Action::NextField),
+                    ("backspace", Action::DeleteChar),
+                    ("delete", Action::DeleteCharForward),
+                    ("ctrl-w", Action::DeleteWord), ("alt-backspace", Action::DeleteWord),
+                    ("ctrl-u", Action::DeleteToStart),
+                    ("left", Action::Left),
+                    ("right", Action::Right),
+                    ("ctrl-left", Action::WordLeft), ("alt-b", Action::WordLeft),
+                    ("ctrl-right", Action::WordRight), ("alt-f", Action::WordRight),
+                    ("home", Action::LineStart), ("ctrl-a", Action::LineStart),
+                    ("end", Action::LineEnd), ("ctrl-e", Action::LineEnd),
+                    ("f1", Action::Help),
+                ],
+                vec![("down", Action::Down), ("up", Action::Up)],
+                vec![
+                    ("down", Action::Down), ("up", Action::Up),
+                    ("alt-enter", Action::NewLine), ("ctrl-j", Action::NewLine),
+                    ("ctrl-o", Action::OpenEditor),
+                    ("ctrl-t", Action::Reveal),
+                ],
+                vec![("tab", Action::Complete), ("up", Action::Up), ("down", Action::Down)],
+            ),
+            "emacs" => (
+                "emacs",
+                vec![
+                    ("ctrl-c", Action::Quit), ("q", Action::Quit),
+                    ("ctrl-n", Action::Down), ("down", Action::Down),
+                    ("ctrl-p", Action::Up), ("up", Action::Up),
+                    ("enter", Action::Copy),
+                    ("a", Action::Add),
+                    ("m", Action::Make),
+                    ("r", Action::Update),
+                    ("ctrl-d", Action::Delete),
+                    ("ctrl-s", Action::Filter),
+                    ("alt-x", Action::Command), (":", Action::Command),
+                    ("alt-s", Action::Sort),
+                    ("f", Action::Favorite),
+                    ("ctrl-b", Action::Collapse), ("left", Action::Collapse),
+                    ("ctrl-f", Action::Expand), ("right", Action::Expand),
+                    ("ctrl-space", Action::Mark), ("space", Action::Mark),
+                    ("alt-h", Action::MarkRange),
+                    ("ctrl-x", Action::MarkAll),
+                    ("ctrl-g", Action::ClearMarks),
+                    ("t", Action::Tag),
+                    ("e", Action::Export),
+                    ("alt-w", Action::CopyNames),
+                    ("u", Action::CopyUsername),
+                    ("p", Action::CopyPassword),
+                    ("o", Action::CopyOtp),
+                    ("w", Action::SaveAttachment),
+                    ("ctrl-/", Action::Undo), ("ctrl-_", Action::Undo),
+                    ("alt-/", Action::Redo),
+                    ("?", Action::Help), ("f1", Action::Help),
+                ],
+                vec![
+                    ("enter", Action::Confirm),
+                    ("ctrl-g", Action::Cancel), ("esc", Action::Cancel),
+                    ("tab", Action::NextField),
+                    ("backspace", Action::DeleteChar), ("ctrl-h", Action::DeleteChar),
+                    ("ctrl-d", Action::DeleteCharForward), ("delete", Action::DeleteCharForward),
+                    ("ctrl-w", Action::DeleteWord), ("alt-backspace", Action::DeleteWord),
+                    ("ctrl-u", Action::DeleteToStart),
+                    ("ctrl-b", Action::Left), ("left", Action::Left),
+                    ("ctrl-f", Action::Right), ("right", Action::Right),
+                    ("alt-b", Action::WordLeft), ("ctrl-left", Action::WordLeft),
+                    ("alt-f", Action::WordRight), ("ctrl-right", Action::WordRight),
+                    ("ctrl-a", Action::LineStart), ("home", Action::LineStart),
+                    ("ctrl-e", Action::LineEnd), ("end", Action::LineEnd),
+                    ("f1", Action::Help),
+                ],
+                vec![
+                    ("ctrl-n", Action::Down), ("down", Action::Down),
+                    ("ctrl-p", Action::Up), ("up", Action::Up),
+                ],
+                vec![
+                    ("ctrl-n", Action::Down), ("down", Action::Down),
+                    ("ctrl-p", Action::Up), ("up", Action::Up),
+                    ("alt-enter", Action::NewLine), ("ctrl-j", Action::NewLine),
+                    ("ctrl-o", Action::OpenEditor),
+                    ("ctrl-t", Action::Reveal),
+                ],
+                vec![
+                    ("tab", Action::Complete),
+                    ("ctrl-p", Action::Up), ("up", Action::Up),
+                    ("ctrl-n", Action::Down), ("down", Action::Down),
+                ],
+            ),
+            _ => return Err(format!("unknown keymap preset '{}', expected one of {}", name, PRESETS.join(", "))),
+        };
+
+        let parse = |keys: &[(&str, Action)]| -> Vec<(KeyChord, Action)> {
+            keys.iter().map(|(key, action)| (KeyChord::parse(key).unwrap(), *action)).collect()
+        };
+        let mut bindings = HashMap::new();
+        for (mode, _) in Keymap::MODES {
+            let keys = match mode {
+                Mode::Normal => parse(&normal),
+                Mode::Filter => [parse(&input), parse(&filter)].concat(),
+                // only add and update edit a secret value, which can span lines
+                Mode::Add | Mode::Update => [parse(&input), parse(&value)].concat(),
+                // tab completes instead of switching fields
+                Mode::Command => [parse(&command), parse(&input)].concat(),
+                _ => parse(&input),
+            };
+            bindings.insert(mode, keys);
+        }
+        Ok(Keymap { preset, bindings })
+    }
+
+    /// the preset named in `config`, with the keys of the actions it lists replaced
+    pub fn from_config(config: KeymapConfig) -> Result<Keymap, String> {
+        let mut keymap = Keymap::preset(config.preset.as_deref().unwrap_or("vim"))?;
+        for (mode_name, actions) in config.modes {
+            let mode = Keymap::MODES
+                .iter()
+                .find(|(_, name)| *name == mode_name)
+                .map(|(mode, _)| *mode)
+                .ok_or(format!("unknown mode '{}' in keymap", mode_name))?;
+            let bindings = keymap.bindings.get_mut(&mode).unwrap();
+            for (action_name, keys) in actions {
+                let action = Action::from_name(&action_name)
+                    .ok_or(format!("unknown action '{}' in keymap", action_name))?;
+                let keys = match keys {
+                    Keys::One(key) => vec![key],
+                    Keys::Many(keys) => keys,
+                };
+                bindings.retain(|(_, bound)| *bound != action);
+                for key in keys {
+                    let chord = KeyChord::parse(&key)?;
+                    // a key can only trigger one action
+                    bindings.retain(|(bound, _)| *bound != chord);
+                    bindings.push((chord, action));
+                }
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// where the keymap is read from, `$XDG_CONFIG_HOME/secm/keymap.toml`
+    pub fn path() -> Option<PathBuf> {
+        config::config_home().map(|dir| dir.join("secm").join("keymap.toml"))
+    }
+
+    /// the keymap from the keymap file, the vim preset when there is none
+    pub fn load() -> Result<Keymap, String> {
+        let Some(path) = Keymap::path().filter(|path| path.exists()) else {
+            return Ok(Keymap::default());
+        };
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        let config: KeymapConfig = toml::from_str(&content)
+            .map_err(|e| format!("Invalid keymap {}: {}", path.display(), e))?;
+        Keymap::from_config(config)
+    }
+
+    pub fn action(&self, mode: &Mode, key: KeyEvent) -> Option<Action> {
+        let chord = KeyChord::from_event(key);
+        self.bindings
+            .get(mode)?
+            .iter()
+            .find(|(bound, _)| *bound == chord)
+            .map(|(_, action)| *action)
+    }
+
+    /// every key bound to `action` in `mode`
+    pub fn keys(&self, mode: &Mode, action: Action) -> Vec<KeyChord> {
+        self.bindings
+            .get(mode)
+            .map(|bindings| {
+                bindings
+                    .iter()
+                    .filter(|(_, bound)| *bound == action)
+                    .map(|(chord, _)| *chord)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// all bindings of `mode`, for the help page
+    pub fn bindings(&self, mode: &Mode) -> &[(KeyChord, Action)] {
+        self.bindings.get(mode).map(|b| b.as_slice()).unwrap_or(&[])
+    }
+
+    /// the actions of the guide line for `mode` with their text, e.g. `d: delete`
+    pub fn guide_items(&self, mode: &Mode, marked: bool) -> Vec<(Action, String)> {
+        let actions: &[Action] = match mode {
+            Mode::Normal if marked => &MARKED_GUIDE,
+            Mode::Normal => &NORMAL_GUIDE,
+            Mode::Filter | Mode::Delete | Mode::Confirm | Mode::Tag | Mode::Move => &CONFIRM_GUIDE,
+            Mode::Make => &INPUT_GUIDE,
+            Mode::Add | Mode::Update => &VALUE_GUIDE,
+            Mode::Command => &COMMAND_GUIDE,
+        };
+        actions
+            .iter()
+            .filter_map(|action| {
+                let key = self.keys(mode, *action).into_iter().next()?;
+                Some((*action, format!("{}: {}", key.display(), action.description())))
+            })
+            .collect()
+    }
+
+    /// the guide line for `mode`, e.g. `d: delete, a: add secret, q: quit`
+    pub fn guide(&self, mode: &Mode, marked: bool) -> String {
+        let items: Vec<String> = self
+            .guide_items(mode, marked)
+            .into_iter()
+            .map(|(_, item)| item)
+            .collect();
+        items.join(GUIDE_SEPARATOR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_chord() {
+        let chord = KeyChord::parse("ctrl-r").unwrap();
+        assert_eq!(chord, KeyChord { code: KeyCode::Char('r'), modifiers: KeyModifiers::CONTROL });
+        assert_eq!(chord.display(), "ctrl-r");
+        assert_eq!(KeyChord::parse("-").unwrap().code, KeyCode::Char('-'));
+        assert_eq!(KeyChord::parse("alt--").unwrap().modifiers, KeyModifiers::ALT);
+        assert_eq!(KeyChord::parse("enter").unwrap().display(), "enter");
+        assert!(KeyChord::parse("hyper-x").is_err());
+    }
+
+    #[test]
+    fn test_keymap_overrides() {
+        let config: KeymapConfig = toml::from_str(r#"
+            preset = "vim"
+
+            [normal]
+            quit = ["ctrl-q", "esc"]
+        "#).unwrap();
+        let keymap = Keymap::from_config(config).unwrap();
+        let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        let q = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE);
+        assert_eq!(keymap.action(&Mode::Normal, esc), Some(Action::Quit));
+        assert_eq!(keymap.action(&Mode::Normal, q), None);
+        assert!(keymap.guide(&Mode::Normal, false).ends_with("ctrl-q: quit"));
+    }
+
+    #[test]
+    fn test_vim_esc_does_not_quit() {
+        let keymap = Keymap::default();
+        let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        assert_eq!(keymap.action(&Mode::Normal, esc), Some(Action::ClearMarks));
+        let star = KeyEvent::new(KeyCode::Char('*'), KeyModifiers::SHIFT);
+        assert_eq!(keymap.action(&Mode::Normal, star), Some(Action::MarkAll));
+        // u undoes as in vim, y yanks the username
+        let u = KeyEvent::new(KeyCode::Char('u'), KeyModifiers::NONE);
+        assert_eq!(keymap.action(&Mode::Normal, u), Some(Action::Undo));
+        let y = KeyEvent::new(KeyCode::Char('y'), KeyModifiers::NONE);
+        assert_eq!(keymap.action(&Mode::Normal, y), Some(Action::CopyUsername));
+    }
+}
@@ -1,16 +1,91 @@
 pub mod app;
+pub mod config;
+pub mod generator;
 pub mod utils;
 pub mod panel;
 pub mod ui;
 pub mod cmds;
 pub mod handle_keys;
+pub mod handle_mouse;
+pub mod input;
 pub mod storage;
 pub mod model;
+pub mod tree;
+pub mod history;
+pub mod keymap;
+pub mod theme;
+pub mod sort;
+pub mod timer;
+pub mod strength;
+pub mod breach;
+pub mod otp;
+pub mod ssh;
+pub mod attachment;
+pub mod credential;
+
+use std::io::{Read, Write};
+use std::path::Path;
+
+use model::{Approval, Attachment, Record, SealedAttachment, SecretStats};
 
 pub trait Storage {
+    /// the storage kept at `path`, created when there is none yet
+    fn open(path: &Path) -> Result<Self, String> where Self: Sized;
     fn write(&self, key: &str, value: &str) -> Result<(), String>;
     fn read(&self, key: &str) -> Result<Option<String>, String>;
     fn update(&self, key: &str, value: &str) -> Result<(), String>;
     fn get_all(&self) -> Result<Vec<(String, String)>, String>;
     fn delete(&self, key: &str) -> Result<(), String>;
+    fn rename(&self, from: &str, to: &str) -> Result<(), String>;
+    /// move every secret below the folder `from` into the folder `to`, returns the number of moved secrets
+    fn rename_prefix(&self, from: &str, to: &str) -> Result<usize, String>;
+    /// delete every secret below the folder `prefix`, returns the number of deleted secrets
+    fn delete_prefix(&self, prefix: &str) -> Result<usize, String>;
+    /// names and tags of every tagged secret
+    fn get_tags(&self) -> Result<Vec<(String, Vec<String>)>, String>;
+    fn set_tags(&self, key: &str, tags: &[String]) -> Result<(), String>;
+    /// count a copy or read of a secret, for sorting by use
+    fn record_use(&self, key: &str) -> Result<(), String>;
+    /// when each secret was created, modified and last used, and how often
+    fn get_stats(&self) -> Result<Vec<(String, SecretStats)>, String>;
+    /// the names of each group of secrets sharing a value, found through
+    /// keyed hashes of the values rather than the values themselves
+    fn get_reused(&self) -> Result<Vec<Vec<String>>, String>;
+    /// names of the secrets whose value is `value`
+    fn names_with_value(&self, value: &str) -> Result<Vec<String>, String>;
+    /// names and fields of every typed secret
+    fn get_records(&self) -> Result<Vec<(String, Record)>, String>;
+    /// make `key` a typed secret, or a plain one again with `None`
+    fn set_record(&self, key: &str, record: Option<&Record>) -> Result<(), String>;
+    /// names of the favorite secrets in the order they were pinned
+    fn get_favorites(&self) -> Result<Vec<String>, String>;
+    fn set_favorite(&self, key: &str, favorite: bool) -> Result<(), String>;
+    /// a value kept between sessions, like the sort order of the list
+    fn get_setting(&self, key: &str) -> Result<Option<String>, String>;
+    fn set_setting(&self, key: &str, value: &str) -> Result<(), String>;
+    /// ask a running TUI to allow signing `what` with the key `key`, returns the id of the request
+    fn request_approval(&self, key: &str, what: &str) -> Result<i64, String>;
+    /// the requests nobody answered yet, the oldest first
+    fn get_approvals(&self) -> Result<Vec<Approval>, String>;
+    /// answer request `id`, only the first answer counts
+    fn decide_approval(&self, id: i64, allow: bool) -> Result<(), String>;
+    /// the answer to request `id`, the request is gone once it was answered
+    fn take_approval(&self, id: i64) -> Result<Option<bool>, String>;
+    /// attach the file read from `data` to `key` as `name`, replacing an attachment of
+    /// that name, returns its size. Nothing is kept when it is larger than `max_size`.
+    fn add_attachment(&self, key: &str, name: &str, data: &mut dyn Read, max_size: u64) -> Result<u64, String>;
+    /// names of the secrets with attachments and the attachments, by secret and name
+    fn get_attachments(&self) -> Result<Vec<(String, Attachment)>, String>;
+    /// write the attachment `name` of `key` to `out` piece by piece, returns its size
+    fn read_attachment(&self, key: &str, name: &str, out: &mut dyn Write) -> Result<u64, String>;
+    fn delete_attachment(&self, key: &str, name: &str) -> Result<(), String>;
+    /// the attachments of `key` as they are stored, to put them back after `key` was deleted
+    fn get_sealed_attachments(&self, key: &str) -> Result<Vec<SealedAttachment>, String>;
+    /// attach what `get_sealed_attachments` returned to `key` again, the ids of
+    /// deleted attachments are never given to new ones so they are still free
+    fn restore_attachments(&self, key: &str, attachments: &[SealedAttachment]) -> Result<(), String>;
+    /// a number that changes whenever another process changed the storage
+    fn data_version(&self) -> Result<i64, String>;
+    /// run `f` as a single transaction, nothing it wrote is kept when it fails
+    fn atomic(&self, f: &mut dyn FnMut(&Self) -> Result<(), String>) -> Result<(), String>;
 }
@@ -1,71 +1,187 @@
 use std::{error::Error, io};
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 use ratatui::{
     backend::{Backend, CrosstermBackend},
     crossterm::{
-        event::{self, DisableMouseCapture, EnableMouseCapture, Event},
+        event::{self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, Event},
         execute,
         terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     },
     terminal::Terminal,
 };
 
-use secm::{app::App, cmds, storage::SqliteStorage, ui, Storage};
+use secm::{app::App, cmds, config::{Config, Options}, storage::SqliteStorage, ui, Storage};
+
+/// how often timers are checked and the screen is redrawn without input
+const TICK_RATE: Duration = Duration::from_millis(250);
 
 const ERROR_MSG: &str = r#"
 "Usage:
+ - secm [--config <path>] [--db <path>] ... # read another config file or database
  - secm # enter secret management ui
+ - secm make <name> [-l=<length>] [-p=<policy>] # generate a secret, e.g. -p="lud2s -ambiguous"
+ - secm gen [-l <length>] [-p <policy>] # print a random secret without saving it
+ - secm gen --words 6 [--sep -] [--case title] [--digit] [--symbol] # print a passphrase
+ - secm ls [prefix] # list secrets and folders under prefix
+ - secm ls --favorites # list the numbered favorites
+ - secm fav <n> # copy favorite number n
+ - secm get <name> [--field <field>] # print the value or a field of a secret, e.g. --field username
+ - secm otp <name> # print the current one-time code of an otpauth:// URI, base32 seed or totp field
+ - secm ssh-key gen <name> [ed25519|ecdsa|ecdsa-p384|rsa] [--bits <n>] # generate an ssh key, prints the public key
+ - secm ssh-key import <name> <file> # store an OpenSSH private key, asking for its passphrase
+ - secm ssh-key pub <name> # print the public key of an ssh key
+ - secm ssh-agent [-a <socket>] # serve the ssh keys to ssh, signing when a TUI or the config allows it
+ - secm attach add <secret> <file|-> [--name <name>] # attach an encrypted file to a secret, at most max_attachment_size MiB
+ - secm attach get <secret> <name> [path|-] # save an attachment readable only by you, or print it with -
+ - secm attach rm <secret> <name> # remove an attachment
+ - secm attach ls [secret] # list attachments and their sizes
+ - secm git-credential get|store|erase # git's credential helper over login records, set credential.helper to secm
+ - secm record <name> [<type> [<field>=<value>...]] # show or set the type and fields of a secret
+ - secm audit # list secrets from the weakest, with what makes them weak
+ - secm audit --reuse # list the groups of secrets sharing a value
+ - secm audit --breached [path] # list secrets found in a local Have I Been Pwned dataset
+ - secm export [--force] # write all secrets to ./secrets.json, replacing an existing one with --force
+ - secm import # read the secrets of ./secrets.json
+ - secm rm [-r] <name|prefix> # delete a secret, or a whole folder with -r
 "#;
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
-    if args.len() == 1 {
-        if let Err(err) = scem() {
+    let (options, args) = match Options::parse(&args[1..]) {
+        Ok(parsed) => parsed,
+        Err(err) => return println!("{}", err),
+    };
+    let config = match Config::load(&options) {
+        Ok(config) => config,
+        Err(err) => return println!("{}", err),
+    };
+    let (db, notice) = match config.db_path(&options) {
+        Ok(db) => db,
+        Err(err) => return println!("{}", err),
+    };
+    if args.is_empty() {
+        if let Err(err) = scem(config, &db, notice) {
             println!("{}", err);
         }
         return;
     }
+    if let Some(notice) = notice {
+        eprintln!("{}", notice);
+    }
     // if args.len() < 3  {
     //     panic!("{}", ERROR_MSG)
     // }
 
-    let verb = args[1].trim();
+    let verb = args[0].trim();
     match verb {
         "import" => {
-            if let Err(err) = cmds::cmd_import() {
+            if let Err(err) = cmds::cmd_import(&db, &config) {
                 println!("{}", err);
             }
         },
         "export" => {
-            if let Err(err) = cmds::cmd_export() {
+            if let Err(err) = cmds::cmd_export(&db, &args[1..]) {
+                println!("{}", err);
+            }
+        },
+        "make" => {
+            if let Err(err) = cmds::cmd_make(&db, &config, &args[1..]) {
+                println!("{}", err);
+            }
+        },
+        "gen" => {
+            if let Err(err) = cmds::cmd_gen(&config, &args[1..]) {
+                println!("{}", err);
+            }
+        },
+        "audit" => {
+            if let Err(err) = cmds::cmd_audit(&db, &config, &args[1..]) {
+                println!("{}", err);
+            }
+        },
+        "ls" => {
+            if let Err(err) = cmds::cmd_ls(&db, &args[1..]) {
+                println!("{}", err);
+            }
+        },
+        "get" => {
+            if let Err(err) = cmds::cmd_get(&db, &args[1..]) {
+                println!("{}", err);
+            }
+        },
+        "otp" => {
+            if let Err(err) = cmds::cmd_otp(&db, &args[1..]) {
+                println!("{}", err);
+            }
+        },
+        "ssh-key" => {
+            if let Err(err) = cmds::cmd_ssh_key(&db, &args[1..]) {
+                println!("{}", err);
+            }
+        },
+        "ssh-agent" => {
+            if let Err(err) = cmds::cmd_ssh_agent(&db, &config, &args[1..]) {
+                println!("{}", err);
+            }
+        },
+        "git-credential" => {
+            // stdout is read by git, errors go to the terminal
+            if let Err(err) = cmds::cmd_git_credential(&db, &config, &args[1..]) {
+                eprintln!("secm: {}", err);
+                std::process::exit(1);
+            }
+        },
+        "attach" => {
+            if let Err(err) = cmds::cmd_attach(&db, &config, &args[1..]) {
+                println!("{}", err);
+            }
+        },
+        "record" => {
+            if let Err(err) = cmds::cmd_record(&db, &args[1..]) {
+                println!("{}", err);
+            }
+        },
+        "fav" => {
+            if let Err(err) = cmds::cmd_fav(&db, &args[1..]) {
+                println!("{}", err);
+            }
+        },
+        "rm" => {
+            if let Err(err) = cmds::cmd_rm(&db, &args[1..]) {
                 println!("{}", err);
             }
         },
         _ => {
-            if let Err(err) = cmds::cmd_save(&args[1..]) {
+            if let Err(err) = cmds::cmd_save(&db, &config, args) {
                 println!("{}", err);
             }
         },
     }
 }
 
-fn scem() -> Result<(), Box<dyn Error>> {
+fn scem(mut config: Config, db: &Path, notice: Option<String>) -> Result<(), Box<dyn Error>> {
+    // read the keymap before taking over the terminal so errors stay readable
+    let keymap = config.keymap()?;
+    let theme = config.theme()?;
+    let db = db.to_str().ok_or("Database path contains invalid UTF-8")?;
+    let storage = SqliteStorage::new(db)?;
+
     // 1.初始化终端
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let home_dir = dirs::home_dir()
-        .ok_or("Unable to determine home directory")?;
-    
-    let home_dir_str = home_dir
-        .to_str()
-        .ok_or("Home directory contains invalid UTF-8")?;
-
-    let storage = SqliteStorage::new(&format!("{}/.secrets.db", home_dir_str))?;
-    let app = App::new(storage);
+    let mut app = App::new(storage);
+    app.set_keymap(keymap);
+    app.theme = theme;
+    app.config = config;
+    if let Some(notice) = notice {
+        app.notify(notice);
+    }
 
     // 2.渲染界面
     let res = run_app(&mut terminal, app);
@@ -75,7 +191,8 @@ fn scem() -> Result<(), Box<dyn Error>> {
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableMouseCapture,
+        DisableBracketedPaste
     )?;
     terminal.show_cursor()?;
 
@@ -92,11 +209,33 @@ fn scem() -> Result<(), Box<dyn Error>> {
 // 这种用法使得错误处理更加方便和紧凑，避免了显式的 `match` 或 `if let` 语句来处理每个可能的错误情况。如果使用 `?` 运算符，你可以将错误传播到调用者，以便在更高层次上进行处理。
 // 在你的示例中，当 `crossterm::event::poll(Duration::from_secs(1))?` 或 `event::read()?` 出现错误时，程序将尽早返回错误，以确保错误得到适当的处理。
 fn run_app<B: Backend, S: Storage>(terminal: &mut Terminal<B>, mut app: App<S>) -> io::Result<()> {
+    let mut last_tick = Instant::now();
     while !app.should_exit {
         terminal.draw(|f| ui::ui(f, &mut app))?;
-        if let Event::Key(key) = event::read()? {
-            app.handle_key(key);
-        };
+        // wait for input until the next tick, so timers run while nothing is typed
+        let timeout = TICK_RATE.saturating_sub(last_tick.elapsed());
+        if event::poll(timeout)? {
+            match event::read()? {
+                Event::Key(key) => app.handle_key(key),
+                Event::Mouse(mouse) => app.handle_mouse(mouse),
+                Event::Paste(text) => app.handle_paste(text),
+                Event::Resize(_, _) => terminal.autoresize()?,
+                _ => {}
+            }
+        }
+        if last_tick.elapsed() >= TICK_RATE {
+            app.tick();
+            last_tick = Instant::now();
+        }
+        if app.open_editor {
+            // hand the terminal to the editor until it exits
+            disable_raw_mode()?;
+            execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture, DisableBracketedPaste)?;
+            app.edit_value_in_editor();
+            enable_raw_mode()?;
+            execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture, EnableBracketedPaste)?;
+            terminal.clear()?;
+        }
     }
     Ok(())
 }
\ No newline at end of file
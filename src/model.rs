@@ -1,7 +1,230 @@
-use serde::Deserialize;
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, Deserialize)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Secret {
     pub name: String,
     pub value: String,
-}
\ No newline at end of file
+}
+/// how a secret was used, times are unix seconds and 0 when unknown
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SecretStats {
+    pub created: i64,
+    pub modified: i64,
+    pub last_used: i64,
+    pub use_count: u32,
+}
+
+/// a signature `secm ssh-agent` asks to make with the key `key`, waiting for a yes or no
+#[derive(Debug, Clone, PartialEq)]
+pub struct Approval {
+    pub id: i64,
+    pub key: String,
+    pub what: String,
+}
+
+/// a file attached to a secret, its content stays encrypted in the storage
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub name: String,
+    pub size: u64,
+    pub created: i64,
+}
+
+/// an attachment as it is stored, its chunks still sealed to its id, so a deleted
+/// attachment can be put back without decrypting it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SealedAttachment {
+    pub id: i64,
+    pub name: String,
+    pub size: u64,
+    pub created: i64,
+    /// seq, last flag and sealed data of each chunk
+    pub chunks: Vec<(i64, bool, Vec<u8>)>,
+}
+
+/// a secret in an export bundle, with its record and its attachments as base64
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExportedSecret {
+    #[serde(flatten)]
+    pub secret: Secret,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record: Option<Record>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<ExportedAttachment>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExportedAttachment {
+    pub name: String,
+    pub data: String,
+}
+
+/// what a record holds, a plain secret has no kind and only its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RecordKind {
+    Login,
+    ApiKey,
+    Database,
+    SshKey,
+    Note,
+    Card,
+}
+
+impl RecordKind {
+    pub const ALL: [RecordKind; 6] = [
+        RecordKind::Login, RecordKind::ApiKey, RecordKind::Database,
+        RecordKind::SshKey, RecordKind::Note, RecordKind::Card,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RecordKind::Login => "login",
+            RecordKind::ApiKey => "api-key",
+            RecordKind::Database => "database",
+            RecordKind::SshKey => "ssh-key",
+            RecordKind::Note => "note",
+            RecordKind::Card => "card",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<RecordKind, String> {
+        RecordKind::ALL.into_iter().find(|kind| kind.name() == name).ok_or_else(|| {
+            let names: Vec<&str> = RecordKind::ALL.iter().map(RecordKind::name).collect();
+            format!("Unknown record type '{}', expected one of {}", name, names.join(", "))
+        })
+    }
+
+    /// the named fields, the first one is the value of the secret
+    pub fn fields(&self) -> &'static [&'static str] {
+        match self {
+            RecordKind::Login => &["password", "username", "urls", "totp"],
+            RecordKind::ApiKey => &["key", "id", "url"],
+            RecordKind::Database => &["password", "host", "port", "user", "database"],
+            RecordKind::SshKey => &["private-key", "public-key", "passphrase"],
+            RecordKind::Note => &["note"],
+            RecordKind::Card => &["number", "holder", "expiry", "cvv", "pin"],
+        }
+    }
+
+    /// fields kept as secret as the value, shown masked like it
+    pub fn is_sensitive(&self, field: &str) -> bool {
+        field == self.fields()[0]
+            || matches!(
+                (self, field),
+                (RecordKind::Login, "totp") | (RecordKind::SshKey, "passphrase") | (RecordKind::Card, "cvv" | "pin")
+            )
+    }
+
+    /// the field `username` stands for, what `y` copies in vim and `u` in emacs
+    fn user_field(&self) -> Option<&'static str> {
+        match self {
+            RecordKind::Login => Some("username"),
+            RecordKind::Database => Some("user"),
+            RecordKind::ApiKey => Some("id"),
+            _ => None,
+        }
+    }
+}
+
+/// the fields of a typed secret besides its value
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Record {
+    pub kind: RecordKind,
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Record {
+    pub fn new(kind: RecordKind) -> Record {
+        Record { kind, fields: BTreeMap::new() }
+    }
+
+    /// `username` and `password` work for every kind that has something like them
+    fn resolve<'a>(&self, field: &'a str) -> Result<&'a str, String> {
+        let resolved = match field {
+            "username" | "user" => self.kind.user_field().unwrap_or(field),
+            "password" | "value" => self.kind.fields()[0],
+            field => field,
+        };
+        match self.kind.fields().contains(&resolved) {
+            true => Ok(resolved),
+            false => Err(format!("A {} has no field '{}', it has {}", self.kind.name(), field, self.kind.fields().join(", "))),
+        }
+    }
+
+    /// the field `field` of the secret whose value is `value`
+    pub fn get<'a>(&'a self, value: &'a str, field: &str) -> Result<Option<&'a str>, String> {
+        let field = self.resolve(field)?;
+        match field == self.kind.fields()[0] {
+            true => Ok(Some(value)),
+            false => Ok(self.fields.get(field).map(String::as_str)),
+        }
+    }
+
+    /// set a field besides the value, an empty one is removed
+    pub fn set(&mut self, field: &str, value: &str) -> Result<(), String> {
+        let field = self.resolve(field)?;
+        if field == self.kind.fields()[0] {
+            return Err(format!("The {} of a {} is its value, change it with update", field, self.kind.name()));
+        }
+        match value.is_empty() {
+            true => self.fields.remove(field),
+            false => self.fields.insert(field.to_string(), value.to_string()),
+        };
+        Ok(())
+    }
+}
+
+/// the field `field` of a secret that may be a record, a plain secret only has its value
+pub fn secret_field<'a>(record: Option<&'a Record>, value: &'a str, field: &str) -> Result<Option<&'a str>, String> {
+    match record {
+        Some(record) => record.get(value, field),
+        None if matches!(field, "password" | "value") => Ok(Some(value)),
+        None => Err(format!("Not a record, there is no field '{}', only the value", field)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_fields() {
+        let mut login = Record::new(RecordKind::Login);
+        login.set("user", "octocat").unwrap();
+        assert_eq!(login.get("hunter2", "username").unwrap(), Some("octocat"));
+        assert_eq!(login.get("hunter2", "password").unwrap(), Some("hunter2"));
+        assert_eq!(login.get("hunter2", "totp").unwrap(), None);
+        assert!(login.set("password", "x").is_err());
+        assert!(login.get("hunter2", "cvv").is_err());
+
+        let mut database = Record::new(RecordKind::Database);
+        database.set("username", "postgres").unwrap();
+        assert_eq!(database.fields.get("user").map(String::as_str), Some("postgres"));
+        database.set("user", "").unwrap();
+        assert!(database.fields.is_empty());
+
+        assert_eq!(secret_field(None, "v", "password").unwrap(), Some("v"));
+        assert!(secret_field(None, "v", "username").is_err());
+        assert_eq!(RecordKind::parse("api-key").unwrap(), RecordKind::ApiKey);
+        assert!(RecordKind::Card.is_sensitive("cvv") && RecordKind::Card.is_sensitive("number"));
+        assert!(RecordKind::Login.is_sensitive("totp") && !RecordKind::Login.is_sensitive("username"));
+    }
+
+    #[test]
+    fn test_exported_record() {
+        let mut login = Record::new(RecordKind::Login);
+        login.set("username", "octocat").unwrap();
+        let secret = Secret { name: "github".to_string(), value: "hunter2".to_string() };
+        let exported = ExportedSecret { secret, record: Some(login.clone()), attachments: vec![] };
+        let json = serde_json::to_string(&exported).unwrap();
+        let imported: ExportedSecret = serde_json::from_str(&json).unwrap();
+        assert_eq!(imported.record, Some(login));
+
+        // bundles of older versions have no records
+        let imported: ExportedSecret = serde_json::from_str(r#"{"name":"npm","value":"x"}"#).unwrap();
+        assert_eq!(imported.record, None);
+    }
+}
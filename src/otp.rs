@@ -0,0 +1,366 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crypto::digest::Digest;
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha1::Sha1;
+use crypto::sha2::{Sha256, Sha512};
+
+use crate::model::Record;
+use crate::Storage;
+
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+const DEFAULT_DIGITS: u32 = 6;
+const DEFAULT_PERIOD: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Sha1 => "SHA1",
+            Algorithm::Sha256 => "SHA256",
+            Algorithm::Sha512 => "SHA512",
+        }
+    }
+
+    fn parse(name: &str) -> Result<Algorithm, String> {
+        match name.to_ascii_uppercase().replace('-', "").as_str() {
+            "SHA1" => Ok(Algorithm::Sha1),
+            "SHA256" => Ok(Algorithm::Sha256),
+            "SHA512" => Ok(Algorithm::Sha512),
+            _ => Err(format!("Unknown OTP algorithm '{}', expected SHA1, SHA256 or SHA512", name)),
+        }
+    }
+
+    fn hmac(&self, key: &[u8], message: &[u8]) -> Vec<u8> {
+        fn mac<D: Digest>(digest: D, key: &[u8], message: &[u8]) -> Vec<u8> {
+            let mut hmac = Hmac::new(digest, key);
+            hmac.input(message);
+            hmac.result().code().to_vec()
+        }
+        match self {
+            Algorithm::Sha1 => mac(Sha1::new(), key, message),
+            Algorithm::Sha256 => mac(Sha256::new(), key, message),
+            Algorithm::Sha512 => mac(Sha512::new(), key, message),
+        }
+    }
+}
+
+/// time based codes change every `period` seconds, counter based ones on every use
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Totp { period: u64 },
+    Hotp { counter: u64 },
+}
+
+/// what an `otpauth://` URI or a bare base32 seed describes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Otp {
+    pub kind: Kind,
+    pub secret: Vec<u8>,
+    pub algorithm: Algorithm,
+    pub digits: u32,
+    pub issuer: Option<String>,
+    pub account: String,
+}
+
+/// where the one-time code of a secret comes from: the `totp` field of a
+/// record, or the value when it is an `otpauth://` URI
+pub fn source<'a>(record: Option<&'a Record>, value: &'a str) -> Option<&'a str> {
+    match record.and_then(|record| record.fields.get("totp")) {
+        Some(totp) if !totp.is_empty() => Some(totp),
+        _ if is_uri(value) => Some(value),
+        _ => None,
+    }
+}
+
+/// the current code of a secret and how long it is valid, asked for explicitly
+/// so a bare base32 value counts too. A counter based code moves the stored counter on
+pub fn use_code<S: Storage>(storage: &S, name: &str, record: Option<&Record>, value: &str) -> Result<(String, Option<u64>), String> {
+    let field = record.and_then(|record| record.fields.get("totp")).filter(|totp| !totp.is_empty());
+    let mut otp = Otp::parse(field.map(String::as_str).unwrap_or(value))
+        .map_err(|e| format!("{} holds no otpauth:// URI or base32 seed: {}", name, e))?;
+    let code = otp.code(unix_now());
+    if let Kind::Hotp { counter } = otp.kind {
+        otp.kind = Kind::Hotp { counter: counter + 1 };
+        match record.filter(|_| field.is_some()) {
+            Some(record) => {
+                let mut record = record.clone();
+                record.fields.insert("totp".to_string(), otp.to_uri());
+                storage.set_record(name, Some(&record))?;
+            }
+            None => storage.update(name, &otp.to_uri())?,
+        }
+    }
+    Ok(code)
+}
+
+pub fn is_uri(text: &str) -> bool {
+    text.trim().get(..10).is_some_and(|scheme| scheme.eq_ignore_ascii_case("otpauth://"))
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+impl Otp {
+    /// an `otpauth://` URI, or a base32 seed for 6 digit SHA1 codes every 30 seconds
+    pub fn parse(text: &str) -> Result<Otp, String> {
+        let text = text.trim();
+        // Google Authenticator exports lists of accounts as a protobuf payload
+        if text.get(..20).is_some_and(|scheme| scheme.eq_ignore_ascii_case("otpauth-migration://")) {
+            return Err("otpauth-migration:// URIs hold an export of several accounts, \
+                        store the otpauth:// URI or the seed of one account instead".to_string());
+        }
+        if !is_uri(text) {
+            return Ok(Otp {
+                kind: Kind::Totp { period: DEFAULT_PERIOD },
+                secret: base32_decode(text)?,
+                algorithm: Algorithm::Sha1,
+                digits: DEFAULT_DIGITS,
+                issuer: None,
+                account: String::new(),
+            });
+        }
+
+        let rest = &text["otpauth://".len()..];
+        let (rest, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let (kind, label) = rest.split_once('/').unwrap_or((rest, ""));
+        let label = percent_decode(label, false)?;
+        // the label is `issuer:account`, with either part optional
+        let (label_issuer, account) = match label.split_once(':') {
+            Some((issuer, account)) => (Some(issuer.trim().to_string()), account.trim().to_string()),
+            None => (None, label.trim().to_string()),
+        };
+
+        let mut secret = None;
+        let mut issuer = None;
+        let mut algorithm = Algorithm::Sha1;
+        let mut digits = DEFAULT_DIGITS;
+        let mut period = DEFAULT_PERIOD;
+        let mut counter = 0;
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(value, true)?;
+            match key.to_ascii_lowercase().as_str() {
+                "secret" => secret = Some(base32_decode(&value)?),
+                "issuer" => issuer = Some(value),
+                "algorithm" => algorithm = Algorithm::parse(&value)?,
+                "digits" => digits = value.parse().ok().filter(|d| (6..=10).contains(d))
+                    .ok_or(format!("Invalid OTP digits '{}', expected 6 to 10", value))?,
+                "period" => period = value.parse().ok().filter(|p| *p > 0)
+                    .ok_or(format!("Invalid OTP period '{}'", value))?,
+                "counter" => counter = value.parse().map_err(|_| format!("Invalid OTP counter '{}'", value))?,
+                // image, color and whatever else apps put in their QR codes
+                _ => {}
+            }
+        }
+
+        let kind = match kind.to_ascii_lowercase().as_str() {
+            "totp" => Kind::Totp { period },
+            "hotp" => Kind::Hotp { counter },
+            _ => return Err(format!("Unknown OTP type '{}', expected totp or hotp", kind)),
+        };
+        Ok(Otp {
+            kind,
+            secret: secret.ok_or("The otpauth:// URI has no secret")?,
+            algorithm,
+            digits,
+            issuer: issuer.filter(|issuer| !issuer.is_empty()).or(label_issuer),
+            account,
+        })
+    }
+
+    /// the `otpauth://` URI describing this, e.g. to store an advanced counter
+    pub fn to_uri(&self) -> String {
+        let (kind, parameter) = match self.kind {
+            Kind::Totp { period } => ("totp", format!("period={}", period)),
+            Kind::Hotp { counter } => ("hotp", format!("counter={}", counter)),
+        };
+        let label = match &self.issuer {
+            Some(issuer) => format!("{}:{}", percent_encode(issuer), percent_encode(&self.account)),
+            None => percent_encode(&self.account),
+        };
+        let mut uri = format!("otpauth://{}/{}?secret={}", kind, label, base32_encode(&self.secret));
+        if let Some(issuer) = &self.issuer {
+            uri += &format!("&issuer={}", percent_encode(issuer));
+        }
+        format!("{}&algorithm={}&digits={}&{}", uri, self.algorithm.name(), self.digits, parameter)
+    }
+
+    /// the RFC 4226 code for `counter`
+    pub fn hotp(&self, counter: u64) -> String {
+        let hash = self.algorithm.hmac(&self.secret, &counter.to_be_bytes());
+        // dynamic truncation, 31 bits from where the last nibble points
+        let offset = (hash[hash.len() - 1] & 0xf) as usize;
+        let bits = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+        let code = bits as u64 % 10u64.pow(self.digits);
+        format!("{:0width$}", code, width = self.digits as usize)
+    }
+
+    /// the RFC 6238 code at unix time `now` and the seconds it is still valid,
+    /// counter based codes use their counter and never expire
+    pub fn code(&self, now: u64) -> (String, Option<u64>) {
+        match self.kind {
+            Kind::Totp { period } => (self.hotp(now / period), Some(period - now % period)),
+            Kind::Hotp { counter } => (self.hotp(counter), None),
+        }
+    }
+}
+
+/// RFC 4648 base32, case, spaces, dashes and padding don't matter
+pub fn base32_decode(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in text.chars().filter(|c| !matches!(c, ' ' | '-' | '=')) {
+        let value = BASE32.iter().position(|b| *b as char == c.to_ascii_uppercase())
+            .ok_or(format!("Invalid base32 character '{}' in the OTP secret", c))?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if bytes.is_empty() {
+        return Err("The OTP secret is empty".to_string());
+    }
+    Ok(bytes)
+}
+
+/// RFC 4648 base32 without padding, as otpauth:// URIs have it
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            text.push(BASE32[(buffer >> bits) as usize & 31] as char);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        text.push(BASE32[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    text
+}
+
+/// `%XX` escapes, and `+` for a space in query values like form encoding has it
+fn percent_decode(text: &str, plus_is_space: bool) -> Result<String, String> {
+    let mut bytes = Vec::new();
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        match byte {
+            b'%' => {
+                let hex = rest.get(..2).and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or(format!("Invalid escape in '{}'", text))?;
+                bytes.push(hex);
+                rest = &rest[2..];
+            }
+            b'+' if plus_is_space => bytes.push(b' '),
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| format!("Invalid UTF-8 in '{}'", text))
+}
+
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => (byte as char).to_string(),
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seed(ascii: &str, algorithm: Algorithm, digits: u32) -> Otp {
+        Otp {
+            kind: Kind::Totp { period: 30 },
+            secret: ascii.as_bytes().to_vec(),
+            algorithm,
+            digits,
+            issuer: None,
+            account: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_rfc_vectors() {
+        // RFC 4226 appendix D
+        let otp = seed("12345678901234567890", Algorithm::Sha1, 6);
+        let codes = ["755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583", "399871", "520489"];
+        for (counter, code) in codes.iter().enumerate() {
+            assert_eq!(otp.hotp(counter as u64), *code);
+        }
+
+        // RFC 6238 appendix B
+        let sha1 = seed("12345678901234567890", Algorithm::Sha1, 8);
+        let sha256 = seed("12345678901234567890123456789012", Algorithm::Sha256, 8);
+        let sha512 = seed("1234567890123456789012345678901234567890123456789012345678901234", Algorithm::Sha512, 8);
+        let vectors = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+        for (time, a, b, c) in vectors {
+            assert_eq!(sha1.code(time).0, a);
+            assert_eq!(sha256.code(time).0, b);
+            assert_eq!(sha512.code(time).0, c);
+        }
+        assert_eq!(sha1.code(59).1, Some(1));
+        assert_eq!(sha1.code(60).1, Some(30));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(base32_encode(b"12345678901234567890"), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(base32_decode("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").unwrap(), b"12345678901234567890");
+        assert_eq!(base32_decode("MZXW6===").unwrap(), b"foo");
+        assert!(base32_decode("not base32!").is_err());
+
+        // a bare seed
+        let otp = Otp::parse("JBSWY3DPEHPK3PXP").unwrap();
+        assert_eq!(otp.kind, Kind::Totp { period: 30 });
+        assert_eq!((otp.digits, otp.algorithm), (6, Algorithm::Sha1));
+
+        // as QR codes of popular sites have them: escaped label, issuer twice, extra parameters
+        let otp = Otp::parse("otpauth://totp/ACME%20Co:john.doe%40email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co&algorithm=SHA1&digits=6&period=30&image=https%3A%2F%2Fexample.com%2Flogo.png").unwrap();
+        assert_eq!(otp.issuer.as_deref(), Some("ACME Co"));
+        assert_eq!(otp.account, "john.doe@email.com");
+        assert_eq!(otp.secret, base32_decode("HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ").unwrap());
+
+        // lower case everything, padding, `+` for spaces and the issuer only in the label
+        let otp = Otp::parse("otpauth://TOTP/Big+Corp:alice?secret=mzxw6%3D%3D%3D&algorithm=sha256&digits=8&period=60").unwrap();
+        assert_eq!(otp.issuer.as_deref(), Some("Big+Corp"));
+        assert_eq!((otp.digits, otp.algorithm, otp.kind), (8, Algorithm::Sha256, Kind::Totp { period: 60 }));
+        assert_eq!(otp.secret, b"foo");
+
+        let otp = Otp::parse("otpauth://hotp/alice?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=3").unwrap();
+        assert_eq!(otp.issuer, None);
+        assert_eq!(otp.code(0), ("969429".to_string(), None));
+        assert_eq!(Otp::parse(&otp.to_uri()).unwrap(), otp);
+
+        assert!(Otp::parse("otpauth://totp/alice?issuer=x").is_err());
+        assert!(Otp::parse("otpauth://motp/alice?secret=MZXW6").is_err());
+        assert!(Otp::parse("otpauth://totp/alice?secret=MZXW6&digits=4").is_err());
+
+        let err = Otp::parse("otpauth-migration://offline?data=CjEKCkhlbGxvId6tvu8SGEV4YW1wbGU").unwrap_err();
+        assert!(err.starts_with("otpauth-migration:// URIs hold an export"));
+    }
+}
@@ -1,6 +1,184 @@
+use crate::app::Mode;
+use crate::input::Input;
+use crate::keymap::Keymap;
+
 // r# 用于创建原始字符串字面值（raw string literals）的标记。原始字符串字面值是一种特殊类型的字符串字面值，它允许你包含任意字符，包括特殊字符、换行符等，而不需要进行转义
-const CRATES_HELP: &str = r#"
-a                       cargo add
+const SECRETS_HELP: &str = r#"
+Secrets are listed as a tree, names are split into folders at `/`,
+e.g. `aws/prod/root`. A folder shows how many secrets it holds and
+opens or closes on copy (enter). Updating a folder moves everything
+in it, deleting a folder deletes everything in it. A secret named
+like the folder, e.g. `aws` next to `aws/prod`, is left alone.
+
+Marked secrets are shown with a `+`. While secrets are marked, delete,
+update, tag, export and copy names apply to all of them at once,
+each after a `y` to the same prompt.
+Tags are shown as #tag after the name.
+
+The list is sorted by name, creation, last change, last use or
+frecency, which favors secrets copied often and recently. The sort key
+cycles through them and is remembered, and secm opens with the most
+likely secret selected.
+
+Favorites are pinned above the tree as ★1, ★2, ... in the order they
+were pinned, `secm fav 2` copies the second one without the TUI.
+
+A secret can be a typed record, shown as [login], [database], ... with
+named fields besides its value: `:type login`, then `:field username
+octocat`. The copy username and copy password keys copy those fields.
+
+A secret holding an `otpauth://` URI, or a login with a `totp` field,
+shows its current one-time code and a bar counting down to the next
+one below the list. The copy one-time code key copies it, also from a
+secret holding a bare base32 seed. Counter based codes move on a step
+each time.
+
+`secm ssh-agent` serves the ssh-key secrets to ssh. Unless the config
+allows a key, each signature waits for a y or n here.
+
+Files attached with `secm attach add` are shown as `1 file`, `2 files`
+after the name. The save attachment key opens `:save` to write one to
+a new file only you can read. Deleting a secret deletes its files.
+
+Type `:` for the command line, e.g. `:gen aws/root 24 --symbols`.
+
+Related commands:
+  secm ls [prefix]             list secrets and folders under prefix
+  secm ls --favorites          list the numbered favorites
+  secm fav <n>                 copy favorite number n
+  secm get <name> [--field f]  print the value or a field of a secret
+  secm record <name> [type]    show or set the type and fields of a secret
+  secm otp <name>              print the current one-time code of a secret
+  secm ssh-key gen <name> [t]  generate an ed25519, ecdsa or rsa key
+  secm ssh-key import <n> <f>  store an OpenSSH private key file
+  secm ssh-agent               serve the ssh keys on a unix socket
+  secm attach add <n> <file>   attach an encrypted file to a secret
+  secm attach get <n> <file>   save an attachment, readable only by you
+  secm git-credential get      answer git from the login records
+  secm rm [-r] <name|prefix>   delete a secret, or a folder with -r
+  secm export [--force]        write all secrets to ./secrets.json
+  secm import                  read secrets from ./secrets.json
+  secm <text...>               save text, the first half becomes the name
+"#;
+
+const FILTER_HELP: &str = r#"
+Type to show only secrets whose full name contains the text.
+Folders holding a match are opened while filtering.
+Confirm keeps the filter, cancel clears it.
+
+Related commands:
+  secm ls [prefix]             list secrets under a folder
+"#;
+
+const MAKE_HELP: &str = r#"
+Generates a random secret and saves it under the given name.
+
+Fields:
+  name      name of the new secret, use `/` to put it in a folder
+  type      `chars` or `words`, switch with left, right or space
+  length    number of characters, 10 by default, or of words, 6
+  policy    for chars, what the secret is made of, letters only by default:
+              l u d s        lower, upper, digits, symbols, at least one
+                             of each, `d3` for at least three digits
+              -ambiguous     no look-alikes like 0 O l 1
+              forbid:<>"     never use these characters
+              alphabet:abc   only use these characters
+            e.g. `lud2s -ambiguous forbid:<>`
+  options   for words, how the passphrase looks:
+              sep:-          join the words with `-`, `sep:space` for spaces
+              case:title     lower, title, upper or random for each word
+              digit symbol   add one to the end of a random word
+            e.g. `sep:. case:title digit`
+
+The entropy line shows how many bits of randomness the secret has.
+The length and policy defaults for chars are set in [make] of the config.
+"#;
+
+const ADD_HELP: &str = r#"
+Saves a secret you already have.
+
+Fields:
+  name      name of the new secret, use `/` to put it in a folder
+  secret    the value, shown as `*` while typing. It can span lines
+            for certificates, keys or JSON: start a new line, paste
+            it, or write it in $EDITOR. It is saved exactly as typed,
+            spaces and line breaks included.
+
+The meter below the secret shows how hard it is to guess and what makes
+it weak. With `min_score` in the config, weaker secrets are refused,
+with `hibp` secrets found in the local breach dataset.
+
+Related commands:
+  secm <text...>               save text, the first half becomes the name
+  secm import                  read many secrets from ./secrets.json
+"#;
+
+const UPDATE_HELP: &str = r#"
+Changes the name and value of the selected secret. Changing the name
+keeps the tags of the secret. On a folder only the name can be changed,
+which moves every secret in it.
+
+Fields:
+  name      new name, use `/` to move it into a folder
+  secret    new value, shown as `*` while typing, can span lines
+            and be edited in $EDITOR like when adding, with the same
+            strength meter and `min_score` check
+"#;
+
+const DELETE_HELP: &str = r#"
+Type `y` and confirm to delete the selected secret or folder.
+Anything else cancels. Deleting can be undone.
+
+Related commands:
+  secm rm [-r] <name|prefix>   delete from the command line
+"#;
+
+const CONFIRM_HELP: &str = r#"
+Type `y` and confirm to delete, tag, move, export or copy the names of
+all marked secrets, as the question says. Anything else cancels.
+Everything but exporting and copying can be undone.
+"#;
+
+const TAG_HELP: &str = r#"
+Tags the marked secrets. Separate tags with spaces, `tag` or `+tag`
+adds a tag and `-tag` removes it, e.g. `prod +db -old`.
+"#;
+
+const MOVE_HELP: &str = r#"
+Moves the marked secrets into a folder, keeping the last part of their
+names: `old/github` moved to `work` becomes `work/github`. Leave it
+empty to move them to the top level.
+"#;
+
+const COMMAND_HELP: &str = r#"
+Runs a command typed after `:`. Tab completes command names, secret
+names and tags, up and down walk through earlier commands. Quote names
+with spaces, e.g. `:mv "old name" new`.
+
+Commands:
+  :add <name>                  add a secret, asks for the value
+  :gen <name> [length] [--symbols] [--words]
+                               generate a secret, 10 letters by default,
+                               --symbols (-s) adds a digit and a symbol,
+                               --words (-w) makes a 6 word passphrase
+  :mv <from> <to>              rename a secret or move a folder
+  :tag <+tag|-tag...>          tag the marked or the selected secret
+  :type <type|none>            make the selected secret a login, api-key,
+                               database, ssh-key, note or card record
+  :field <field> [value]       set a field of the selected record, an
+                               empty value removes it
+  :export [path]               write the marked or all secrets as JSON,
+                               to ./secrets.json by default, with
+                               the attached files
+  :save <attachment> [path]    save a file attached to the selected
+                               secret, to ./<attachment> by default
+  :sort <order>                order the list by name, created, modified,
+                               last-used or frecency
+  :vault <name>                switch to the vault <name>, a database
+                               kept as ~/.local/share/secm/<name>.db,
+                               `secrets` is the one secm opens with
+  :help                        this page
+  :q                           quit
 "#;
 
 // #[derive(PartialEq, Clone, Eq, Hash, Copy)] 是一个宏属性（derive attribute），它用于自动生成一些常见的 Rust 特性（traits）的实现
@@ -16,27 +194,67 @@ pub enum PanelName {
     UpdateSecret,
     AddSecret,
     DeleteSecret,
+    Confirm,
+    TagSecrets,
+    MoveSecrets,
+    Command,
 }
 
 pub struct Panel {
     pub index: usize,
     pub panel_name: PanelName,
-    pub content: Vec<String>,
+    pub content: Vec<Input>,
 }
 
 // 方法使用 self 参数，关联函数不使用 self 参数
 impl Panel {
-    pub fn get_help(&self) -> String {
+    pub fn mode(&self) -> Mode {
+        match self.panel_name {
+            PanelName::Filter => Mode::Filter,
+            PanelName::Secrets => Mode::Normal,
+            PanelName::MakeSecret => Mode::Make,
+            PanelName::AddSecret => Mode::Add,
+            PanelName::UpdateSecret => Mode::Update,
+            PanelName::DeleteSecret => Mode::Delete,
+            PanelName::Confirm => Mode::Confirm,
+            PanelName::TagSecrets => Mode::Tag,
+            PanelName::MoveSecrets => Mode::Move,
+            PanelName::Command => Mode::Command,
+        }
+    }
+
+    /// the help page of the panel, starting with the keys bound in its mode
+    pub fn get_help(&self, keymap: &Keymap) -> String {
         // 解构数组，中括号 [name, page]: [&str; 2] 的部分用于声明并解构数组，2 是数组的长度。
         let [name, page]: [&str; 2] = match self.panel_name {
-            PanelName::Filter => ["filter", CRATES_HELP],
-            PanelName::Secrets => ["secrets", CRATES_HELP],
-            PanelName::MakeSecret => ["make secret", CRATES_HELP],
-            PanelName::AddSecret => ["make secret", CRATES_HELP],
-            PanelName::UpdateSecret => ["update secret", CRATES_HELP],
-            PanelName::DeleteSecret => ["delete secret", CRATES_HELP],
+            PanelName::Filter => ["filter", FILTER_HELP],
+            PanelName::Secrets => ["secrets", SECRETS_HELP],
+            PanelName::MakeSecret => ["make secret", MAKE_HELP],
+            PanelName::AddSecret => ["add secret", ADD_HELP],
+            PanelName::UpdateSecret => ["update secret", UPDATE_HELP],
+            PanelName::DeleteSecret => ["delete secret", DELETE_HELP],
+            PanelName::Confirm => ["confirm", CONFIRM_HELP],
+            PanelName::TagSecrets => ["tag secrets", TAG_HELP],
+            PanelName::MoveSecrets => ["move secrets", MOVE_HELP],
+            PanelName::Command => ["command line", COMMAND_HELP],
         };
-        format!("This is a help page for `{}` module!\n{}", name, page)
+
+        let bindings = keymap.bindings(&self.mode());
+        let mut keys: Vec<(String, &str)> = vec![];
+        for (chord, action) in bindings {
+            // one line per action, all of its keys together
+            match keys.iter_mut().find(|(_, description)| *description == action.description()) {
+                Some((line, _)) => line.push_str(&format!(", {}", chord.display())),
+                None => keys.push((chord.display(), action.description())),
+            }
+        }
+        let width = keys.iter().map(|(line, _)| line.len()).max().unwrap_or(0);
+        let keys: Vec<String> = keys
+            .iter()
+            .map(|(line, description)| format!("  {:width$}  {}", line, description, width = width))
+            .collect();
+
+        format!("Help for `{}` ({} keys)\n\nKeys:\n{}\n{}", name, keymap.preset, keys.join("\n"), page)
     }
 
     pub fn clear_content(&mut self) {
@@ -0,0 +1,106 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::model::SecretStats;
+
+/// the order of the secret list, folders are ordered by their best secret
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum SortOrder {
+    #[default]
+    Name,
+    Created,
+    Modified,
+    LastUsed,
+    Frecency,
+}
+
+const DAY: i64 = 24 * 60 * 60;
+
+impl SortOrder {
+    pub const ALL: [SortOrder; 5] = [
+        SortOrder::Name,
+        SortOrder::Created,
+        SortOrder::Modified,
+        SortOrder::LastUsed,
+        SortOrder::Frecency,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortOrder::Name => "name",
+            SortOrder::Created => "created",
+            SortOrder::Modified => "modified",
+            SortOrder::LastUsed => "last-used",
+            SortOrder::Frecency => "frecency",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<SortOrder, String> {
+        SortOrder::ALL
+            .into_iter()
+            .find(|order| order.name() == name)
+            .ok_or(format!("Unknown sort order '{}', expected one of {}", name, SortOrder::names().join(", ")))
+    }
+
+    pub fn names() -> Vec<&'static str> {
+        SortOrder::ALL.iter().map(|order| order.name()).collect()
+    }
+
+    /// the order after this one, for cycling with a key
+    pub fn next(&self) -> SortOrder {
+        let i = SortOrder::ALL.iter().position(|order| order == self).unwrap();
+        SortOrder::ALL[(i + 1) % SortOrder::ALL.len()]
+    }
+
+    /// higher ranks come first, ties and the name order are sorted by name
+    pub fn rank(&self, stats: &SecretStats, now: i64) -> i64 {
+        match self {
+            SortOrder::Name => 0,
+            SortOrder::Created => stats.created,
+            SortOrder::Modified => stats.modified,
+            SortOrder::LastUsed => stats.last_used,
+            SortOrder::Frecency => frecency(stats, now),
+        }
+    }
+}
+
+/// how often a secret was used, weighted by how recently, like firefox ranks its history
+pub fn frecency(stats: &SecretStats, now: i64) -> i64 {
+    let weight = match (now - stats.last_used) / DAY {
+        0..=3 => 100,
+        4..=14 => 70,
+        15..=31 => 50,
+        32..=90 => 30,
+        _ => 10,
+    };
+    stats.use_count as i64 * weight
+}
+
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frecency() {
+        let now = 1_000 * DAY;
+        let daily = SecretStats { use_count: 3, last_used: now - DAY, ..Default::default() };
+        let old = SecretStats { use_count: 20, last_used: now - 200 * DAY, ..Default::default() };
+        let never = SecretStats::default();
+        assert!(frecency(&daily, now) > frecency(&old, now));
+        assert!(frecency(&old, now) > frecency(&never, now));
+        assert_eq!(frecency(&never, now), 0);
+    }
+
+    #[test]
+    fn test_cycle() {
+        assert_eq!(SortOrder::Frecency.next(), SortOrder::Name);
+        assert_eq!(SortOrder::parse("last-used").unwrap(), SortOrder::LastUsed);
+        assert!(SortOrder::parse("size").is_err());
+    }
+}
@@ -0,0 +1,419 @@
+use std::fs::{self, DirBuilder};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+use signature::{SignatureEncoding, Signer};
+use ssh_key::private::{KeypairData, RsaKeypair};
+use rsa::pkcs1v15::SigningKey;
+use rsa::BigUint;
+use ssh_key::sha2::{Sha256, Sha512};
+use ssh_key::{Algorithm, EcdsaCurve, HashAlg, LineEnding, Mpint, PrivateKey, Signature};
+
+use crate::model::{Record, RecordKind};
+use crate::Storage;
+
+pub const KEY_TYPES: [&str; 4] = ["ed25519", "ecdsa", "ecdsa-p384", "rsa"];
+const DEFAULT_RSA_BITS: usize = 3072;
+
+// message numbers of the agent protocol, draft-miller-ssh-agent
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+const SSH_AGENT_RSA_SHA2_256: u32 = 2;
+const SSH_AGENT_RSA_SHA2_512: u32 = 4;
+const SSH_MSG_USERAUTH_REQUEST: u8 = 50;
+
+/// far more than any real request needs
+const MAX_MESSAGE: usize = 256 * 1024;
+/// how often the agent looks for an answer to an approval request
+const APPROVAL_POLL: Duration = Duration::from_millis(200);
+
+/// which signatures are made without asking in a TUI first
+#[derive(Debug, Clone)]
+pub struct Policy {
+    pub ask: bool,
+    /// keys, or folders of keys, that never ask
+    pub allow: Vec<String>,
+    /// how long to wait for an answer before refusing
+    pub timeout: Duration,
+}
+
+impl Policy {
+    pub fn allows(&self, name: &str) -> bool {
+        !self.ask || self.allow.iter().any(|allowed| {
+            let folder = allowed.trim_end_matches('/');
+            name == folder || name.strip_prefix(folder).is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+}
+
+/// a new key of one of `KEY_TYPES`, only RSA keys take a size
+pub fn generate(kind: &str, bits: Option<usize>, comment: &str) -> Result<PrivateKey, String> {
+    if bits.is_some() && kind != "rsa" {
+        return Err(format!("{} keys have a fixed size", kind));
+    }
+    let key = match kind {
+        "ed25519" => PrivateKey::random(&mut OsRng, Algorithm::Ed25519),
+        "ecdsa" => PrivateKey::random(&mut OsRng, Algorithm::Ecdsa { curve: EcdsaCurve::NistP256 }),
+        "ecdsa-p384" => PrivateKey::random(&mut OsRng, Algorithm::Ecdsa { curve: EcdsaCurve::NistP384 }),
+        "rsa" => {
+            let bits = bits.unwrap_or(DEFAULT_RSA_BITS);
+            if bits < 2048 {
+                return Err("RSA keys need at least 2048 bits".to_string());
+            }
+            RsaKeypair::random(&mut OsRng, bits).and_then(|keypair| PrivateKey::new(KeypairData::from(keypair), ""))
+        }
+        _ => return Err(format!("Unknown key type '{}', expected one of {}", kind, KEY_TYPES.join(", "))),
+    };
+    let mut key = key.map_err(|e| format!("Unable to generate the key: {}", e))?;
+    key.set_comment(comment);
+    Ok(key)
+}
+
+/// an OpenSSH private key, decrypted with `passphrase` when it is encrypted
+pub fn parse_private(pem: &str, passphrase: Option<&str>) -> Result<PrivateKey, String> {
+    let key = PrivateKey::from_openssh(pem.trim()).map_err(|e| format!("Not an OpenSSH private key, `ssh-keygen -p -f <file>` converts older ones: {}", e))?;
+    if !key.is_encrypted() {
+        return Ok(key);
+    }
+    let passphrase = passphrase.ok_or("The key is encrypted and there is no passphrase")?;
+    key.decrypt(passphrase).map_err(|_| "Wrong passphrase for the key".to_string())
+}
+
+pub fn is_encrypted(pem: &str) -> bool {
+    PrivateKey::from_openssh(pem.trim()).is_ok_and(|key| key.is_encrypted())
+}
+
+/// the value and record of a secret holding `key`: the private key, and its public key in a field
+pub fn to_secret(key: &PrivateKey) -> Result<(String, Record), String> {
+    let private = key.to_openssh(LineEnding::LF).map_err(|e| e.to_string())?;
+    let public = key.public_key().to_openssh().map_err(|e| e.to_string())?;
+    let mut record = Record::new(RecordKind::SshKey);
+    record.set("public-key", &public)?;
+    Ok((private.to_string(), record))
+}
+
+/// the name of a secret holding an ssh key, and the key or what is wrong with it
+pub type Identity = (String, Result<PrivateKey, String>);
+
+/// every ssh key among the secrets, with what is wrong with the ones that can't be used
+pub fn identities<S: Storage>(storage: &S) -> Result<Vec<Identity>, String> {
+    let mut keys = vec![];
+    for (name, record) in storage.get_records()? {
+        if record.kind != RecordKind::SshKey {
+            continue;
+        }
+        let Some(value) = storage.read(&name)? else {
+            continue;
+        };
+        let passphrase = record.fields.get("passphrase").map(String::as_str);
+        keys.push((name, parse_private(&value, passphrase)));
+    }
+    keys.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(keys)
+}
+
+/// the signature of `data` in the agent's wire format. RSA keys sign with
+/// SHA-256 or SHA-512 as `flags` ask, the SHA-1 of plain ssh-rsa is refused.
+pub fn sign(key: &PrivateKey, data: &[u8], flags: u32) -> Result<Vec<u8>, String> {
+    let signature = match key.key_data() {
+        KeypairData::Rsa(keypair) if flags & SSH_AGENT_RSA_SHA2_512 != 0 => {
+            let signature = SigningKey::<Sha512>::new(rsa_key(keypair)?).try_sign(data).map_err(|e| e.to_string())?;
+            Signature::new(Algorithm::Rsa { hash: Some(HashAlg::Sha512) }, signature.to_vec())
+        }
+        KeypairData::Rsa(keypair) if flags & SSH_AGENT_RSA_SHA2_256 != 0 => {
+            let signature = SigningKey::<Sha256>::new(rsa_key(keypair)?).try_sign(data).map_err(|e| e.to_string())?;
+            Signature::new(Algorithm::Rsa { hash: Some(HashAlg::Sha256) }, signature.to_vec())
+        }
+        KeypairData::Rsa(_) => {
+            return Err("ssh-rsa signatures use SHA-1, only rsa-sha2-256 and rsa-sha2-512 are made".to_string());
+        }
+        _ => key.try_sign(data).map_err(|e| e.into()),
+    };
+    let signature = signature.map_err(|e: ssh_key::Error| format!("Unable to sign: {}", e))?;
+    let mut encoded = vec![];
+    put_string(&mut encoded, signature.algorithm().as_str().as_bytes());
+    put_string(&mut encoded, signature.as_bytes());
+    Ok(encoded)
+}
+
+/// the key as the rsa crate has it, built here as ssh-key 0.6 puts p where q belongs
+fn rsa_key(keypair: &RsaKeypair) -> Result<rsa::RsaPrivateKey, String> {
+    let int = |mpint: &Mpint| mpint.as_positive_bytes().map(BigUint::from_bytes_be).ok_or("Invalid RSA key");
+    let primes = vec![int(&keypair.private.p)?, int(&keypair.private.q)?];
+    rsa::RsaPrivateKey::from_components(int(&keypair.public.n)?, int(&keypair.public.e)?, int(&keypair.private.d)?, primes)
+        .map_err(|e| format!("Invalid RSA key: {}", e))
+}
+
+/// what a client wants signed, for the approval prompt and the log
+pub fn describe(data: &[u8]) -> String {
+    if let Some(rest) = data.strip_prefix(b"SSHSIG") {
+        let namespace = Reader(rest).string().map(String::from_utf8_lossy);
+        return match namespace {
+            Ok(namespace) => format!("a {} signature", namespace),
+            Err(_) => "a signature".to_string(),
+        };
+    }
+    // a publickey user authentication, RFC 4252 section 7
+    let mut reader = Reader(data);
+    let user = reader.string()
+        .and_then(|_| reader.byte())
+        .and_then(|kind| match kind {
+            SSH_MSG_USERAUTH_REQUEST => reader.string(),
+            _ => Err(String::new()),
+        });
+    match user {
+        Ok(user) => format!("ssh login as {}", String::from_utf8_lossy(user)),
+        Err(_) => format!("{} bytes of data", data.len()),
+    }
+}
+
+/// listen at `socket`, taking over the file of an agent that is gone.
+/// Only the user can reach the socket.
+pub fn listen(socket: &Path) -> Result<UnixListener, String> {
+    let dir = match socket.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.exists() {
+        DirBuilder::new().recursive(true).mode(0o700).create(dir)
+            .map_err(|e| format!("Unable to create {}: {}", dir.display(), e))?;
+    }
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(format!("An agent is listening at {} already", socket.display()));
+        }
+        fs::remove_file(socket).map_err(|e| format!("Unable to remove {}: {}", socket.display(), e))?;
+    }
+    // bind in a directory only the user can enter and move the socket out once it is
+    // 0600, a socket bound in place is open to anyone the umask lets in until chmod
+    let private = dir.join(format!(".secm-agent-{:08x}", OsRng.next_u32()));
+    DirBuilder::new().mode(0o700).create(&private)
+        .map_err(|e| format!("Unable to create {}: {}", private.display(), e))?;
+    let bound = private.join("agent.sock");
+    let listener = UnixListener::bind(&bound)
+        .and_then(|listener| fs::set_permissions(&bound, fs::Permissions::from_mode(0o600)).map(|_| listener))
+        .and_then(|listener| fs::rename(&bound, socket).map(|_| listener))
+        .map_err(|e| format!("Unable to listen at {}: {}", socket.display(), e));
+    let _ = fs::remove_file(&bound);
+    let _ = fs::remove_dir(&private);
+    listener
+}
+
+/// answer the requests of one client until it hangs up
+pub fn serve<S: Storage>(stream: &mut (impl Read + Write), storage: &S, policy: &Policy) -> Result<(), String> {
+    loop {
+        let mut length = [0; 4];
+        match stream.read_exact(&mut length) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.to_string()),
+        }
+        let length = u32::from_be_bytes(length) as usize;
+        if length == 0 || length > MAX_MESSAGE {
+            return Err(format!("Invalid message length {}", length));
+        }
+        let mut message = vec![0; length];
+        stream.read_exact(&mut message).map_err(|e| e.to_string())?;
+        let response = respond(storage, policy, &message).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            vec![SSH_AGENT_FAILURE]
+        });
+        stream.write_all(&(response.len() as u32).to_be_bytes()).map_err(|e| e.to_string())?;
+        stream.write_all(&response).map_err(|e| e.to_string())?;
+    }
+}
+
+fn respond<S: Storage>(storage: &S, policy: &Policy, message: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = Reader(message);
+    let usable = || -> Result<Vec<(String, PrivateKey)>, String> {
+        Ok(identities(storage)?.into_iter().filter_map(|(name, key)| Some((name, key.ok()?))).collect())
+    };
+    match reader.byte()? {
+        SSH_AGENTC_REQUEST_IDENTITIES => {
+            let keys = usable()?;
+            let mut response = vec![SSH_AGENT_IDENTITIES_ANSWER];
+            response.extend((keys.len() as u32).to_be_bytes());
+            for (name, key) in keys {
+                put_string(&mut response, &key.public_key().to_bytes().map_err(|e| e.to_string())?);
+                put_string(&mut response, name.as_bytes());
+            }
+            Ok(response)
+        }
+        SSH_AGENTC_SIGN_REQUEST => {
+            let (blob, data, flags) = (reader.string()?, reader.string()?, reader.u32()?);
+            let (name, key) = usable()?
+                .into_iter()
+                .find(|(_, key)| key.public_key().to_bytes().is_ok_and(|public| public == blob))
+                .ok_or("Asked to sign with a key secm doesn't have")?;
+            let what = describe(data);
+            if !approve(storage, policy, &name, &what)? {
+                return Err(format!("Refused {} with {}", what, name));
+            }
+            let signature = sign(&key, data, flags)?;
+            storage.record_use(&name)?;
+            eprintln!("Signed {} with {}", what, name);
+            let mut response = vec![SSH_AGENT_SIGN_RESPONSE];
+            put_string(&mut response, &signature);
+            Ok(response)
+        }
+        // adding keys, locking, extensions, ... the keys are managed through secm
+        _ => Ok(vec![SSH_AGENT_FAILURE]),
+    }
+}
+
+/// allowed by the policy, or by whoever answers first in a TUI before the timeout
+fn approve<S: Storage>(storage: &S, policy: &Policy, name: &str, what: &str) -> Result<bool, String> {
+    if policy.allows(name) {
+        return Ok(true);
+    }
+    eprintln!("Waiting for a secm TUI to allow {} with {}", what, name);
+    let id = storage.request_approval(name, what)?;
+    let deadline = Instant::now() + policy.timeout;
+    while Instant::now() < deadline {
+        if let Some(allow) = storage.take_approval(id)? {
+            return Ok(allow);
+        }
+        thread::sleep(APPROVAL_POLL);
+    }
+    // nobody answered, take the request back
+    storage.decide_approval(id, false)?;
+    storage.take_approval(id)?;
+    Ok(false)
+}
+
+fn put_string(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    buffer.extend((bytes.len() as u32).to_be_bytes());
+    buffer.extend(bytes);
+}
+
+/// reads the integers and length prefixed strings of ssh messages
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.0.len() < n {
+            return Err("Truncated ssh-agent message".to_string());
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<&'a [u8], String> {
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ssh_key::PublicKey;
+
+    use super::*;
+    use crate::storage::SqliteStorage;
+
+    fn request(kind: u8, parts: &[&[u8]], flags: Option<u32>) -> Vec<u8> {
+        let mut message = vec![kind];
+        for part in parts {
+            put_string(&mut message, part);
+        }
+        message.extend(flags.map(u32::to_be_bytes).unwrap_or_default());
+        message
+    }
+
+    #[test]
+    fn test_agent() {
+        let storage = SqliteStorage::new(":memory:").unwrap();
+        for (name, kind, bits) in [("ssh/ed", "ed25519", None), ("ssh/ec", "ecdsa", None), ("ssh/rsa", "rsa", Some(2048))] {
+            let (value, record) = to_secret(&generate(kind, bits, name).unwrap()).unwrap();
+            storage.write(name, &value).unwrap();
+            storage.set_record(name, Some(&record)).unwrap();
+        }
+        storage.write("ssh/broken", "not a key").unwrap();
+        storage.set_record("ssh/broken", Some(&Record::new(RecordKind::SshKey))).unwrap();
+
+        let policy = Policy { ask: true, allow: vec!["ssh/".to_string()], timeout: Duration::ZERO };
+        let answer = respond(&storage, &policy, &[SSH_AGENTC_REQUEST_IDENTITIES]).unwrap();
+        let mut reader = Reader(&answer);
+        assert_eq!(reader.byte().unwrap(), SSH_AGENT_IDENTITIES_ANSWER);
+        let keys: Vec<(&[u8], &[u8])> = (0..reader.u32().unwrap())
+            .map(|_| (reader.string().unwrap(), reader.string().unwrap()))
+            .collect();
+        let names: Vec<&[u8]> = keys.iter().map(|(_, name)| *name).collect();
+        assert_eq!(names, [b"ssh/ec".as_slice(), b"ssh/ed", b"ssh/rsa"]);
+
+        // what a login asks for, signed by every key and checked against the public key
+        let mut login = vec![];
+        put_string(&mut login, b"session");
+        login.push(SSH_MSG_USERAUTH_REQUEST);
+        put_string(&mut login, b"git");
+        assert_eq!(describe(&login), "ssh login as git");
+        for flags in [0, SSH_AGENT_RSA_SHA2_256, SSH_AGENT_RSA_SHA2_512] {
+            for (blob, name) in &keys {
+                let response = respond(&storage, &policy, &request(SSH_AGENTC_SIGN_REQUEST, &[blob, &login], Some(flags)));
+                if *name == b"ssh/rsa" && flags == 0 {
+                    assert!(response.is_err());
+                    continue;
+                }
+                let response = response.unwrap();
+                assert_eq!(response[0], SSH_AGENT_SIGN_RESPONSE);
+                let mut signature = Reader(Reader(&response[1..]).string().unwrap());
+                let algorithm = Algorithm::new(std::str::from_utf8(signature.string().unwrap()).unwrap()).unwrap();
+                let signature = Signature::new(algorithm, signature.string().unwrap()).unwrap();
+                let public = PublicKey::from_bytes(blob).unwrap();
+                assert!(signature::Verifier::verify(public.key_data(), &login, &signature).is_ok());
+            }
+        }
+        assert_eq!(storage.get_stats().unwrap().iter().find(|(name, _)| name == "ssh/ed").unwrap().1.use_count, 3);
+
+        // keys outside `allow` wait for an answer, refused once nobody answers in time
+        let policy = Policy { allow: vec!["ssh/e".to_string()], ..policy };
+        assert!(policy.allows("ssh/e") && !policy.allows("ssh/ed"));
+        assert!(respond(&storage, &policy, &request(SSH_AGENTC_SIGN_REQUEST, &[keys[1].0, b"SSHSIG"], Some(0))).is_err());
+        assert!(storage.get_approvals().unwrap().is_empty());
+
+        let id = storage.request_approval("ssh/ed", "a file signature").unwrap();
+        assert_eq!(storage.get_approvals().unwrap()[0].what, "a file signature");
+        assert_eq!(storage.take_approval(id).unwrap(), None);
+        storage.decide_approval(id, true).unwrap();
+        storage.decide_approval(id, false).unwrap();
+        assert_eq!(storage.take_approval(id).unwrap(), Some(true));
+        assert_eq!(storage.take_approval(id).unwrap(), None);
+    }
+
+    #[test]
+    fn test_listen() {
+        let dir = std::env::temp_dir().join(format!("secm-agent-{}", std::process::id()));
+        let socket = dir.join("agent.sock");
+        let listener = listen(&socket).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+        assert_eq!(fs::metadata(&socket).unwrap().permissions().mode() & 0o777, 0o600);
+        // the private directory it was bound in is gone
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        assert!(UnixStream::connect(&socket).is_ok());
+        assert!(listen(&socket).is_err());
+
+        // the file of an agent that is gone is taken over
+        drop(listener);
+        assert!(listen(&socket).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,9 +1,33 @@
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use std::cell::OnceCell;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use rand::rngs::OsRng;
+use rand::RngCore;
 use rusqlite::{params, Connection, OptionalExtension};
 
+use crate::attachment::{self, Sealer};
+use crate::model::{Approval, Attachment, Record, SealedAttachment, SecretStats};
 use crate::Storage;
 
+/// the current time as unix seconds, in SQL
+const NOW: &str = "CAST(strftime('%s', 'now') AS INTEGER)";
+
+/// the setting holding the highest attachment id ever used, deleted attachments
+/// keep their ids so undoing the delete can put their sealed chunks back
+const LAST_ATTACHMENT_KEY: &str = "last_attachment";
+
+/// the setting holding the key of the value fingerprints
+const FINGERPRINT_KEY: &str = "fingerprint_key";
+
 pub struct SqliteStorage {
     conn: Connection,
+    fingerprint_key: Vec<u8>,
+    // from the keychain, only once attachments are used
+    sealer: OnceCell<Sealer>,
 }
 
 impl SqliteStorage {
@@ -14,16 +38,164 @@ impl SqliteStorage {
             [],
         )
         .map_err(|e| e.to_string())?;
-        Ok(Self { conn })
+        // databases created by older versions lack the later columns,
+        // their secrets get 0 for times they don't know
+        let columns = [
+            ("tags", "TEXT NOT NULL DEFAULT ''"),
+            ("created", "INTEGER NOT NULL DEFAULT 0"),
+            ("modified", "INTEGER NOT NULL DEFAULT 0"),
+            ("last_used", "INTEGER NOT NULL DEFAULT 0"),
+            ("use_count", "INTEGER NOT NULL DEFAULT 0"),
+            // 0 for other secrets, favorites count up in the order they were pinned
+            ("favorite", "INTEGER NOT NULL DEFAULT 0"),
+            // keyed hash of the value, equal values have equal fingerprints
+            ("fingerprint", "TEXT NOT NULL DEFAULT ''"),
+            // the other fields of a typed secret as JSON, '' for plain secrets
+            ("record", "TEXT NOT NULL DEFAULT ''"),
+        ];
+        for (column, definition) in columns {
+            let exists = conn
+                .prepare(&format!("SELECT {} FROM secrets LIMIT 0", column))
+                .is_ok();
+            if !exists {
+                conn.execute(&format!("ALTER TABLE secrets ADD COLUMN {} {}", column, definition), [])
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS settings (key TEXT PRIMARY KEY, value TEXT NOT NULL)",
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+        // signing requests of `secm ssh-agent`, decision is NULL until a TUI answers
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS approvals (id INTEGER PRIMARY KEY, key TEXT NOT NULL, what TEXT NOT NULL, decision INTEGER)",
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+
+        // files attached to secrets, encrypted in chunks of `attachment::CHUNK_SIZE`
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS attachments (id INTEGER PRIMARY KEY, secret TEXT NOT NULL, name TEXT NOT NULL, \
+             size INTEGER NOT NULL, created INTEGER NOT NULL, UNIQUE(secret, name))",
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS attachment_chunks (attachment INTEGER NOT NULL, seq INTEGER NOT NULL, \
+             last INTEGER NOT NULL, data BLOB NOT NULL, PRIMARY KEY(attachment, seq))",
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+
+        let mut storage = Self { conn, fingerprint_key: vec![], sealer: OnceCell::new() };
+        storage.fingerprint_key = match storage.get_setting(FINGERPRINT_KEY)? {
+            Some(key) => hex_decode(&key).ok_or("Invalid fingerprint key in settings")?,
+            None => {
+                let mut key = vec![0; 32];
+                OsRng.fill_bytes(&mut key);
+                storage.set_setting(FINGERPRINT_KEY, &hex_encode(&key))?;
+                key
+            }
+        };
+        storage.fingerprint_missing()?;
+        Ok(storage)
+    }
+
+    /// HMAC-SHA256 of `value` under the key of this database, so fingerprints can't
+    /// be looked up in tables of known hashes or matched with other databases
+    fn fingerprint(&self, value: &str) -> String {
+        let mut mac = Hmac::new(Sha256::new(), &self.fingerprint_key);
+        mac.input(value.as_bytes());
+        hex_encode(mac.result().code())
+    }
+
+    #[cfg(not(test))]
+    fn sealer(&self) -> &Sealer {
+        self.sealer.get_or_init(Sealer::from_keychain)
+    }
+
+    // tests of any module use a fixed key and never reach the keychain
+    #[cfg(test)]
+    fn sealer(&self) -> &Sealer {
+        self.sealer.get_or_init(|| Sealer::new(b"test key"))
+    }
+
+    /// run `f` in a savepoint, which unlike a transaction also works inside `atomic`
+    fn savepoint<T>(&self, f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+        self.conn.execute_batch("SAVEPOINT secm").map_err(|e| e.to_string())?;
+        let result = f();
+        let end = match result {
+            Ok(_) => "RELEASE secm",
+            Err(_) => "ROLLBACK TO secm; RELEASE secm",
+        };
+        self.conn.execute_batch(end).map_err(|e| e.to_string())?;
+        result
     }
+
+    /// delete the attachments of the secrets `secrets` selects, `?1` is the parameter
+    fn delete_attachments_of(&self, secrets: &str, param: &str) -> Result<(), String> {
+        self.conn
+            .execute(
+                &format!(
+                    "DELETE FROM attachment_chunks WHERE attachment IN (SELECT id FROM attachments WHERE {})",
+                    secrets
+                ),
+                params![param],
+            )
+            .map_err(|e| e.to_string())?;
+        self.conn
+            .execute(&format!("DELETE FROM attachments WHERE {}", secrets), params![param])
+            .map_err(|e| e.to_string())
+            .map(|_| ())
+    }
+
+    /// secrets saved by versions without fingerprints get theirs
+    fn fingerprint_missing(&self) -> Result<(), String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, value FROM secrets WHERE fingerprint = ''")
+            .map_err(|e| e.to_string())?;
+        let missing = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<(String, String)>, _>>()
+            .map_err(|e| e.to_string())?;
+        for (name, value) in missing {
+            self.conn
+                .execute("UPDATE secrets SET fingerprint = ?1 WHERE name = ?2", params![self.fingerprint(&value), name])
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
 }
 
 impl Storage for SqliteStorage {
+    fn open(path: &Path) -> Result<Self, String> {
+        SqliteStorage::new(path.to_str().ok_or("Database path contains invalid UTF-8")?)
+    }
+
     fn write(&self, key: &str, value: &str) -> Result<(), String> {
         self.conn
             .execute(
-                "INSERT INTO secrets (name, value) VALUES (?1, ?2) ON CONFLICT(name) DO UPDATE SET value = excluded.value",
-                params![key, value],
+                &format!(
+                    "INSERT INTO secrets (name, value, fingerprint, created, modified) VALUES (?1, ?2, ?3, {now}, {now}) \
+                     ON CONFLICT(name) DO UPDATE SET value = excluded.value, fingerprint = excluded.fingerprint, \
+                     modified = excluded.modified",
+                    now = NOW,
+                ),
+                params![key, value, self.fingerprint(value)],
             )
             .map_err(|e| e.to_string())?;
         Ok(())
@@ -44,8 +216,8 @@ impl Storage for SqliteStorage {
         let rows_updated = self
             .conn
             .execute(
-                "UPDATE secrets SET value = ?1 WHERE name = ?2",
-                params![value, key],
+                &format!("UPDATE secrets SET value = ?1, fingerprint = ?3, modified = {} WHERE name = ?2", NOW),
+                params![value, key, self.fingerprint(value)],
             )
             .map_err(|e| e.to_string())?;
         
@@ -74,10 +246,557 @@ impl Storage for SqliteStorage {
     }
 
     fn delete(&self, key: &str) -> Result<(), String> {
+        self.savepoint(|| {
+            self.delete_attachments_of("secret = ?1", key)?;
+            self.conn
+                .execute("DELETE FROM secrets WHERE name = ?1", params![key])
+                .map_err(|e| e.to_string())
+                .map(|_| ())
+        })
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<(), String> {
+        self.savepoint(|| {
+            let rows_updated = self
+                .conn
+                .execute(
+                    "UPDATE secrets SET name = ?1 WHERE name = ?2",
+                    params![to, from],
+                )
+                .map_err(|e| e.to_string())?;
+
+            if rows_updated == 0 {
+                return Err(format!("Key '{}' does not exist", from));
+            }
+            self.conn
+                .execute("UPDATE attachments SET secret = ?1 WHERE secret = ?2", params![to, from])
+                .map_err(|e| e.to_string())
+                .map(|_| ())
+        })
+    }
+
+    fn rename_prefix(&self, from: &str, to: &str) -> Result<usize, String> {
+        // the primary key rejects collisions, the savepoint undoes the whole move then
+        self.savepoint(|| {
+            self.conn
+                .execute(
+                    "UPDATE attachments SET secret = ?2 || substr(secret, length(?1) + 1) \
+                     WHERE substr(secret, 1, length(?1) + 1) = ?1 || '/'",
+                    params![from, to],
+                )
+                .map_err(|e| e.to_string())?;
+            self.conn
+                .execute(
+                    "UPDATE secrets SET name = ?2 || substr(name, length(?1) + 1) WHERE substr(name, 1, length(?1) + 1) = ?1 || '/'",
+                    params![from, to],
+                )
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn delete_prefix(&self, prefix: &str) -> Result<usize, String> {
+        self.savepoint(|| {
+            self.delete_attachments_of("substr(secret, 1, length(?1) + 1) = ?1 || '/'", prefix)?;
+            self.conn
+                .execute(
+                    "DELETE FROM secrets WHERE substr(name, 1, length(?1) + 1) = ?1 || '/'",
+                    params![prefix],
+                )
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn get_tags(&self) -> Result<Vec<(String, Vec<String>)>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, tags FROM secrets WHERE tags != ''")
+            .map_err(|e| e.to_string())?;
+        let results = stmt
+            .query_map([], |row| {
+                let key: String = row.get(0)?;
+                let tags: String = row.get(1)?;
+                Ok((key, tags.split(',').map(|t| t.to_string()).collect()))
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<(String, Vec<String>)>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(results)
+    }
+
+    fn set_tags(&self, key: &str, tags: &[String]) -> Result<(), String> {
         self.conn
-            .execute("DELETE FROM secrets WHERE name = ?1", params![key])
+            .execute(
+                "UPDATE secrets SET tags = ?1 WHERE name = ?2",
+                params![tags.join(","), key],
+            )
+            .map_err(|e| e.to_string())
+            .map(|_| ())
+    }
+
+    fn record_use(&self, key: &str) -> Result<(), String> {
+        self.conn
+            .execute(
+                &format!("UPDATE secrets SET use_count = use_count + 1, last_used = {} WHERE name = ?1", NOW),
+                params![key],
+            )
             .map_err(|e| e.to_string())
             .map(|_| ())
     }
+
+    fn get_stats(&self) -> Result<Vec<(String, SecretStats)>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, created, modified, last_used, use_count FROM secrets")
+            .map_err(|e| e.to_string())?;
+        let results = stmt
+            .query_map([], |row| {
+                let key: String = row.get(0)?;
+                let stats = SecretStats {
+                    created: row.get(1)?,
+                    modified: row.get(2)?,
+                    last_used: row.get(3)?,
+                    use_count: row.get(4)?,
+                };
+                Ok((key, stats))
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<(String, SecretStats)>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(results)
+    }
+
+    fn get_reused(&self) -> Result<Vec<Vec<String>>, String> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT fingerprint, name FROM secrets WHERE fingerprint IN \
+                 (SELECT fingerprint FROM secrets GROUP BY fingerprint HAVING COUNT(*) > 1) \
+                 AND fingerprint != ?1 ORDER BY fingerprint, name",
+            )
+            .map_err(|e| e.to_string())?;
+        // cleared values, like an erased git password, aren't reused passwords
+        let rows = stmt
+            .query_map(params![self.fingerprint("")], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<(String, String)>, _>>()
+            .map_err(|e| e.to_string())?;
+        let mut groups: Vec<Vec<String>> = vec![];
+        let mut last = None;
+        for (fingerprint, name) in rows {
+            match groups.last_mut() {
+                Some(group) if last.as_ref() == Some(&fingerprint) => group.push(name),
+                _ => groups.push(vec![name]),
+            }
+            last = Some(fingerprint);
+        }
+        // the order of the fingerprints means nothing, list by name
+        groups.sort();
+        Ok(groups)
+    }
+
+    fn names_with_value(&self, value: &str) -> Result<Vec<String>, String> {
+        if value.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name FROM secrets WHERE fingerprint = ?1 ORDER BY name")
+            .map_err(|e| e.to_string())?;
+        let results = stmt
+            .query_map(params![self.fingerprint(value)], |row| row.get(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(results)
+    }
+
+    fn get_records(&self) -> Result<Vec<(String, Record)>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, record FROM secrets WHERE record != ''")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<(String, String)>, _>>()
+            .map_err(|e| e.to_string())?;
+        rows.into_iter()
+            .map(|(name, record)| {
+                let record = serde_json::from_str(&record).map_err(|e| format!("Invalid record of {}: {}", name, e))?;
+                Ok((name, record))
+            })
+            .collect()
+    }
+
+    fn set_record(&self, key: &str, record: Option<&Record>) -> Result<(), String> {
+        let record = match record {
+            Some(record) => serde_json::to_string(record).map_err(|e| e.to_string())?,
+            None => String::new(),
+        };
+        self.conn
+            .execute("UPDATE secrets SET record = ?1 WHERE name = ?2", params![record, key])
+            .map_err(|e| e.to_string())
+            .map(|_| ())
+    }
+
+    fn get_favorites(&self) -> Result<Vec<String>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name FROM secrets WHERE favorite > 0 ORDER BY favorite")
+            .map_err(|e| e.to_string())?;
+        let results = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(results)
+    }
+
+    fn set_favorite(&self, key: &str, favorite: bool) -> Result<(), String> {
+        let sql = if favorite {
+            // a secret pinned again keeps its place
+            "UPDATE secrets SET favorite = (SELECT MAX(favorite) + 1 FROM secrets) WHERE name = ?1 AND favorite = 0"
+        } else {
+            "UPDATE secrets SET favorite = 0 WHERE name = ?1"
+        };
+        self.conn
+            .execute(sql, params![key])
+            .map_err(|e| e.to_string())
+            .map(|_| ())
+    }
+
+    fn get_setting(&self, key: &str) -> Result<Option<String>, String> {
+        self.conn
+            .query_row("SELECT value FROM settings WHERE key = ?1", params![key], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())
+    }
+
+    fn set_setting(&self, key: &str, value: &str) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO settings (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                params![key, value],
+            )
+            .map_err(|e| e.to_string())
+            .map(|_| ())
+    }
+
+    fn request_approval(&self, key: &str, what: &str) -> Result<i64, String> {
+        self.conn
+            .execute("INSERT INTO approvals (key, what) VALUES (?1, ?2)", params![key, what])
+            .map_err(|e| e.to_string())?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    fn get_approvals(&self) -> Result<Vec<Approval>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, key, what FROM approvals WHERE decision IS NULL ORDER BY id")
+            .map_err(|e| e.to_string())?;
+        let results = stmt
+            .query_map([], |row| Ok(Approval { id: row.get(0)?, key: row.get(1)?, what: row.get(2)? }))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<Approval>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(results)
+    }
+
+    fn decide_approval(&self, id: i64, allow: bool) -> Result<(), String> {
+        self.conn
+            .execute("UPDATE approvals SET decision = ?1 WHERE id = ?2 AND decision IS NULL", params![allow, id])
+            .map_err(|e| e.to_string())
+            .map(|_| ())
+    }
+
+    fn take_approval(&self, id: i64) -> Result<Option<bool>, String> {
+        let decision: Option<bool> = self
+            .conn
+            .query_row("SELECT decision FROM approvals WHERE id = ?1", params![id], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?
+            .flatten();
+        if decision.is_some() {
+            self.conn
+                .execute("DELETE FROM approvals WHERE id = ?1", params![id])
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(decision)
+    }
+
+    fn add_attachment(&self, key: &str, name: &str, data: &mut dyn Read, max_size: u64) -> Result<u64, String> {
+        attachment::check_name(name)?;
+        if self.read(key)?.is_none() {
+            return Err(format!("Key '{}' does not exist", key));
+        }
+        let sealer = self.sealer();
+        self.savepoint(|| {
+            self.delete_attachment(key, name).ok();
+            let last_id: i64 = self.get_setting(LAST_ATTACHMENT_KEY)?.and_then(|id| id.parse().ok()).unwrap_or(0);
+            self.conn
+                .execute(
+                    &format!(
+                        "INSERT INTO attachments (id, secret, name, size, created) \
+                         SELECT max(coalesce(max(id), 0), ?3) + 1, ?1, ?2, 0, {} FROM attachments",
+                        NOW
+                    ),
+                    params![key, name, last_id],
+                )
+                .map_err(|e| e.to_string())?;
+            let id = self.conn.last_insert_rowid();
+            self.set_setting(LAST_ATTACHMENT_KEY, &id.to_string())?;
+
+            // read one chunk ahead, the last one is marked so a cut off attachment can't pass as whole
+            let mut chunk = vec![];
+            let mut next = vec![];
+            attachment::read_chunk(data, &mut chunk)?;
+            let mut size = 0;
+            for seq in 0.. {
+                let last = chunk.len() < attachment::CHUNK_SIZE || {
+                    attachment::read_chunk(data, &mut next)?;
+                    next.is_empty()
+                };
+                size += chunk.len() as u64;
+                if size > max_size {
+                    return Err(format!("{} is larger than the limit of {}", name, attachment::format_size(max_size)));
+                }
+                self.conn
+                    .execute(
+                        "INSERT INTO attachment_chunks (attachment, seq, last, data) VALUES (?1, ?2, ?3, ?4)",
+                        params![id, seq, last, sealer.seal(id, seq, last, &chunk)?],
+                    )
+                    .map_err(|e| e.to_string())?;
+                if last {
+                    break;
+                }
+                std::mem::swap(&mut chunk, &mut next);
+            }
+            self.conn
+                .execute("UPDATE attachments SET size = ?1 WHERE id = ?2", params![size as i64, id])
+                .map_err(|e| e.to_string())?;
+            Ok(size)
+        })
+    }
+
+    fn get_attachments(&self) -> Result<Vec<(String, Attachment)>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT secret, name, size, created FROM attachments ORDER BY secret, name")
+            .map_err(|e| e.to_string())?;
+        let results = stmt
+            .query_map([], |row| {
+                let size: i64 = row.get(2)?;
+                Ok((row.get(0)?, Attachment { name: row.get(1)?, size: size as u64, created: row.get(3)? }))
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<(String, Attachment)>, _>>()
+            .map_err(|e| e.to_string())?;
+        Ok(results)
+    }
+
+    fn read_attachment(&self, key: &str, name: &str, out: &mut dyn Write) -> Result<u64, String> {
+        let (id, size): (i64, i64) = self
+            .conn
+            .query_row(
+                "SELECT id, size FROM attachments WHERE secret = ?1 AND name = ?2",
+                params![key, name],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?
+            .ok_or(format!("{} has no attachment '{}'", key, name))?;
+        let sealer = self.sealer();
+        let damaged = || format!("Attachment '{}' of {} is damaged", name, key);
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT seq, last, data FROM attachment_chunks WHERE attachment = ?1 ORDER BY seq")
+            .map_err(|e| e.to_string())?;
+        let mut rows = stmt.query(params![id]).map_err(|e| e.to_string())?;
+        let mut written = 0;
+        let mut complete = false;
+        let mut expected = 0;
+        while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+            let seq: i64 = row.get(0).map_err(|e| e.to_string())?;
+            let last: bool = row.get(1).map_err(|e| e.to_string())?;
+            let data: Vec<u8> = row.get(2).map_err(|e| e.to_string())?;
+            if complete || seq != expected {
+                return Err(damaged());
+            }
+            let chunk = sealer.open(id, seq, last, &data).map_err(|_| damaged())?;
+            out.write_all(&chunk).map_err(|e| format!("Unable to write attachment: {}", e))?;
+            written += chunk.len() as u64;
+            complete = last;
+            expected += 1;
+        }
+        if !complete || written != size as u64 {
+            return Err(damaged());
+        }
+        out.flush().map_err(|e| format!("Unable to write attachment: {}", e))?;
+        Ok(written)
+    }
+
+    fn delete_attachment(&self, key: &str, name: &str) -> Result<(), String> {
+        let id: i64 = self
+            .conn
+            .query_row(
+                "SELECT id FROM attachments WHERE secret = ?1 AND name = ?2",
+                params![key, name],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?
+            .ok_or(format!("{} has no attachment '{}'", key, name))?;
+        self.savepoint(|| {
+            self.conn
+                .execute("DELETE FROM attachment_chunks WHERE attachment = ?1", params![id])
+                .map_err(|e| e.to_string())?;
+            self.conn
+                .execute("DELETE FROM attachments WHERE id = ?1", params![id])
+                .map_err(|e| e.to_string())
+                .map(|_| ())
+        })
+    }
+
+    fn get_sealed_attachments(&self, key: &str) -> Result<Vec<SealedAttachment>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, name, size, created FROM attachments WHERE secret = ?1 ORDER BY name")
+            .map_err(|e| e.to_string())?;
+        let mut attachments = stmt
+            .query_map(params![key], |row| {
+                let size: i64 = row.get(2)?;
+                Ok(SealedAttachment { id: row.get(0)?, name: row.get(1)?, size: size as u64, created: row.get(3)?, chunks: vec![] })
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        let mut stmt = self
+            .conn
+            .prepare("SELECT seq, last, data FROM attachment_chunks WHERE attachment = ?1 ORDER BY seq")
+            .map_err(|e| e.to_string())?;
+        for attachment in &mut attachments {
+            attachment.chunks = stmt
+                .query_map(params![attachment.id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .map_err(|e| e.to_string())?
+                .collect::<Result<_, _>>()
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(attachments)
+    }
+
+    fn restore_attachments(&self, key: &str, attachments: &[SealedAttachment]) -> Result<(), String> {
+        if self.read(key)?.is_none() {
+            return Err(format!("Key '{}' does not exist", key));
+        }
+        self.savepoint(|| {
+            for attachment in attachments {
+                self.conn
+                    .execute(
+                        "INSERT INTO attachments (id, secret, name, size, created) VALUES (?1, ?2, ?3, ?4, ?5)",
+                        params![attachment.id, key, attachment.name, attachment.size as i64, attachment.created],
+                    )
+                    .map_err(|e| format!("Unable to restore {}: {}", attachment.name, e))?;
+                for (seq, last, data) in &attachment.chunks {
+                    self.conn
+                        .execute(
+                            "INSERT INTO attachment_chunks (attachment, seq, last, data) VALUES (?1, ?2, ?3, ?4)",
+                            params![attachment.id, seq, last, data],
+                        )
+                        .map_err(|e| format!("Unable to restore {}: {}", attachment.name, e))?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn data_version(&self) -> Result<i64, String> {
+        // sqlite only counts commits made through other connections
+        self.conn
+            .query_row("PRAGMA data_version", [], |row| row.get(0))
+            .map_err(|e| e.to_string())
+    }
+
+    fn atomic(&self, f: &mut dyn FnMut(&Self) -> Result<(), String>) -> Result<(), String> {
+        // dropping the transaction without commit rolls it back
+        let tx = self.conn.unchecked_transaction().map_err(|e| e.to_string())?;
+        f(self)?;
+        tx.commit().map_err(|e| e.to_string())
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reused() {
+        let storage = SqliteStorage::new(":memory:").unwrap();
+        storage.write("db/prod", "hunter2").unwrap();
+        storage.write("db/staging", "hunter2").unwrap();
+        storage.write("github", "other").unwrap();
+        storage.write("gitlab", "other").unwrap();
+        storage.write("npm", "hunter3").unwrap();
+        assert_eq!(storage.get_reused().unwrap(), vec![vec!["db/prod", "db/staging"], vec!["github", "gitlab"]]);
+
+        storage.update("gitlab", "changed").unwrap();
+        storage.rename("db/staging", "db/dev").unwrap();
+        assert_eq!(storage.get_reused().unwrap(), vec![vec!["db/dev", "db/prod"]]);
+        assert_eq!(storage.names_with_value("changed").unwrap(), vec!["gitlab"]);
+
+        // two cleared values don't count as reused
+        storage.write("git/github.com", "").unwrap();
+        storage.write("git/gitlab.com", "").unwrap();
+        assert_eq!(storage.get_reused().unwrap(), vec![vec!["db/dev", "db/prod"]]);
+        assert!(storage.names_with_value("").unwrap().is_empty());
+
+        // the fingerprint is keyed, not a plain hash of the value
+        let fingerprint = storage.fingerprint("hunter2");
+        assert_eq!(fingerprint.len(), 64);
+        assert_ne!(SqliteStorage::new(":memory:").unwrap().fingerprint("hunter2"), fingerprint);
+    }
+
+    #[test]
+    fn test_attachments() {
+        let storage = SqliteStorage::new(":memory:").unwrap();
+        storage.write("k8s/prod", "token").unwrap();
+
+        // a few chunks, the last one short
+        let kubeconfig: Vec<u8> = (0..attachment::CHUNK_SIZE * 2 + 100).map(|i| i as u8).collect();
+        let size = storage.add_attachment("k8s/prod", "kubeconfig", &mut kubeconfig.as_slice(), 1 << 20).unwrap();
+        assert_eq!(size, kubeconfig.len() as u64);
+        storage.add_attachment("k8s/prod", "empty", &mut [].as_slice(), 1 << 20).unwrap();
+        let err = storage.add_attachment("k8s/prod", "big", &mut kubeconfig.as_slice(), 1000).unwrap_err();
+        assert_eq!(err, "big is larger than the limit of 1000 B");
+        assert!(storage.add_attachment("missing", "file", &mut [].as_slice(), 1000).is_err());
+
+        storage.rename_prefix("k8s", "kube").unwrap();
+        let names: Vec<(String, String)> = storage
+            .get_attachments()
+            .unwrap()
+            .into_iter()
+            .map(|(key, attachment)| (key, attachment.name))
+            .collect();
+        assert_eq!(names, vec![("kube/prod".into(), "empty".into()), ("kube/prod".into(), "kubeconfig".into())]);
+
+        let mut out = vec![];
+        storage.read_attachment("kube/prod", "kubeconfig", &mut out).unwrap();
+        assert_eq!(out, kubeconfig);
+
+        // a dropped last chunk is noticed
+        storage.conn.execute("DELETE FROM attachment_chunks WHERE seq = 2", []).unwrap();
+        assert!(storage.read_attachment("kube/prod", "kubeconfig", &mut vec![]).is_err());
+
+        // deleting a folder only deletes what is in it, a secret named like it stays
+        storage.write("kube", "admin").unwrap();
+        storage.add_attachment("kube", "config", &mut b"apiVersion: v1".as_slice(), 1024).unwrap();
+        storage.write("kubelet", "other").unwrap();
+        assert_eq!(storage.delete_prefix("kube").unwrap(), 1);
+        let names: Vec<String> = storage.get_all().unwrap().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["kube", "kubelet"]);
+        let names: Vec<String> = storage.get_attachments().unwrap().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["kube"]);
+        let chunks: i64 = storage.conn.query_row("SELECT COUNT(*) FROM attachment_chunks", [], |row| row.get(0)).unwrap();
+        assert_eq!(chunks, 1);
+    }
+}
@@ -0,0 +1,495 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::generator;
+
+/// the most common passwords, most common first
+const COMMON: &str = include_str!("wordlists/common_passwords.txt");
+
+/// only the start of long secrets like keys is searched for patterns, the rest counts as random
+const MAX_MATCHED: usize = 64;
+
+/// years are guessed outwards from this one
+const REFERENCE_YEAR: i32 = 2025;
+const MIN_YEAR_SPACE: i32 = 20;
+
+/// the keyboard rows, unshifted and shifted, with where the first key starts in key widths
+const KEYBOARD: [(&str, &str, f64); 4] = [
+    ("`1234567890-=", "~!@#$%^&*()_+", 0.0),
+    ("qwertyuiop[]\\", "QWERTYUIOP{}|", 1.5),
+    ("asdfghjkl;'", "ASDFGHJKL:\"", 1.75),
+    ("zxcvbnm,./", "ZXCVBNM<>?", 2.25),
+];
+
+pub const LABELS: [&str; 5] = ["very weak", "weak", "fair", "strong", "very strong"];
+
+/// how hard a secret is to guess, after the ideas of zxcvbn: the secret is split
+/// into the patterns an attacker would try first, words, keyboard rows, dates and
+/// the like, and the guesses for the cheapest split are counted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Estimate {
+    /// 0 for very weak to 4 for very strong
+    pub score: u8,
+    /// log10 of the guesses needed
+    pub guesses_log10: f64,
+    /// what makes the secret weak, only for scores below 3
+    pub warning: Option<&'static str>,
+}
+
+impl Estimate {
+    pub fn label(&self) -> &'static str {
+        LABELS[self.score as usize]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Pattern {
+    Common,
+    Word,
+    L33t,
+    Reversed,
+    Keyboard,
+    Sequence,
+    Repeat,
+    Date,
+    /// characters that follow no pattern
+    Random,
+}
+
+impl Pattern {
+    fn warning(&self) -> &'static str {
+        match self {
+            Pattern::Common => "This is one of the most common passwords",
+            Pattern::Word => "Dictionary words are easy to guess",
+            Pattern::L33t => "Substitutions like @ for a don't fool anyone",
+            Pattern::Reversed => "Reversed words are as easy to guess as the words",
+            Pattern::Keyboard => "Rows of keys like qwerty are easy to guess",
+            Pattern::Sequence => "Sequences like abc or 6543 are easy to guess",
+            Pattern::Repeat => "Repeats like aaa or abcabc are easy to guess",
+            Pattern::Date => "Dates and years are easy to guess",
+            Pattern::Random => "Too short, add a few more characters",
+        }
+    }
+}
+
+/// a part of the secret, `start..end` in chars, and the log10 of its guesses
+struct Match {
+    start: usize,
+    end: usize,
+    log10: f64,
+    pattern: Pattern,
+}
+
+pub fn estimate(secret: &str) -> Estimate {
+    let chars: Vec<char> = secret.chars().collect();
+    let (guesses_log10, pattern) = guesses(&chars);
+    let score = match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    };
+    let warning = pattern.filter(|_| score < 3).map(|pattern| pattern.warning());
+    Estimate { score, guesses_log10, warning }
+}
+
+/// the log10 guesses of the cheapest split and the pattern covering most of it
+fn guesses(chars: &[char]) -> (f64, Option<Pattern>) {
+    let n = chars.len().min(MAX_MATCHED);
+    if n == 0 {
+        return (0.0, Some(Pattern::Random));
+    }
+    let random = cardinality(chars).log10();
+    let mut matches = find_matches(&chars[..n]);
+    // any run of characters can be brute forced
+    for start in 0..n {
+        for end in start + 1..=n {
+            matches.push(Match { start, end, log10: (end - start) as f64 * random, pattern: Pattern::Random });
+        }
+    }
+
+    // best[k][j] are the least guesses to cover the first j chars with k matches,
+    // through the match in last[k][j]
+    let mut best = vec![vec![f64::INFINITY; n + 1]; n + 1];
+    let mut last: Vec<Vec<Option<usize>>> = vec![vec![None; n + 1]; n + 1];
+    best[0][0] = 0.0;
+    for j in 1..=n {
+        for (i, m) in matches.iter().enumerate().filter(|(_, m)| m.end == j) {
+            for k in 1..=j {
+                let guesses = best[k - 1][m.start] + m.log10;
+                if guesses < best[k][j] {
+                    best[k][j] = guesses;
+                    last[k][j] = Some(i);
+                }
+            }
+        }
+    }
+
+    // more matches take more tries to put together, k! orders of them
+    let factorial = |k: usize| (1..=k).map(|i| (i as f64).log10()).sum::<f64>();
+    let (k, total) = (1..=n)
+        .map(|k| (k, best[k][n] + factorial(k)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap();
+
+    let mut covered: Vec<(usize, Pattern)> = vec![];
+    let (mut k, mut j) = (k, n);
+    while let Some(i) = last[k][j] {
+        let m = &matches[i];
+        covered.push((m.end - m.start, m.pattern));
+        k -= 1;
+        j = m.start;
+    }
+    let pattern = covered
+        .iter()
+        .filter(|(_, pattern)| *pattern != Pattern::Random)
+        .max_by_key(|(len, _)| *len)
+        .or(covered.first())
+        .map(|(_, pattern)| *pattern);
+
+    // what is past the matched start counts as random, except where it goes on
+    // repeating a block before it, like the rest of 200 times `a`
+    let mut tail = 0.0;
+    let mut i = n;
+    while i < chars.len() {
+        let repeated = (1..=MAX_MATCHED / 2)
+            .map(|block| (block, chars[i..].iter().zip(&chars[i - block..]).take_while(|(a, b)| a == b).count()))
+            .filter(|(block, len)| len >= block)
+            .map(|(_, len)| len)
+            .max();
+        match repeated {
+            // only the number of repeats grows
+            Some(len) => {
+                tail += ((i + len) as f64 / i as f64).log10();
+                i += len;
+            }
+            None => {
+                tail += random;
+                i += 1;
+            }
+        }
+    }
+    (total + tail, pattern)
+}
+
+/// how many characters each random one could be, from the classes in the secret
+fn cardinality(chars: &[char]) -> f64 {
+    let mut classes = [false; 5];
+    for ch in chars {
+        let class = match ch {
+            'a'..='z' => 0,
+            'A'..='Z' => 1,
+            '0'..='9' => 2,
+            ch if ch.is_ascii() => 3,
+            _ => 4,
+        };
+        classes[class] = true;
+    }
+    [26.0, 26.0, 10.0, 33.0, 100.0]
+        .iter()
+        .zip(classes)
+        .filter(|(_, present)| *present)
+        .map(|(size, _)| size)
+        .sum()
+}
+
+fn find_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = dictionary_matches(chars);
+    matches.extend(keyboard_matches(chars));
+    matches.extend(sequence_matches(chars));
+    matches.extend(repeat_matches(chars));
+    matches.extend(date_matches(chars));
+    matches
+}
+
+/// common passwords rank by how common they are, a passphrase word ranks as
+/// the size of the whole list since any of them is as likely
+fn dictionary() -> &'static HashMap<&'static str, (usize, Pattern)> {
+    static DICTIONARY: OnceLock<HashMap<&'static str, (usize, Pattern)>> = OnceLock::new();
+    DICTIONARY.get_or_init(|| {
+        let words = generator::words();
+        let size = words.len();
+        let mut dictionary: HashMap<&str, (usize, Pattern)> = words.into_iter().map(|word| (word, (size, Pattern::Word))).collect();
+        for (i, password) in COMMON.lines().filter(|line| !line.is_empty()).enumerate() {
+            dictionary.insert(password, (i + 1, Pattern::Common));
+        }
+        dictionary
+    })
+}
+
+fn unleet(ch: char) -> char {
+    match ch {
+        '4' | '@' => 'a',
+        '8' => 'b',
+        '(' => 'c',
+        '3' => 'e',
+        '9' => 'g',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '$' | '5' => 's',
+        '7' | '+' => 't',
+        '2' => 'z',
+        ch => ch,
+    }
+}
+
+/// the ways to capitalize a word an attacker tries before `chars`
+fn case_variations(chars: &[char]) -> f64 {
+    let upper = chars.iter().filter(|ch| ch.is_uppercase()).count();
+    let lower = chars.iter().filter(|ch| ch.is_lowercase()).count();
+    let first_or_last = chars.first().is_some_and(|ch| ch.is_uppercase()) || chars.last().is_some_and(|ch| ch.is_uppercase());
+    match (upper, lower) {
+        (0, _) => 1.0,
+        (_, 0) => 2.0,
+        (1, _) if first_or_last => 2.0,
+        _ => (1..=upper.min(lower)).map(|k| binomial(upper + lower, k)).sum(),
+    }
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+fn dictionary_matches(chars: &[char]) -> Vec<Match> {
+    let dictionary = dictionary();
+    let lower: Vec<char> = chars.iter().map(|ch| ch.to_ascii_lowercase()).collect();
+    let mut matches = vec![];
+    for start in 0..chars.len() {
+        for end in start + 3..=chars.len() {
+            let word: String = lower[start..end].iter().collect();
+            let cases = case_variations(&chars[start..end]);
+            let mut found = |rank: usize, variations: f64, pattern: Pattern| {
+                matches.push(Match { start, end, log10: (rank as f64 * cases * variations).log10(), pattern });
+            };
+            if let Some((rank, pattern)) = dictionary.get(word.as_str()) {
+                found(*rank, 1.0, *pattern);
+            }
+            let reversed: String = word.chars().rev().collect();
+            if let Some((rank, _)) = dictionary.get(reversed.as_str()).filter(|_| reversed != word) {
+                found(*rank, 2.0, Pattern::Reversed);
+            }
+            let plain: String = word.chars().map(unleet).collect();
+            if let Some((rank, _)) = dictionary.get(plain.as_str()).filter(|_| plain != word) {
+                let substituted = word.chars().zip(plain.chars()).filter(|(a, b)| a != b).count();
+                found(*rank, 2f64.powi(substituted as i32), Pattern::L33t);
+            }
+        }
+    }
+    matches
+}
+
+/// where a key is on the keyboard, and whether it takes shift
+fn key_position(ch: char) -> Option<(usize, f64, bool)> {
+    KEYBOARD.iter().enumerate().find_map(|(row, (plain, shifted, offset))| {
+        let position = |keys: &str| keys.chars().position(|key| key == ch).map(|col| offset + col as f64);
+        position(plain).map(|x| (row, x, false)).or_else(|| position(shifted).map(|x| (row, x, true)))
+    })
+}
+
+fn adjacent(a: (usize, f64, bool), b: (usize, f64, bool)) -> bool {
+    let dx = (a.1 - b.1).abs();
+    match a.0.abs_diff(b.0) {
+        0 => dx > 0.0 && dx <= 1.0,
+        1 => dx <= 1.25,
+        _ => false,
+    }
+}
+
+/// runs of at least 4 neighboring keys like `qwer` or `zaq1`
+fn keyboard_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = vec![];
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = start + 1;
+        let mut turns = 0;
+        let mut shifted = 0;
+        let mut direction = None;
+        while end < chars.len() {
+            let (Some(a), Some(b)) = (key_position(chars[end - 1]), key_position(chars[end])) else {
+                break;
+            };
+            if !adjacent(a, b) {
+                break;
+            }
+            let step = (b.0 as i32 - a.0 as i32, (b.1 - a.1).signum() as i32);
+            if direction != Some(step) {
+                turns += 1;
+                direction = Some(step);
+            }
+            shifted += b.2 as i32;
+            end += 1;
+        }
+        let len = end - start;
+        if len >= 4 {
+            // any of about 47 keys to start, about 4 neighbors to turn to
+            let guesses = 47.0 * len as f64 * 4f64.powi(turns) * 2f64.powi(shifted.min(1));
+            matches.push(Match { start, end, log10: guesses.log10(), pattern: Pattern::Keyboard });
+            start = end - 1;
+        } else {
+            start += 1;
+        }
+    }
+    matches
+}
+
+/// runs of at least 3 like `abc`, `6543` or `XYZ`
+fn sequence_matches(chars: &[char]) -> Vec<Match> {
+    let class = |ch: char| match ch {
+        'a'..='z' => Some(26.0),
+        'A'..='Z' => Some(26.0),
+        '0'..='9' => Some(10.0),
+        _ => None,
+    };
+    let step = |a: char, b: char| b as i32 - a as i32;
+    let mut matches = vec![];
+    let mut start = 0;
+    while start + 1 < chars.len() {
+        let delta = step(chars[start], chars[start + 1]);
+        let mut end = start + 1;
+        while end < chars.len()
+            && delta.abs() == 1
+            && step(chars[end - 1], chars[end]) == delta
+            && class(chars[end]).is_some()
+            && class(chars[end]) == class(chars[start])
+            && chars[end].is_uppercase() == chars[start].is_uppercase()
+        {
+            end += 1;
+        }
+        if end - start >= 3 {
+            // the obvious starts come first
+            let base = match chars[start] {
+                'a' | 'A' | 'z' | 'Z' | '0' | '1' | '9' => 4.0,
+                ch => class(ch).unwrap(),
+            };
+            let direction = if delta < 0 { 2.0 } else { 1.0 };
+            let guesses = base * (end - start) as f64 * direction;
+            matches.push(Match { start, end, log10: guesses.log10(), pattern: Pattern::Sequence });
+            start = end - 1;
+        } else {
+            start += 1;
+        }
+    }
+    matches
+}
+
+/// `aaa` or `abcabc`: the guesses of the block times how often it repeats
+fn repeat_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = vec![];
+    for start in 0..chars.len() {
+        let longest = (1..=(chars.len() - start) / 2)
+            .map(|block| {
+                let count = chars[start..]
+                    .chunks_exact(block)
+                    .take_while(|chunk| *chunk == &chars[start..start + block])
+                    .count();
+                (block, count)
+            })
+            .filter(|(block, count)| *count >= 2 && (*block > 1 || *count >= 3))
+            .max_by_key(|(block, count)| block * count);
+        if let Some((block, count)) = longest {
+            let (block_guesses, _) = guesses(&chars[start..start + block]);
+            let log10 = block_guesses + (count as f64).log10();
+            matches.push(Match { start, end: start + block * count, log10, pattern: Pattern::Repeat });
+        }
+    }
+    matches
+}
+
+fn year_space(year: i32) -> f64 {
+    (year - REFERENCE_YEAR).abs().max(MIN_YEAR_SPACE) as f64
+}
+
+/// a 2 digit year is the closest one of 19xx and 20xx
+fn full_year(digits: &str) -> Option<i32> {
+    let year: i32 = digits.parse().ok()?;
+    match digits.len() {
+        2 if year < 50 => Some(2000 + year),
+        2 => Some(1900 + year),
+        4 if (1900..=2099).contains(&year) => Some(year),
+        _ => None,
+    }
+}
+
+/// a date from its day, month and year, the guesses for it
+fn date_guesses(day: &str, month: &str, year: &str) -> Option<f64> {
+    let day: u32 = day.parse().ok()?;
+    let month: u32 = month.parse().ok()?;
+    let year = full_year(year)?;
+    ((1..=31).contains(&day) && (1..=12).contains(&month)).then(|| 365.0 * year_space(year))
+}
+
+/// years like `1987`, dates like `19870412`, `120487` or `12/4/87`
+fn date_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = vec![];
+    for start in 0..chars.len() {
+        for end in start + 4..=(start + 10).min(chars.len()) {
+            let text: String = chars[start..end].iter().collect();
+            let guesses = if text.chars().all(|ch| ch.is_ascii_digit()) {
+                let part = |from: usize, to: usize| &text[from..to];
+                match text.len() {
+                    4 => full_year(&text).map(year_space),
+                    6 => date_guesses(part(0, 2), part(2, 4), part(4, 6))
+                        .or_else(|| date_guesses(part(2, 4), part(0, 2), part(4, 6)))
+                        .or_else(|| date_guesses(part(4, 6), part(2, 4), part(0, 2))),
+                    8 => date_guesses(part(0, 2), part(2, 4), part(4, 8))
+                        .or_else(|| date_guesses(part(2, 4), part(0, 2), part(4, 8)))
+                        .or_else(|| date_guesses(part(6, 8), part(4, 6), part(0, 4))),
+                    _ => None,
+                }
+            } else {
+                let separator = text.chars().find(|ch| !ch.is_ascii_digit()).unwrap();
+                let parts: Vec<&str> = text.split(separator).collect();
+                match parts[..] {
+                    [a, b, c] if "/-._ ".contains(separator) && parts.iter().all(|part| (1..=4).contains(&part.len()) && part.chars().all(|ch| ch.is_ascii_digit())) => {
+                        let guesses = match c.len() {
+                            2 | 4 => date_guesses(a, b, c).or_else(|| date_guesses(b, a, c)),
+                            _ => date_guesses(c, b, a),
+                        };
+                        // which separator it is
+                        guesses.map(|guesses| guesses * 4.0)
+                    }
+                    _ => None,
+                }
+            };
+            if let Some(guesses) = guesses {
+                matches.push(Match { start, end, log10: guesses.log10(), pattern: Pattern::Date });
+            }
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weak() {
+        let estimate = estimate("password123");
+        assert_eq!(estimate.score, 0);
+        assert_eq!(estimate.warning, Some(Pattern::Common.warning()));
+
+        assert_eq!(super::estimate("p@ssw0rd").warning, Some(Pattern::L33t.warning()));
+        assert_eq!(super::estimate("qwertyuiop").score, 0);
+        assert_eq!(super::estimate("zxcvbnm,./").warning, Some(Pattern::Keyboard.warning()));
+        assert_eq!(super::estimate("abcdefgh").warning, Some(Pattern::Sequence.warning()));
+        assert_eq!(super::estimate("aaaaaaaaaa").warning, Some(Pattern::Repeat.warning()));
+        assert_eq!(super::estimate("12/04/1987").warning, Some(Pattern::Date.warning()));
+        assert!(super::estimate("drowssap").score <= 1);
+        assert_eq!(super::estimate("").score, 0);
+        // past what is matched, a repeat stays a repeat
+        assert!(super::estimate(&"a".repeat(200)).score <= 1);
+        assert!(super::estimate(&"abc".repeat(70)).score <= 1);
+    }
+
+    #[test]
+    fn test_strong() {
+        assert_eq!(estimate("hK8#qZ2!vT9@wL4$").score, 4);
+        assert_eq!(estimate("abandon-ability-able-about-above-absent").score, 4);
+        assert!(estimate("Gx7pQm2r").score >= 2);
+        assert!(estimate("Gx7pQm2r").guesses_log10 > estimate("password").guesses_log10);
+        assert_eq!(estimate("hK8#qZ2!vT9@wL4$").warning, None);
+        assert_eq!(estimate(&format!("{}hK8#qZ2!vT9@wL4$", "a".repeat(64))).score, 4);
+    }
+}
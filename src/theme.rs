@@ -0,0 +1,299 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use ratatui::style::{palette::tailwind::{BLUE, SLATE}, Color, Modifier, Style};
+use serde::Deserialize;
+
+use crate::config;
+
+/// how many colors the terminal can show
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ColorSupport {
+    None,
+    Ansi16,
+    Full,
+}
+
+impl ColorSupport {
+    /// guess from the environment, `NO_COLOR` always wins, see https://no-color.org
+    pub fn detect() -> ColorSupport {
+        if env::var("NO_COLOR").is_ok_and(|v| !v.is_empty()) {
+            return ColorSupport::None;
+        }
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" || term.contains("256color") {
+            ColorSupport::Full
+        } else {
+            ColorSupport::Ansi16
+        }
+    }
+
+    fn parse(s: &str) -> Result<ColorSupport, String> {
+        match s {
+            "none" => Ok(ColorSupport::None),
+            "16" => Ok(ColorSupport::Ansi16),
+            "256" | "truecolor" => Ok(ColorSupport::Full),
+            _ => Err(format!("unknown color mode '{}', expected none, 16, 256 or truecolor", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub header: Style,
+    pub row_bg: Color,
+    pub alt_row_bg: Color,
+    pub selected: Style,
+    pub marked: Style,
+    pub tag: Style,
+    pub input: Style,
+    pub guide: Style,
+    pub error: Style,
+    pub notice: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::builtin("dark").unwrap()
+    }
+}
+
+pub const BUILTIN_THEMES: [&str; 3] = ["dark", "light", "high-contrast"];
+
+/// the theme file, a built-in theme with some colors replaced:
+///
+/// ```toml
+/// base = "light"
+/// colors = "16"
+///
+/// [styles]
+/// selected_bg = "#d0d0ff"
+/// error = "magenta"
+/// ```
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeConfig {
+    base: Option<String>,
+    colors: Option<String>,
+    #[serde(default)]
+    styles: std::collections::HashMap<String, String>,
+}
+
+impl Theme {
+    pub fn builtin(name: &str) -> Result<Theme, String> {
+        match name {
+            "dark" => Ok(Theme {
+                header: Style::new().fg(SLATE.c100).bg(BLUE.c800),
+                row_bg: SLATE.c950,
+                alt_row_bg: SLATE.c900,
+                selected: Style::new().bg(SLATE.c800).add_modifier(Modifier::BOLD),
+                marked: Style::new().fg(Color::Green),
+                tag: Style::new().fg(SLATE.c500),
+                input: Style::new().fg(Color::Yellow),
+                guide: Style::new().fg(Color::Blue),
+                error: Style::new().fg(Color::Red),
+                notice: Style::new().fg(Color::Green),
+            }),
+            "light" => Ok(Theme {
+                header: Style::new().fg(SLATE.c50).bg(BLUE.c600),
+                row_bg: SLATE.c50,
+                alt_row_bg: SLATE.c100,
+                selected: Style::new().fg(SLATE.c950).bg(BLUE.c200).add_modifier(Modifier::BOLD),
+                marked: Style::new().fg(Color::Rgb(0x15, 0x80, 0x3d)),
+                tag: Style::new().fg(SLATE.c500),
+                input: Style::new().fg(SLATE.c900).bg(SLATE.c200),
+                guide: Style::new().fg(BLUE.c700),
+                error: Style::new().fg(Color::Rgb(0xb9, 0x1c, 0x1c)),
+                notice: Style::new().fg(Color::Rgb(0x15, 0x80, 0x3d)),
+            }),
+            "high-contrast" => Ok(Theme {
+                header: Style::new().fg(Color::Black).bg(Color::White).add_modifier(Modifier::BOLD),
+                row_bg: Color::Black,
+                alt_row_bg: Color::Black,
+                selected: Style::new().add_modifier(Modifier::REVERSED | Modifier::BOLD),
+                marked: Style::new().fg(Color::LightGreen).add_modifier(Modifier::BOLD),
+                tag: Style::new().fg(Color::LightCyan),
+                input: Style::new().fg(Color::White).add_modifier(Modifier::BOLD),
+                guide: Style::new().fg(Color::White),
+                error: Style::new().fg(Color::LightRed).add_modifier(Modifier::BOLD),
+                notice: Style::new().fg(Color::LightGreen).add_modifier(Modifier::BOLD),
+            }),
+            _ => Err(format!("unknown theme '{}', expected one of {}", name, BUILTIN_THEMES.join(", "))),
+        }
+    }
+
+    /// the base theme of `config` with its styles replaced, adapted to what the terminal can show
+    pub fn from_config(config: ThemeConfig) -> Result<Theme, String> {
+        Theme::from_config_for(config, ColorSupport::detect())
+    }
+
+    /// like `from_config` for a terminal that `detected` says shows so many colors
+    fn from_config_for(config: ThemeConfig, detected: ColorSupport) -> Result<Theme, String> {
+        let mut theme = Theme::builtin(config.base.as_deref().unwrap_or("dark"))?;
+        for (key, value) in &config.styles {
+            let color = Color::from_str(value).map_err(|_| format!("invalid color '{}' for {}", value, key))?;
+            match key.as_str() {
+                "header_fg" => theme.header = theme.header.fg(color),
+                "header_bg" => theme.header = theme.header.bg(color),
+                "row_bg" => theme.row_bg = color,
+                "alt_row_bg" => theme.alt_row_bg = color,
+                "selected_fg" => theme.selected = theme.selected.fg(color),
+                "selected_bg" => theme.selected = theme.selected.bg(color),
+                "marked" => theme.marked = theme.marked.fg(color),
+                "tag" => theme.tag = theme.tag.fg(color),
+                "input_fg" => theme.input = theme.input.fg(color),
+                "input_bg" => theme.input = theme.input.bg(color),
+                "guide" => theme.guide = theme.guide.fg(color),
+                "error" => theme.error = theme.error.fg(color),
+                "notice" => theme.notice = theme.notice.fg(color),
+                _ => return Err(format!("unknown theme style '{}'", key)),
+            }
+        }
+        let support = match config.colors.as_deref() {
+            // NO_COLOR beats the theme file
+            Some(colors) if detected != ColorSupport::None => ColorSupport::parse(colors)?,
+            _ => detected,
+        };
+        Ok(theme.adapt(support))
+    }
+
+    /// where the theme is read from, `$XDG_CONFIG_HOME/secm/theme.toml`
+    pub fn path() -> Option<PathBuf> {
+        config::config_home().map(|dir| dir.join("secm").join("theme.toml"))
+    }
+
+    /// the theme from the theme file, the dark theme when there is none
+    pub fn load() -> Result<Theme, String> {
+        let Some(path) = Theme::path().filter(|path| path.exists()) else {
+            return Theme::from_config(ThemeConfig::default());
+        };
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        let config: ThemeConfig = toml::from_str(&content)
+            .map_err(|e| format!("Invalid theme {}: {}", path.display(), e))?;
+        Theme::from_config(config)
+    }
+
+    /// drop or reduce colors the terminal can't show, modifiers like bold are kept
+    pub fn adapt(self, support: ColorSupport) -> Theme {
+        let color = |c: Color| match support {
+            ColorSupport::None => Color::Reset,
+            ColorSupport::Ansi16 => to_ansi16(c),
+            ColorSupport::Full => c,
+        };
+        let style = |s: Style| Style {
+            fg: s.fg.map(color),
+            bg: s.bg.map(color),
+            ..s
+        };
+        let mut theme = Theme {
+            header: style(self.header),
+            row_bg: color(self.row_bg),
+            alt_row_bg: color(self.alt_row_bg),
+            selected: style(self.selected),
+            marked: style(self.marked),
+            tag: style(self.tag),
+            input: style(self.input),
+            guide: style(self.guide),
+            error: style(self.error),
+            notice: style(self.notice),
+        };
+        if support == ColorSupport::None {
+            // without colors the selection still has to stand out
+            theme.selected = theme.selected.add_modifier(Modifier::REVERSED);
+            theme.marked = theme.marked.add_modifier(Modifier::UNDERLINED);
+        }
+        theme
+    }
+}
+
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (128, 0, 0)),
+    (Color::Green, (0, 128, 0)),
+    (Color::Yellow, (128, 128, 0)),
+    (Color::Blue, (0, 0, 128)),
+    (Color::Magenta, (128, 0, 128)),
+    (Color::Cyan, (0, 128, 128)),
+    (Color::Gray, (192, 192, 192)),
+    (Color::DarkGray, (128, 128, 128)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (0, 0, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// the closest of the 16 basic terminal colors
+fn to_ansi16(color: Color) -> Color {
+    let (r, g, b) = match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Indexed(i) if i < 16 => return ANSI16[i as usize].0,
+        Color::Indexed(i) if i >= 232 => {
+            let level = 8 + (i - 232) * 10;
+            (level, level, level)
+        }
+        Color::Indexed(i) => {
+            let i = i - 16;
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            (level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        named => return named,
+    };
+    let distance = |(r2, g2, b2): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, r2) + d(g, g2) + d(b, b2)
+    };
+    ANSI16
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb))
+        .map(|(named, _)| *named)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adapt() {
+        let theme = Theme::builtin("dark").unwrap();
+        let plain = theme.clone().adapt(ColorSupport::None);
+        assert_eq!(plain.row_bg, Color::Reset);
+        assert_eq!(plain.error.fg, Some(Color::Reset));
+        assert!(plain.selected.add_modifier.contains(Modifier::REVERSED));
+
+        let ansi = theme.adapt(ColorSupport::Ansi16);
+        assert_eq!(ansi.row_bg, Color::Black);
+        assert_eq!(ansi.error.fg, Some(Color::Red));
+        assert_eq!(to_ansi16(Color::Rgb(250, 250, 250)), Color::White);
+        assert_eq!(to_ansi16(Color::Indexed(196)), Color::LightRed);
+    }
+
+    #[test]
+    fn test_theme_config() {
+        let config: ThemeConfig = toml::from_str(r##"
+            base = "light"
+            colors = "truecolor"
+
+            [styles]
+            error = "#ff00ff"
+        "##).unwrap();
+        let theme = Theme::from_config_for(config, ColorSupport::Ansi16).unwrap();
+        assert_eq!(theme.error.fg, Some(Color::Rgb(255, 0, 255)));
+        assert_eq!(theme.row_bg, SLATE.c50);
+
+        // NO_COLOR beats the colors of the file
+        let config: ThemeConfig = toml::from_str("colors = \"truecolor\"").unwrap();
+        assert_eq!(Theme::from_config_for(config, ColorSupport::None).unwrap().row_bg, Color::Reset);
+
+        let config: ThemeConfig = toml::from_str("[styles]\nrow = \"red\"").unwrap();
+        assert!(Theme::from_config_for(config, ColorSupport::Full).is_err());
+        assert!(toml::from_str::<ThemeConfig>("colours = \"16\"").is_err());
+    }
+}
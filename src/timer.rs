@@ -0,0 +1,91 @@
+use std::time::{Duration, Instant};
+
+/// tasks that run after a delay or every period, checked on each tick of the event loop
+pub struct Scheduler<T> {
+    entries: Vec<Entry<T>>,
+}
+
+struct Entry<T> {
+    task: T,
+    at: Instant,
+    every: Option<Duration>,
+}
+
+impl<T> Default for Scheduler<T> {
+    fn default() -> Self {
+        Self { entries: vec![] }
+    }
+}
+
+impl<T: Clone + PartialEq> Scheduler<T> {
+    /// run `task` once after `delay`, a task that is scheduled already starts over
+    pub fn after(&mut self, task: T, delay: Duration) {
+        self.schedule(task, Instant::now() + delay, None);
+    }
+
+    /// run `task` every `period`, the first time one period from now
+    pub fn every(&mut self, task: T, period: Duration) {
+        self.schedule(task, Instant::now() + period, Some(period));
+    }
+
+    fn schedule(&mut self, task: T, at: Instant, every: Option<Duration>) {
+        self.cancel(&task);
+        self.entries.push(Entry { task, at, every });
+    }
+
+    pub fn cancel(&mut self, task: &T) {
+        self.entries.retain(|entry| entry.task != *task);
+    }
+
+    pub fn is_scheduled(&self, task: &T) -> bool {
+        self.entries.iter().any(|entry| entry.task == *task)
+    }
+
+    /// the tasks due at `now` in the order they were due. Repeating tasks are
+    /// scheduled again, a tick that came late runs them once and not once per period.
+    pub fn due(&mut self, now: Instant) -> Vec<T> {
+        let mut due: Vec<(Instant, T)> = vec![];
+        self.entries.retain_mut(|entry| {
+            if entry.at > now {
+                return true;
+            }
+            due.push((entry.at, entry.task.clone()));
+            match entry.every {
+                Some(period) => {
+                    entry.at = now + period;
+                    true
+                }
+                None => false,
+            }
+        });
+        due.sort_by_key(|(at, _)| *at);
+        due.into_iter().map(|(_, task)| task).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_due() {
+        let mut scheduler = Scheduler::default();
+        scheduler.every("reload", Duration::from_secs(2));
+        scheduler.after("clear", Duration::from_secs(1));
+        scheduler.after("lock", Duration::from_secs(60));
+        assert!(scheduler.due(Instant::now()).is_empty());
+
+        let later = Instant::now() + Duration::from_secs(3);
+        assert_eq!(scheduler.due(later), vec!["clear", "reload"]);
+        assert!(!scheduler.is_scheduled(&"clear"));
+        assert!(scheduler.is_scheduled(&"reload"));
+        assert!(scheduler.due(later).is_empty());
+        assert_eq!(scheduler.due(later + Duration::from_secs(2)), vec!["reload"]);
+
+        // scheduling again starts over
+        scheduler.after("lock", Duration::from_secs(3600));
+        assert!(scheduler.due(later + Duration::from_secs(60)).iter().all(|task| *task != "lock"));
+        scheduler.cancel(&"reload");
+        assert!(!scheduler.is_scheduled(&"reload"));
+    }
+}
@@ -0,0 +1,218 @@
+use crate::model::Secret;
+
+/// secret names use `/` as a path separator, e.g. `aws/prod/root`
+pub const SEPARATOR: char = '/';
+
+pub enum TreeRow {
+    Folder {
+        path: String,
+        depth: usize,
+        count: usize,
+        expanded: bool,
+    },
+    Secret {
+        secret: Secret,
+        depth: usize,
+        pinned: Option<usize>, // the favorite number of a row in the pinned section
+    },
+}
+
+impl TreeRow {
+    pub fn depth(&self) -> usize {
+        match self {
+            TreeRow::Folder { depth, .. } => *depth,
+            TreeRow::Secret { depth, .. } => *depth,
+        }
+    }
+
+    /// the text of the row in the tree, indented by depth
+    pub fn text(&self) -> String {
+        let indent = "  ".repeat(self.depth());
+        match self {
+            TreeRow::Folder { count, expanded, .. } => {
+                let arrow = if *expanded { "▾" } else { "▸" };
+                format!("{}{} {}/ ({})", indent, arrow, self.label(), count)
+            }
+            TreeRow::Secret { secret, pinned: Some(number), .. } => format!("★{} {}", number, secret.name),
+            TreeRow::Secret { .. } => format!("{}  {}", indent, self.label()),
+        }
+    }
+
+    /// the last part of the path, shown in the tree under its parent folder
+    pub fn label(&self) -> &str {
+        let path = match self {
+            TreeRow::Folder { path, .. } => path,
+            TreeRow::Secret { secret, .. } => &secret.name,
+        };
+        match path.rsplit_once(SEPARATOR) {
+            Some((_, last)) if !last.is_empty() => last,
+            _ => path,
+        }
+    }
+}
+
+/// turn a user supplied prefix like `aws/prod/` or `aws/prod` into the folder path `aws/prod`
+pub fn normalize_prefix(prefix: &str) -> &str {
+    prefix.trim().trim_end_matches(SEPARATOR)
+}
+
+/// whether `name` lives somewhere below the folder `folder`
+pub fn in_folder(name: &str, folder: &str) -> bool {
+    folder.is_empty()
+        || (name.len() > folder.len() + 1
+            && name.starts_with(folder)
+            && name[folder.len()..].starts_with(SEPARATOR))
+}
+
+/// the folder a secret or folder lives in, `None` for top level entries
+pub fn parent(path: &str) -> Option<&str> {
+    path.rsplit_once(SEPARATOR)
+        .map(|(parent, _)| parent)
+        .filter(|parent| !parent.is_empty())
+}
+
+/// flatten the secrets below `folder` into the rows of a tree, folders first.
+/// `collapsed` decides which folders hide their children. Rows with a higher
+/// `rank` come first, a folder ranks like its best secret, ties go by name.
+pub fn build_rows(
+    secrets: &[(String, String)],
+    folder: &str,
+    collapsed: &dyn Fn(&str) -> bool,
+    rank: &dyn Fn(&str) -> i64,
+) -> Vec<TreeRow> {
+    let mut sorted: Vec<&(String, String)> = secrets
+        .iter()
+        .filter(|(name, _)| in_folder(name, folder))
+        .collect();
+    sorted.sort_by(|a, b| rank(&b.0).cmp(&rank(&a.0)).then_with(|| a.0.cmp(&b.0)));
+
+    let prefix = if folder.is_empty() { String::new() } else { format!("{}{}", folder, SEPARATOR) };
+    let mut rows = Vec::new();
+    push_level(&sorted, &prefix, 0, collapsed, &mut rows);
+    rows
+}
+
+/// `secrets` are sorted already, so folders keep the order of their first secret
+fn push_level(
+    secrets: &[&(String, String)],
+    prefix: &str,
+    depth: usize,
+    collapsed: &dyn Fn(&str) -> bool,
+    rows: &mut Vec<TreeRow>,
+) {
+    let mut folders: Vec<(&str, Vec<&(String, String)>)> = Vec::new();
+    let mut leaves = Vec::new();
+    for secret in secrets {
+        let rest = &secret.0[prefix.len()..];
+        match rest.split_once(SEPARATOR) {
+            Some((head, tail)) if !head.is_empty() && !tail.is_empty() => {
+                match folders.iter_mut().find(|(folder, _)| *folder == head) {
+                    Some((_, children)) => children.push(*secret),
+                    None => folders.push((head, vec![*secret])),
+                }
+            }
+            _ => leaves.push(*secret),
+        }
+    }
+
+    for (head, children) in folders {
+        let path = format!("{}{}", prefix, head);
+        let expanded = !collapsed(&path);
+        let child_prefix = format!("{}{}", path, SEPARATOR);
+        rows.push(TreeRow::Folder { path, depth, count: children.len(), expanded });
+        if expanded {
+            push_level(&children, &child_prefix, depth + 1, collapsed, rows);
+        }
+    }
+
+    for (name, value) in leaves {
+        rows.push(TreeRow::Secret {
+            secret: Secret { name: name.clone(), value: value.clone() },
+            depth,
+            pinned: None,
+        });
+    }
+}
+
+/// the rows pinned above the tree, one for each favorite in `secrets`, numbered
+/// by their place among all `favorites` so the numbers don't change with a filter
+pub fn pinned_rows(secrets: &[(String, String)], favorites: &[String]) -> Vec<TreeRow> {
+    favorites
+        .iter()
+        .enumerate()
+        .filter_map(|(i, favorite)| {
+            let (name, value) = secrets.iter().find(|(name, _)| name == favorite)?;
+            Some(TreeRow::Secret {
+                secret: Secret { name: name.clone(), value: value.clone() },
+                depth: 0,
+                pinned: Some(i + 1),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secrets(names: &[&str]) -> Vec<(String, String)> {
+        names.iter().map(|n| (n.to_string(), "v".to_string())).collect()
+    }
+
+    #[test]
+    fn test_build_rows() {
+        let secrets = secrets(&["github", "aws/prod/root", "aws/dev", "aws/prod/ci"]);
+        let rows = build_rows(&secrets, "", &|_| false, &|_| 0);
+        let labels: Vec<(usize, &str)> = rows.iter().map(|r| (r.depth(), r.label())).collect();
+        assert_eq!(labels, vec![(0, "aws"), (1, "prod"), (2, "ci"), (2, "root"), (1, "dev"), (0, "github")]);
+
+        match &rows[0] {
+            TreeRow::Folder { count, .. } => assert_eq!(*count, 3),
+            _ => panic!("expected folder"),
+        }
+    }
+
+    #[test]
+    fn test_build_rows_collapsed() {
+        let secrets = secrets(&["aws/prod/root", "aws/dev", "github"]);
+        let rows = build_rows(&secrets, "", &|path| path == "aws/prod", &|_| 0);
+        let labels: Vec<&str> = rows.iter().map(|r| r.label()).collect();
+        assert_eq!(labels, vec!["aws", "prod", "dev", "github"]);
+
+        let rows = build_rows(&secrets, "aws", &|_| true, &|_| 0);
+        let labels: Vec<&str> = rows.iter().map(|r| r.label()).collect();
+        assert_eq!(labels, vec!["prod", "dev"]);
+    }
+
+    #[test]
+    fn test_build_rows_ranked() {
+        let secrets = secrets(&["aws/dev", "aws/prod", "github", "gitlab"]);
+        let rank = |name: &str| match name {
+            "gitlab" => 3,
+            "aws/prod" => 2,
+            _ => 0,
+        };
+        let rows = build_rows(&secrets, "", &|_| false, &rank);
+        let labels: Vec<&str> = rows.iter().map(|r| r.label()).collect();
+        assert_eq!(labels, vec!["aws", "prod", "dev", "gitlab", "github"]);
+    }
+
+    #[test]
+    fn test_pinned_rows() {
+        let secrets = secrets(&["aws/vpn", "sso"]);
+        let favorites = vec!["sso".to_string(), "github".to_string(), "aws/vpn".to_string()];
+        let rows = pinned_rows(&secrets, &favorites);
+        let texts: Vec<String> = rows.iter().map(|r| r.text()).collect();
+        assert_eq!(texts, vec!["★1 sso", "★3 aws/vpn"]);
+    }
+
+    #[test]
+    fn test_in_folder() {
+        assert!(in_folder("aws/prod", "aws"));
+        assert!(in_folder("aws", ""));
+        assert!(!in_folder("aws", "aws"));
+        assert!(!in_folder("awsx/prod", "aws"));
+        assert_eq!(parent("aws/prod/root"), Some("aws/prod"));
+        assert_eq!(parent("aws"), None);
+    }
+}
@@ -1,32 +1,45 @@
 use ratatui::{
-    layout::{Alignment, Constraint, Direction, Layout, Rect}, style::{palette::tailwind::{BLUE, SLATE}, Color, Modifier, Style, Stylize}, symbols, terminal::Frame, text::Line, widgets::{Block, Borders, Clear, HighlightSpacing, List, ListItem, Paragraph}
+    layout::{Alignment, Constraint, Direction, Layout, Rect}, style::{Color, Stylize}, symbols, terminal::Frame, text::{Line, Span}, widgets::{Block, Borders, Clear, HighlightSpacing, List, ListItem, Paragraph, Wrap}
 };
 use unicode_width::UnicodeWidthStr;
 use crate::{app::App, panel::Panel, Storage};
+use crate::handle_mouse::ClickAreas;
+use crate::generator::Generator;
+use crate::input::Input;
+use crate::keymap::{Action, GUIDE_SEPARATOR};
+use crate::otp::{self, Kind, Otp};
 use crate::app::Mode;
 use crate::panel::PanelName;
+use crate::sort::SortOrder;
+use crate::strength::{self, Estimate, LABELS};
+use crate::theme::Theme;
+use crate::tree::TreeRow;
+
+/// how many lines of a multi-line secret the add and update popups show at once
+const MAX_VALUE_LINES: u16 = 8;
+/// width of the bar counting down to the next one-time code
+const OTP_BAR_WIDTH: usize = 20;
 
-const TODO_HEADER_STYLE: Style = Style::new().fg(SLATE.c100).bg(BLUE.c800);
-const NORMAL_ROW_BG: Color = SLATE.c950;
-const ALT_ROW_BG_COLOR: Color = SLATE.c900;
-const SELECTED_STYLE: Style = Style::new().bg(SLATE.c800).add_modifier(Modifier::BOLD);
 
 pub fn ui<S: Storage>(f: &mut Frame, app: &mut App<S>) {
     let size = f.size();
+    let theme = app.theme.clone();
+    let mut click_areas = ClickAreas::default();
 
     let vertical = Layout::vertical([
         Constraint::Max(0),
         Constraint::Min(0),
         Constraint::Length(3)
     ]).margin(2);
-    let [mut filter_area, mut secrets_area, guide_area] = vertical.areas(size);
+    let [mut filter_area, mut secrets_area, mut guide_area] = vertical.areas(size);
 
     if app.mode == Mode::Filter {
         let filter_string = app.get_filter_string();
         app.filter_secrets_list(&filter_string);
-        let filter_chunk = Paragraph::new(filter_string.clone())
-            .style(Style::default()
-            .fg(Color::Yellow))
+        let filter_input = &app.panels.get(&PanelName::Filter).unwrap().content[0];
+        let (visible, cursor) = filter_input.view(filter_area.width.saturating_sub(2), false);
+        let filter_chunk = Paragraph::new(visible)
+            .style(theme.input)
             .block(Block::default()
             .borders(Borders::ALL).title("Filter"));
 
@@ -36,36 +49,72 @@ pub fn ui<S: Storage>(f: &mut Frame, app: &mut App<S>) {
 
         f.render_widget(filter_chunk, filter_area);
         f.set_cursor(
-            // Put cursor past the end of the input text
-            filter_area.x + filter_string.width() as u16 + 1,
+            // Put cursor where it is in the input text
+            filter_area.x + cursor + 1,
             // Move one line down, from the border to the input line
             filter_area.y + 1,
         );
     }
 
+    // the live one-time code of the selected secret, on a line below the list
+    if let Some(otp) = app.selected_otp().filter(|_| secrets_area.height > 2) {
+        secrets_area.height -= 1;
+        let otp_area = Rect { y: secrets_area.bottom(), height: 1, ..secrets_area };
+        let key = app.keymap.keys(&Mode::Normal, Action::CopyOtp).first().map(|key| key.display()).unwrap_or_default();
+        let line = match otp {
+            Ok(otp) => Paragraph::new(otp_line(&otp, otp::unix_now(), &key)).style(theme.input),
+            Err(err) => Paragraph::new(format!(" one-time code: {}", err)).style(theme.error),
+        };
+        f.render_widget(line, otp_area);
+    }
+
     // Render the list of secrets
+    let title = match app.sort {
+        SortOrder::Name => "SECRETS".to_string(),
+        sort => format!("SECRETS by {}", sort.name()),
+    };
     let block = Block::new()
-        .title(Line::raw("SECRETS").centered())
+        .title(Line::raw(title).centered())
         .borders(Borders::TOP)
         .border_set(symbols::border::EMPTY)
-        .border_style(TODO_HEADER_STYLE)
-        .bg(NORMAL_ROW_BG);
+        .border_style(theme.header)
+        .bg(theme.row_bg);
 
     let items: Vec<ListItem> = app
         .secret_list
-        .secrets
+        .rows
         .iter()
         .enumerate()
-        .map(|(i, secret_item)| {
-            let color = alternate_colors(i);
-            ListItem::from(secret_item).bg(color)
+        .map(|(i, row)| {
+            let color = alternate_colors(&theme, i);
+            let mut spans = vec![Span::raw(row.text())];
+            if let TreeRow::Secret { secret, .. } = row {
+                if app.marked.contains(&secret.name) {
+                    spans[0] = Span::styled(row.text(), theme.marked);
+                    spans.insert(0, Span::styled("+", theme.marked));
+                }
+                if let Some(record) = app.records.get(&secret.name) {
+                    spans.push(Span::styled(format!("  [{}]", record.kind.name()), theme.tag));
+                }
+                if let Some(attachments) = app.attachments.get(&secret.name) {
+                    spans.push(Span::styled(format!("  {}", files(attachments.len())), theme.tag));
+                }
+                if app.reused.contains(&secret.name) {
+                    spans.push(Span::styled("  reused", theme.error));
+                }
+                if let Some(tags) = app.tags.get(&secret.name) {
+                    let tags: Vec<String> = tags.iter().map(|tag| format!("#{}", tag)).collect();
+                    spans.push(Span::styled(format!("  {}", tags.join(" ")), theme.tag));
+                }
+            }
+            ListItem::new(Line::from(spans)).bg(color)
         })
         .collect();
 
     // Create a List from all list items and highlight the currently selected one
     let list = List::new(items)
         .block(block)
-        .highlight_style(SELECTED_STYLE)
+        .highlight_style(theme.selected)
         .highlight_symbol(">")
         .highlight_spacing(HighlightSpacing::Always);
 
@@ -74,11 +123,15 @@ pub fn ui<S: Storage>(f: &mut Frame, app: &mut App<S>) {
     // StatefulWidget::render(list, area, buf, &mut self.secret_list.state);
     // f.render_widget(secrets_chunk, secrets_area);
     f.render_stateful_widget(list, secrets_area, &mut app.secret_list.state);
+    click_areas.list = secrets_area;
 
-    if app.mode == Mode::Add || app.mode == Mode::Update{
+    let selected_folder = app.get_selected_folder();
+    if app.mode == Mode::Update && selected_folder.is_some() {
+        let name_area = centered_rect(60, 7, size);
+        let panel = app.panels.get(&PanelName::UpdateSecret).unwrap();
+        render_label_input(f, &theme, name_area, "move to: ".to_string(), &panel.content[0], false, true);
+    } else if app.mode == Mode::Add || app.mode == Mode::Update{
         let name_area = centered_rect(60, 7, size);
-        let mut value_area = centered_rect(60, 7, size);
-        value_area.y += 2; // position below name area
 
         let panels: &Panel;
         if app.mode == Mode::Add {
@@ -87,48 +140,195 @@ pub fn ui<S: Storage>(f: &mut Frame, app: &mut App<S>) {
             panels = app.panels.get(&PanelName::UpdateSecret).unwrap();
         }
 
-        render_label_input(f, name_area, "name: ".to_string(), panels.content[0].clone(), panels.index == 0);
+        let name_field = render_label_input(f, &theme, name_area, "name: ".to_string(), &panels.content[0], false, panels.index == 0);
+        // below the name, growing with the lines of the value
+        let value = &panels.content[1];
+        let value_area = Rect {
+            y: name_area.y + 2,
+            height: (value.line_count() as u16).min(MAX_VALUE_LINES),
+            ..name_area
+        }.intersection(size);
+        let label = match value.line_count() {
+            1 => "secret: ".to_string(),
+            n => format!("secret ({} lines): ", n),
+        };
+        // the secret is shown as '*'s until revealed
+        let value_field = render_multiline_input(f, &theme, value_area, label, value, !app.reveal, panels.index == 1);
+        click_areas.fields = vec![name_field, value_field];
 
-        let secret_len = panels.content[1].width();
-        // create a string using '*'s to represent the secret
-        let secret_string = (0..secret_len).map(|_| "*").collect::<String>();
-        render_label_input(f, value_area, "secret: ".to_string(), secret_string, panels.index == 1);
+        let meter_area = Rect { y: value_area.bottom() + 1, height: 2, ..name_area };
+        if !value.value().is_empty() && meter_area.bottom() <= size.bottom() {
+            f.render_widget(Clear, meter_area);
+            f.render_widget(Paragraph::new(strength_meter(&strength::estimate(value.value()))).style(theme.input), meter_area);
+        }
     }
     if app.mode == Mode::Delete {
         let confirm_area = centered_rect(30, 7, size);
-        if let Some(selected_secret) = app.get_selected_item() {
-            let confirm = format!("delete {}? y/n:", selected_secret.name);
-            render_label_input(f, confirm_area, confirm, app.panels.get(&PanelName::DeleteSecret).unwrap().content[0].clone(), true);
+        let question = match &selected_folder {
+            Some(folder) => Some(format!("delete {}/ and everything in it", folder)),
+            None => app.get_selected_item().map(|secret| match app.attachments.get(&secret.name) {
+                Some(attachments) => format!("delete {} and its {}", secret.name, files(attachments.len())),
+                None => format!("delete {}", secret.name),
+            }),
+        };
+        if let Some(question) = question {
+            let confirm = format!("{}? y/n:", question);
+            render_label_input(f, &theme, confirm_area, confirm, &app.panels.get(&PanelName::DeleteSecret).unwrap().content[0], false, true);
         }
     }
+    if let (Mode::Confirm, Some(bulk)) = (&app.mode, &app.bulk) {
+        // the question names what is about to happen and needs the room
+        let confirm_area = centered_rect(60, 7, size);
+        let confirm = format!("{}? y/n:", bulk.question(app.marked.len()));
+        render_label_input(f, &theme, confirm_area, confirm, &app.panels.get(&PanelName::Confirm).unwrap().content[0], false, true);
+    }
     if app.mode == Mode::Make {
-        let name_area = centered_rect(30, 7, size);
-        let mut length_area = centered_rect(30, 7, size);
-        let mut advance_area = centered_rect(30, 7, size);
-        length_area.y += 2;
-        advance_area.y += 4;
+        // one more field than the other popups, start a bit higher
+        let mut name_area = centered_rect(30, 7, size);
+        name_area.y = name_area.y.saturating_sub(2);
+        let mut kind_area = name_area;
+        let mut length_area = name_area;
+        let mut policy_area = name_area;
+        kind_area.y += 2;
+        length_area.y += 4;
+        policy_area.y += 6;
 
         let make_panel = app.panels.get(&PanelName::MakeSecret).unwrap();
+        let [name, kind, length, policy] = &make_panel.content[..] else { unreachable!() };
+        let (length_label, policy_label) = match kind.value() {
+            "words" => ("words: ", "options: "),
+            _ => ("length: ", "policy: "),
+        };
+
+        click_areas.fields = vec![
+            render_label_input(f, &theme, name_area, "name: ".to_string(), name, false, make_panel.index == 0),
+            render_label_input(f, &theme, kind_area, "type: ".to_string(), &Input::new(&format!("◂ {} ▸", kind.value())), false, make_panel.index == 1),
+            render_label_input(f, &theme, length_area, length_label.to_string(), length, false, make_panel.index == 2),
+            render_label_input(f, &theme, policy_area, policy_label.to_string(), policy, false, make_panel.index == 3),
+        ];
 
-        render_label_input(f, name_area, "name: ".to_string(), make_panel.content[0].clone(), make_panel.index == 0);
-        render_label_input(f, length_area, "length: ".to_string(), make_panel.content[1].clone(), make_panel.index == 1);
-        render_label_input(f, advance_area, "advance: ".to_string(), make_panel.content[2].clone(), make_panel.index == 2);
+        // how strong the secret would be, nothing while the fields don't parse
+        let generator = length.value().trim().parse().ok()
+            .and_then(|n| Generator::parse(kind.value(), policy.value(), n).ok());
+        let mut entropy_area = Rect { height: 1, ..policy_area };
+        entropy_area.y += 1;
+        if let Some(generator) = generator.filter(|_| entropy_area.bottom() <= size.bottom()) {
+            let text = format!(" entropy: {:.0} bits", generator.entropy_bits());
+            f.render_widget(Paragraph::new(text).style(theme.input), entropy_area);
+        }
+    }
+    if app.mode == Mode::Tag {
+        let tag_area = centered_rect(40, 7, size);
+        let panel = app.panels.get(&PanelName::TagSecrets).unwrap();
+        let label = format!("tag {} secrets: ", app.marked.len());
+        render_label_input(f, &theme, tag_area, label, &panel.content[0], false, true);
+    }
+    if app.mode == Mode::Move {
+        let move_area = centered_rect(40, 7, size);
+        let panel = app.panels.get(&PanelName::MoveSecrets).unwrap();
+        let label = format!("move {} secrets to: ", app.marked.len());
+        render_label_input(f, &theme, move_area, label, &panel.content[0], false, true);
+    }
+    if let Some(scroll) = app.help_scroll {
+        let help_area = centered_rect(70, 80, size);
+        let help = app.get_help();
+        // stop scrolling once the last line is visible
+        let max_scroll = (help.lines().count() as u16).saturating_sub(help_area.height.saturating_sub(2));
+        let scroll = scroll.min(max_scroll);
+        app.help_scroll = Some(scroll);
+        let help_chunk = Paragraph::new(help)
+            .style(theme.input)
+            .wrap(Wrap { trim: false })
+            .scroll((scroll, 0))
+            .block(Block::default().borders(Borders::ALL).title("Help (j/k: scroll, esc: close)"));
+        f.render_widget(Clear, help_area);
+        f.render_widget(help_chunk, help_area);
+    }
+    if let Some(approval) = &app.approval {
+        // room for a request that wraps to two lines
+        let approval_area = Rect { height: 4, ..centered_rect(60, 20, size) }.intersection(size);
+        let text = format!("allow {} with {}? y/n", approval.what, approval.key);
+        let approval_chunk = Paragraph::new(text)
+            .style(theme.input)
+            .wrap(Wrap { trim: true })
+            .block(Block::default().borders(Borders::ALL).title("ssh-agent"));
+        f.render_widget(Clear, approval_area);
+        f.render_widget(approval_chunk, approval_area);
+    }
+    if app.mode == Mode::Command {
+        // the command line takes the first line of the guide area, like vim's
+        let line = Rect { height: 1, ..guide_area };
+        let input = &app.panels.get(&PanelName::Command).unwrap().content[0];
+        let (visible, cursor) = input.view(line.width.saturating_sub(1), false);
+        f.render_widget(Paragraph::new(format!(":{}", visible)).style(theme.input), line);
+        f.set_cursor(line.x + 1 + cursor, line.y);
+        guide_area.y += 1;
+        guide_area.height -= 1;
     }
-    let guide_chunk = Paragraph::new(app.guide.to_string()).alignment(Alignment::Center).style(Style::default().fg(Color::Blue));
-    let error_chunk = Paragraph::new(app.error.msg.to_string()).alignment(Alignment::Center).style(Style::default().fg(Color::Red));
-    if app.error.msg.is_empty() {
+    let prefix = match app.marked.len() {
+        0 => String::new(),
+        n => format!("{} marked | ", n),
+    };
+    let guide = format!("{}{}", prefix, app.guide);
+    let guide_width = guide.width() as u16;
+    let guide_chunk = Paragraph::new(guide).alignment(Alignment::Center).style(theme.guide);
+    let error_chunk = Paragraph::new(app.error.msg.to_string()).alignment(Alignment::Center).style(theme.error);
+    let notice_chunk = Paragraph::new(app.notice.msg.to_string()).alignment(Alignment::Center).style(theme.notice);
+    if app.error.msg.is_empty() && !app.notice.msg.is_empty() {
+        f.render_widget(notice_chunk, guide_area);
+    } else if app.error.msg.is_empty() {
+        if app.help_scroll.is_none() {
+            click_areas.guide = guide_click_areas(app, guide_area, &prefix, guide_width);
+        }
         f.render_widget(guide_chunk, guide_area);
     } else {
         f.render_widget(error_chunk, guide_area);
-        app.clear_error_if_expired();
     }
+    app.click_areas = click_areas;
+}
+
+/// where each action of the centered guide line was drawn
+fn guide_click_areas<S: Storage>(app: &App<S>, area: Rect, prefix: &str, width: u16) -> Vec<(Rect, Action)> {
+    let mut x = area.x + area.width.saturating_sub(width) / 2 + prefix.width() as u16;
+    let mut areas = vec![];
+    for (action, text) in app.keymap.guide_items(&app.mode, !app.marked.is_empty()) {
+        let text_width = text.width() as u16;
+        areas.push((Rect::new(x, area.y, text_width, 1).intersection(area), action));
+        x = x.saturating_add(text_width + GUIDE_SEPARATOR.width() as u16);
+    }
+    areas
+}
+
+/// like `render_label_input` for an input that can hold several lines, one row each
+fn render_multiline_input(f: &mut Frame, theme: &Theme, area: Rect, label: String, input: &Input, masked: bool, set_cursor: bool) -> Rect {
+    let layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(label.width() as u16 + 1), Constraint::Percentage(80)].as_ref())
+        .split(area);
+    let (lines, (column, row)) = input.view_lines(layout[1].width, layout[1].height, masked);
+    let lines: Vec<Line> = lines.into_iter().map(Line::raw).collect();
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(label).style(theme.input), layout[0]);
+    f.render_widget(Paragraph::new(lines).style(theme.input), layout[1]);
+    if set_cursor {
+        f.set_cursor(layout[1].x + column, layout[1].y + row)
+    }
+    area
 }
 
-const fn alternate_colors(i: usize) -> Color {
+/// `1 file`, `2 files`
+fn files(count: usize) -> String {
+    match count {
+        1 => "1 file".to_string(),
+        _ => format!("{} files", count),
+    }
+}
+
+fn alternate_colors(theme: &Theme, i: usize) -> Color {
     if i % 2 == 0 {
-        NORMAL_ROW_BG
+        theme.row_bg
     } else {
-        ALT_ROW_BG_COLOR
+        theme.alt_row_bg
     }
 }
 
@@ -159,25 +359,408 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         .split(popup_layout[1])[1]
 }
 
-fn render_label_input(f: &mut Frame, area: Rect, label: String, input_content: String, set_cursor: bool) {
+/// returns the line the input was drawn on, so it can be clicked
+/// `strength: ■■■□□ fair`, and below it what makes the secret weak
+fn strength_meter(estimate: &Estimate) -> Vec<Line<'static>> {
+    let filled = estimate.score as usize + 1;
+    let bar = "■".repeat(filled) + &"□".repeat(LABELS.len() - filled);
+    let mut lines = vec![Line::from(format!(" strength: {} {}", bar, estimate.label()))];
+    lines.extend(estimate.warning.map(|warning| Line::from(format!(" {}", warning))));
+    lines
+}
+
+/// the current code with a bar running down to the next one, e.g. ` 287 082  ■■■■□□ 11s`
+fn otp_line(otp: &Otp, now: u64, key: &str) -> String {
+    let (code, remaining) = otp.code(now);
+    match (otp.kind, remaining) {
+        (Kind::Totp { period }, Some(remaining)) => {
+            // groups of 3 like authenticator apps, 8 digits as 4 and 4
+            let group = if code.len() % 3 == 0 { 3 } else { 4 };
+            let groups: Vec<&str> = code.as_bytes().chunks(group).map(|c| std::str::from_utf8(c).unwrap()).collect();
+            let filled = (remaining as usize * OTP_BAR_WIDTH).div_ceil(period as usize);
+            let bar = "■".repeat(filled) + &"□".repeat(OTP_BAR_WIDTH - filled);
+            format!(" {}  {} {:>2}s  {}: copy", groups.join(" "), bar, remaining, key)
+        }
+        _ => format!(" counter based one-time code, {}: copy the next one", key),
+    }
+}
+
+fn render_label_input(f: &mut Frame, theme: &Theme, area: Rect, label: String, input: &Input, masked: bool, set_cursor: bool) -> Rect {
     let layout = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Length(label.width() as u16 + 1), Constraint::Percentage(80)].as_ref())
         .split(area);
     let label_paragraph = Paragraph::new(label)
-        .style(Style::default()
-        .fg(Color::Yellow));
-    let input_paragraph= Paragraph::new(input_content.clone())
-        .style(Style::default()
-        .fg(Color::Yellow))
+        .style(theme.input);
+    // long input scrolls sideways to keep the cursor in view
+    let (visible, cursor) = input.view(layout[1].width, masked);
+    let input_paragraph= Paragraph::new(visible)
+        .style(theme.input)
         .block(Block::default());
     f.render_widget(Clear, area);
     f.render_widget(label_paragraph, layout[0]);
     f.render_widget(input_paragraph, layout[1]);
     if set_cursor {
         f.set_cursor(
-            layout[1].x + input_content.width() as u16,
+            layout[1].x + cursor,
             layout[1].y
         )
     }
-}
\ No newline at end of file
+    Rect { height: 1, ..area }
+}
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+    use std::{env, fs};
+
+    use ratatui::{
+        backend::TestBackend,
+        buffer::Buffer,
+        crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
+        Terminal,
+    };
+
+    use super::*;
+    use crate::app::{Clipboard, Task};
+    use crate::storage::SqliteStorage;
+    use crate::timer::Scheduler;
+
+    /// an app over a database that only lives in memory, with a few secrets in folders
+    fn app() -> App<SqliteStorage> {
+        let storage = SqliteStorage::new(":memory:").unwrap();
+        for (name, value) in [("aws/prod/root", "s3cr3t"), ("aws/dev", "dev-pass"), ("github", "hunter2")] {
+            storage.write(name, value).unwrap();
+        }
+        let mut app = App::new(storage);
+        app.clipboard = Box::new(FakeClipboard::default());
+        app
+    }
+
+    /// keeps what was copied instead of touching the clipboard of the desktop
+    #[derive(Clone, Default)]
+    struct FakeClipboard(Rc<RefCell<Vec<String>>>);
+
+    impl Clipboard for FakeClipboard {
+        fn copy(&mut self, text: String) -> Result<(), String> {
+            self.0.borrow_mut().push(text);
+            Ok(())
+        }
+    }
+
+    fn press<S: Storage>(app: &mut App<S>, code: KeyCode) {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    fn type_str<S: Storage>(app: &mut App<S>, text: &str) {
+        text.chars().for_each(|ch| press(app, KeyCode::Char(ch)));
+    }
+
+    fn buffer_text(buffer: &Buffer) -> String {
+        let area = buffer.area;
+        (area.top()..area.bottom())
+            .map(|y| {
+                let line: String = (area.left()..area.right()).map(|x| buffer.get(x, y).symbol()).collect();
+                format!("{}\n", line.trim_end())
+            })
+            .collect()
+    }
+
+    /// render a frame and compare it with `src/snapshots/<name>.txt`. A missing snapshot fails
+    /// the test, `UPDATE_SNAPSHOTS=1 cargo test` writes new and changed ones after a change to the UI.
+    fn assert_snapshot<S: Storage>(name: &str, app: &mut App<S>) {
+        let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+        terminal.draw(|f| ui(f, app)).unwrap();
+        let actual = buffer_text(terminal.backend().buffer());
+
+        let path = format!("{}/src/snapshots/{}.txt", env!("CARGO_MANIFEST_DIR"), name);
+        // snapshots are only written on request, a missing one fails like a changed one
+        if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            return fs::write(&path, actual).unwrap();
+        }
+        match fs::read_to_string(&path) {
+            Ok(expected) => {
+                assert!(expected == actual, "snapshot {} changed, expected:\n{}\nactual:\n{}", name, expected, actual);
+            }
+            Err(err) => panic!("snapshot {} is missing ({}), run with UPDATE_SNAPSHOTS=1 to write it, actual:\n{}", name, err, actual),
+        }
+    }
+
+    #[test]
+    fn test_normal_and_filter() {
+        let mut app = app();
+        assert_snapshot("normal", &mut app);
+        press(&mut app, KeyCode::Char('j'));
+        press(&mut app, KeyCode::Char('h'));
+        assert_snapshot("normal_collapsed", &mut app);
+
+        press(&mut app, KeyCode::Char('/'));
+        type_str(&mut app, "dev");
+        assert_snapshot("filter", &mut app);
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.mode, Mode::Normal);
+    }
+
+    #[test]
+    fn test_add() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('a'));
+        type_str(&mut app, "gitlab");
+        press(&mut app, KeyCode::Tab);
+        type_str(&mut app, "glpat");
+        assert_snapshot("add", &mut app);
+        press(&mut app, KeyCode::Enter);
+        assert_snapshot("add_done", &mut app);
+        assert_eq!(app.storage.read("gitlab").unwrap().as_deref(), Some("glpat"));
+    }
+
+    #[test]
+    fn test_add_reused() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('a'));
+        type_str(&mut app, "gitlab");
+        press(&mut app, KeyCode::Tab);
+        type_str(&mut app, "hunter2");
+        press(&mut app, KeyCode::Enter);
+        assert_snapshot("add_reused", &mut app);
+        assert!(app.reused.contains("github") && app.reused.contains("gitlab"));
+    }
+
+    #[test]
+    fn test_add_errors() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('a'));
+        type_str(&mut app, "github");
+        press(&mut app, KeyCode::Tab);
+        type_str(&mut app, "other");
+        press(&mut app, KeyCode::Enter);
+        assert_snapshot("add_exists", &mut app);
+        assert_eq!(app.mode, Mode::Add);
+        assert_eq!(app.storage.read("github").unwrap().as_deref(), Some("hunter2"));
+    }
+
+    #[test]
+    fn test_make() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('m'));
+        type_str(&mut app, "npm");
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Backspace);
+        press(&mut app, KeyCode::Backspace);
+        type_str(&mut app, "x");
+        assert_snapshot("make", &mut app);
+        press(&mut app, KeyCode::Enter);
+        assert_snapshot("make_bad_length", &mut app);
+
+        press(&mut app, KeyCode::Backspace);
+        type_str(&mut app, "24");
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, Mode::Normal);
+        assert_eq!(app.storage.read("npm").unwrap().map(|value| value.len()), Some(24));
+    }
+
+    #[test]
+    fn test_make_words() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('m'));
+        type_str(&mut app, "wifi");
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Char(' '));
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Backspace);
+        type_str(&mut app, "4");
+        assert_snapshot("make_words", &mut app);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, Mode::Normal);
+        let value = app.storage.read("wifi").unwrap().unwrap();
+        assert_eq!(value.split('-').count(), 4);
+    }
+
+    #[test]
+    fn test_update() {
+        let mut app = app();
+        app.select_path("github");
+        press(&mut app, KeyCode::Char('r'));
+        assert_snapshot("update", &mut app);
+        press(&mut app, KeyCode::Tab);
+        type_str(&mut app, "!");
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.storage.read("github").unwrap().as_deref(), Some("hunter2!"));
+
+        // updating a folder moves it
+        app.select_path("aws");
+        press(&mut app, KeyCode::Char('r'));
+        assert_snapshot("update_folder", &mut app);
+        type_str(&mut app, "-old");
+        press(&mut app, KeyCode::Enter);
+        assert_snapshot("update_folder_done", &mut app);
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut app = app();
+        app.select_path("aws/dev");
+        press(&mut app, KeyCode::Char('d'));
+        type_str(&mut app, "y");
+        assert_snapshot("delete", &mut app);
+        press(&mut app, KeyCode::Enter);
+        assert_snapshot("delete_done", &mut app);
+        assert_eq!(app.storage.read("aws/dev").unwrap(), None);
+
+        press(&mut app, KeyCode::Char('u'));
+        assert_snapshot("delete_undone", &mut app);
+    }
+
+    #[test]
+    fn test_delete_favorite() {
+        let mut app = app();
+        app.select_path("github");
+        press(&mut app, KeyCode::Char('f'));
+        press(&mut app, KeyCode::Char('d'));
+        type_str(&mut app, "y");
+        press(&mut app, KeyCode::Enter);
+        assert!(app.favorites.is_empty());
+
+        // undo pins it again
+        app.undo();
+        assert_eq!(app.favorites, vec!["github".to_string()]);
+        assert_eq!(app.storage.get_favorites().unwrap(), vec!["github".to_string()]);
+    }
+
+    #[test]
+    fn test_marked_tag_and_move() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('*'));
+        press(&mut app, KeyCode::Char('t'));
+        type_str(&mut app, "prod");
+        assert_snapshot("tag", &mut app);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.mode, Mode::Confirm);
+        assert!(app.get_help().starts_with("Help for `confirm`"));
+        assert_snapshot("tag_confirm", &mut app);
+        type_str(&mut app, "y");
+        press(&mut app, KeyCode::Enter);
+        assert_snapshot("tag_done", &mut app);
+
+        press(&mut app, KeyCode::Char('r'));
+        type_str(&mut app, "all");
+        assert_snapshot("move", &mut app);
+        press(&mut app, KeyCode::Enter);
+        // anything but `y` leaves the marked secrets where they are
+        type_str(&mut app, "n");
+        press(&mut app, KeyCode::Enter);
+        assert!(app.storage.read("github").unwrap().is_some());
+
+        press(&mut app, KeyCode::Char('r'));
+        type_str(&mut app, "all");
+        press(&mut app, KeyCode::Enter);
+        type_str(&mut app, "y");
+        press(&mut app, KeyCode::Enter);
+        assert_snapshot("move_done", &mut app);
+    }
+
+    #[test]
+    fn test_command() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char(':'));
+        type_str(&mut app, "mv git");
+        press(&mut app, KeyCode::Tab);
+        type_str(&mut app, "gh");
+        assert_snapshot("command", &mut app);
+        press(&mut app, KeyCode::Enter);
+        assert_snapshot("command_done", &mut app);
+    }
+
+    #[test]
+    fn test_vault() {
+        let dir = env::temp_dir().join(format!("secm-vault-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let work = SqliteStorage::open(&dir.join("work.db")).unwrap();
+        work.write("jira/api", "token").unwrap();
+        drop(work);
+
+        let mut app = app();
+        app.vault_dir = Some(dir.clone());
+        app.select_path("github");
+        press(&mut app, KeyCode::Char(' '));
+        press(&mut app, KeyCode::Char(':'));
+        type_str(&mut app, "vault w");
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Enter);
+        assert_snapshot("vault", &mut app);
+        assert_eq!(app.storage.read("jira/api").unwrap().as_deref(), Some("token"));
+        assert!(app.marked.is_empty());
+        // nothing done in the other vault can be undone here
+        app.undo();
+        assert_eq!(app.storage.read("jira/api").unwrap().as_deref(), Some("token"));
+
+        press(&mut app, KeyCode::Char(':'));
+        type_str(&mut app, "vault ../x");
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.error.msg, "Invalid vault name '../x'");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_record() {
+        let mut app = app();
+        app.select_path("github");
+        for line in ["type login", "field user octocat"] {
+            press(&mut app, KeyCode::Char(':'));
+            type_str(&mut app, line);
+            press(&mut app, KeyCode::Enter);
+        }
+        let clipboard = FakeClipboard::default();
+        app.clipboard = Box::new(clipboard.clone());
+        press(&mut app, KeyCode::Char('y'));
+        assert_snapshot("record", &mut app);
+        assert_eq!(*clipboard.0.borrow(), vec!["octocat".to_string()]);
+        let record = app.records.get("github").unwrap();
+        assert_eq!(record.get("hunter2", "username").unwrap(), Some("octocat"));
+
+        press(&mut app, KeyCode::Char('u'));
+        assert_eq!(app.records.get("github").unwrap().fields.get("username"), None);
+    }
+
+    #[test]
+    fn test_approval() {
+        let mut app = app();
+        let id = app.storage.request_approval("ssh/github", "ssh login as git").unwrap();
+        app.scheduler = Scheduler::default();
+        app.scheduler.after(Task::CheckApprovals, Duration::ZERO);
+        app.tick();
+        assert_snapshot("approval", &mut app);
+
+        // other keys wait for the answer
+        press(&mut app, KeyCode::Char('j'));
+        press(&mut app, KeyCode::Char('y'));
+        assert_eq!(app.approval, None);
+        assert_eq!(app.storage.take_approval(id).unwrap(), Some(true));
+        assert_eq!(app.notice.msg, "Allowed ssh login as git with ssh/github");
+    }
+
+    #[test]
+    fn test_save_attachment() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut app = app();
+        app.storage.add_attachment("github", "recovery-codes.txt", &mut b"1234-5678".as_slice(), 1024).unwrap();
+        app.refresh_secret_list();
+        (0..5).for_each(|_| press(&mut app, KeyCode::Char('j')));
+        press(&mut app, KeyCode::Char('w'));
+        assert_snapshot("save_attachment", &mut app);
+
+        let dir = env::temp_dir().join(format!("secm-attachment-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("codes.txt");
+        type_str(&mut app, path.to_str().unwrap());
+        press(&mut app, KeyCode::Enter);
+        let saved = fs::read(&path);
+        let mode = fs::metadata(&path).map(|metadata| metadata.permissions().mode() & 0o777);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(saved.unwrap(), b"1234-5678");
+        assert_eq!(mode.unwrap(), 0o600);
+    }
+}
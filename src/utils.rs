@@ -2,44 +2,14 @@ use crypto::{symmetriccipher::SymmetricCipherError, aes, buffer::{RefWriteBuffer
 use crypto::aes::KeySize::KeySize256;
 use crypto::blockmodes::PkcsPadding;
 use crypto::buffer::{WriteBuffer, ReadBuffer, BufferResult};
-use rand::Rng;
-use rand::seq::SliceRandom;
-use std::{fs::File, io::Write};
+use std::{env, fs, fs::File, io::Write, process};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::Path;
 use security_framework::os::macos::keychain::SecKeychain;
 use rusqlite::{Connection, Result};
+use crate::generator::Policy;
 
 
-pub fn generate_random_string(length: usize, advance: bool) -> String {
-    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
-    const DIGIT: &[u8] = b"0123456789";
-    const SYMBOL: &[u8] = b"!@#$%^&*-_=+;:,./?";
-
-    let mut rng = rand::thread_rng();
-    if advance {
-        let password_str: String = (0..length-2)
-            .map(|_| {
-                let idx =rng.gen_range(0..CHARSET.len());
-                CHARSET[idx] as char
-            })
-            .collect();
-
-        let digit = DIGIT[rng.gen_range(0..DIGIT.len())] as char;
-        let symbol = SYMBOL[rng.gen_range(0..DIGIT.len())] as char;
-        let pre_password = format!("{}{}{}", password_str, digit, symbol);
-        let mut char_vec: Vec<char> = pre_password.chars().collect();
-        char_vec.shuffle(&mut rng);
-        char_vec.into_iter().collect()
-    } else {
-        let password: String = (0..length)
-            .map(|_| {
-                let idx =rng.gen_range(0..CHARSET.len());
-                CHARSET[idx] as char
-            })
-            .collect();
-        password
-    }
-}
-
 pub fn get_secrets(conn: &Connection) -> Result<Vec<(String, String)>> {
     let mut stmt = conn.prepare("SELECT name, value FROM secrets")?;
     let rows = stmt.query_map([], |row| {
@@ -82,7 +52,7 @@ pub fn generate_secm_key() {
     let keychain = SecKeychain::default().expect("Unable to get default keychain");
     let res = keychain.find_generic_password("secm", "secm");
     if res.is_err() {
-        let key = generate_random_string(32, false);
+        let key = Policy::letters(32).generate().expect("Unable to generate secm key");
         keychain.add_generic_password("secm", "secm", key.as_bytes()).expect("Unable to set secm key");
     }
 }
@@ -93,8 +63,63 @@ pub fn get_secm_key() -> String {
     return String::from_utf8(password.as_ref().to_vec()).unwrap()
 }
 
+/// edit `value` in `$VISUAL`/`$EDITOR` (vi by default) and return what was saved, byte for byte.
+/// the temp file lives in a directory only the user can read, and everything in it,
+/// including swap files the editor left behind, is overwritten with zeros before removal.
+pub fn edit_in_editor(value: &str) -> Result<String, String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let dir = env::temp_dir().join(format!("secm-{}", Policy::letters(12).generate()?));
+    fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&dir)
+        .map_err(|e| format!("Unable to create {}: {}", dir.display(), e))?;
+
+    let edited = edit_file(&editor, &dir.join("secret"), value);
+    wipe_dir(&dir);
+    edited
+}
+
+fn edit_file(editor: &str, path: &Path, value: &str) -> Result<String, String> {
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .and_then(|mut file| file.write_all(value.as_bytes()))
+        .map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+    // through the shell, so EDITOR="code --wait" works
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("secm")
+        .arg(path)
+        .status()
+        .map_err(|e| format!("Unable to run {}: {}", editor, e))?;
+    if !status.success() {
+        return Err(format!("{} exited with {}, secret unchanged", editor, status));
+    }
+    let bytes = fs::read(path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    String::from_utf8(bytes).map_err(|_| "Secret must be valid UTF-8".to_string())
+}
+
+fn wipe_dir(dir: &Path) {
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if let Ok(mut file) = fs::OpenOptions::new().write(true).open(&path) {
+                let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+                let _ = file.write_all(&vec![0; len as usize]);
+                let _ = file.sync_all();
+            }
+        }
+    }
+    let _ = fs::remove_dir_all(dir);
+}
+
 /// Encrypt a buffer with the given key and iv using AES256/CBC/Pkcs encryption.
-fn aes256_cbc_encrypt(data: &[u8], key: &[u8; 32], iv: &[u8; 16]) -> Result<Vec<u8>, SymmetricCipherError> {
+pub(crate) fn aes256_cbc_encrypt(data: &[u8], key: &[u8; 32], iv: &[u8; 16]) -> Result<Vec<u8>, SymmetricCipherError> {
     let mut encryptor = aes::cbc_encryptor(KeySize256, key, iv, PkcsPadding);
 
     let mut buffer = [0; 4096];
@@ -115,7 +140,7 @@ fn aes256_cbc_encrypt(data: &[u8], key: &[u8; 32], iv: &[u8; 16]) -> Result<Vec<
 }
 
 /// Decrypt a buffer with the given key and iv using AES256/CBC/Pkcs encryption.
-fn aes256_cbc_decrypt(data: &[u8], key: &[u8; 32], iv: &[u8; 16]) -> Result<Vec<u8>, SymmetricCipherError> {
+pub(crate) fn aes256_cbc_decrypt(data: &[u8], key: &[u8; 32], iv: &[u8; 16]) -> Result<Vec<u8>, SymmetricCipherError> {
     let mut decryptor = aes::cbc_decryptor(KeySize256, key, iv, PkcsPadding);
 
     let mut buffer = [0; 4096];